@@ -7,3 +7,9 @@ echo
 
 echo "zeblang"
 time ./is_127_prime
+
+echo 
+echo
+
+echo "zeblang vm"
+time ../target/release/zeblang run --vm is_127_prime.zb
@@ -3,12 +3,50 @@ use std::env;
 
 pub fn parse_args() -> HashMap<&'static str, String> {
     let mut out: HashMap<&str, String> = HashMap::new();
-    for arg in env::args().into_iter() {
+    let mut args = env::args();
+    while let Some(arg) = args.next() {
         match arg.as_str() {
+            "--emit" => out.insert("emit", args.next().unwrap_or_default()),
             "-j" | "--json" => out.insert("json", arg),
+            "repl" => out.insert("repl", arg),
+            "run" => out.insert("run", arg),
+            "fmt" => out.insert("fmt", arg),
+            "--check" => out.insert("check", arg),
+            "build" => out.insert("build", arg),
+            "-o" => out.insert("out", args.next().unwrap_or_default()),
+            "-S" => out.insert("asm_only", arg),
+            "--keep-temps" => out.insert("keep_temps", arg),
+            "--assembler" => out.insert("assembler", args.next().unwrap_or_default()),
+            "--interp" => out.insert("interp", arg),
+            "--vm" => out.insert("vm", arg),
+            "--jit" => out.insert("jit", arg),
+            "--no-tco" => out.insert("no_tco", arg),
+            level if level.starts_with("-O") => out.insert("level", level[2..].to_string()),
+            // anything after the file in zeblang run is for the program
+            filename if filename.ends_with(".zb") && out.contains_key("run") => {
+                out.insert("filename", arg);
+                break;
+            }
             filename if filename.ends_with(".zb") => out.insert("filename", arg),
             _ => continue,
         };
     }
     out
 }
+
+// the arguments after the file, passed on by zeblang run
+pub fn program_args() -> Vec<String> {
+    env::args()
+        .skip(1)
+        .skip_while(|arg| !arg.ends_with(".zb"))
+        .skip(1)
+        .collect()
+}
+
+// every file given to zeblang fmt
+pub fn files() -> Vec<String> {
+    env::args()
+        .skip(1)
+        .filter(|arg| arg.ends_with(".zb"))
+        .collect()
+}
@@ -0,0 +1,811 @@
+use crate::error::{new_error, syntax_error};
+
+use std::collections::HashMap;
+use std::io::Result;
+
+// assembles the nasm the generator writes straight to machine code. it
+// covers the x86-64 the generator uses and not much more, jumps are always
+// rel32 and labels in memory operands are always absolute 32 bit addresses
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocKind {
+    // symbol - (field + 4), for jumps
+    Pc32,
+    // symbol + addend as a sign extended 32 bit address
+    Abs32S,
+}
+
+// a field in the text waiting on the address of a symbol
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reloc {
+    pub offset: usize,
+    pub symbol: String,
+    pub addend: i64,
+    pub kind: RelocKind,
+}
+
+#[derive(Debug, Default)]
+pub struct Object {
+    pub text: Vec<u8>,
+    pub data: Vec<u8>,
+    pub bss: usize,
+    pub symbols: HashMap<String, (Section, usize)>,
+    pub globals: Vec<String>,
+    pub relocs: Vec<Reloc>,
+}
+
+// where each section ends up in memory
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub text: u64,
+    pub data: u64,
+    pub bss: u64,
+}
+
+impl Object {
+    pub fn address(&self, symbol: &str, layout: &Layout) -> Result<u64> {
+        let (section, offset) = self
+            .symbols
+            .get(symbol)
+            .ok_or_else(|| new_error(&format!("undefined symbol {}", symbol)))?;
+        Ok(*offset as u64
+            + match section {
+                Section::Text => layout.text,
+                Section::Data => layout.data,
+                Section::Bss => layout.bss,
+            })
+    }
+
+    // the text with every relocation filled in for the given layout
+    pub fn relocated_text(&self, layout: &Layout) -> Result<Vec<u8>> {
+        let mut text = self.text.clone();
+        for reloc in self.relocs.iter() {
+            let target = self.address(&reloc.symbol, layout)? as i64 + reloc.addend;
+            let value = match reloc.kind {
+                RelocKind::Pc32 => target - (layout.text + reloc.offset as u64) as i64,
+                RelocKind::Abs32S => target,
+            };
+            let value = i32::try_from(value)
+                .map_err(|_| new_error(&format!("{} is too far away", reloc.symbol)))?;
+            text[reloc.offset..reloc.offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Reg {
+    num: u8,
+    size: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Imm {
+    value: i64,
+    symbol: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Mem {
+    base: Option<u8>,
+    index: Option<(u8, u8)>,
+    disp: i64,
+    symbol: Option<String>,
+    size: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Reg(Reg),
+    Imm(Imm),
+    Mem(Mem),
+}
+
+fn register(name: &str) -> Option<Reg> {
+    const QWORDS: [&str; 16] = [
+        "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15",
+    ];
+    const DWORDS: [&str; 16] = [
+        "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d",
+        "r12d", "r13d", "r14d", "r15d",
+    ];
+    // ah to bh share their numbers with spl to dil, which are left out
+    const BYTES: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+    let find = |names: &[&str], size| {
+        names.iter().position(|reg| *reg == name).map(|num| Reg {
+            num: num as u8,
+            size,
+        })
+    };
+    find(&QWORDS, 8)
+        .or_else(|| find(&DWORDS, 4))
+        .or_else(|| find(&BYTES, 1))
+}
+
+// numbers the way nasm reads them, including 'ab' character constants which
+// are little endian
+fn number(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let digits = digits.replace('_', "");
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else if let Some(chars) = digits.strip_prefix('\'').and_then(|c| c.strip_suffix('\'')) {
+        if chars.is_empty() || chars.len() > 8 {
+            return None;
+        }
+        chars
+            .bytes()
+            .rev()
+            .fold(0i64, |value, byte| value << 8 | byte as i64)
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits
+            .parse::<i64>()
+            .ok()
+            .or_else(|| digits.parse::<u64>().ok().map(|value| value as i64))?
+    } else {
+        return None;
+    };
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+fn is_symbol(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn condition(name: &str) -> Option<u8> {
+    Some(match name {
+        "o" => 0,
+        "no" => 1,
+        "b" | "c" | "nae" => 2,
+        "ae" | "nb" | "nc" => 3,
+        "e" | "z" => 4,
+        "ne" | "nz" => 5,
+        "be" | "na" => 6,
+        "a" | "nbe" => 7,
+        "s" => 8,
+        "ns" => 9,
+        "p" | "pe" => 10,
+        "np" | "po" => 11,
+        "l" | "nge" => 12,
+        "ge" | "nl" => 13,
+        "le" | "ng" => 14,
+        "g" | "nle" => 15,
+        _ => return None,
+    })
+}
+
+fn fits_i8(value: i64) -> bool {
+    i8::try_from(value).is_ok()
+}
+
+fn fits_i32(value: i64) -> bool {
+    i32::try_from(value).is_ok()
+}
+
+#[derive(Debug)]
+pub struct Assembler {
+    object: Object,
+    section: Section,
+    line: usize,
+    hook: Option<String>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self {
+            object: Object::default(),
+            section: Section::Text,
+            line: 0,
+            hook: None,
+        }
+    }
+
+    // every syscall becomes a call to label so whoever loads the code can
+    // handle them. the handler has to keep every register but rax, rcx and
+    // r11 like the kernel does
+    pub fn with_syscall_hook(label: &str) -> Self {
+        Self {
+            hook: Some(label.to_string()),
+            ..Self::new()
+        }
+    }
+
+    fn error(&self, msg: &str) -> std::io::Error {
+        syntax_error(msg, self.line)
+    }
+
+    pub fn assemble(mut self, source: &str) -> Result<Object> {
+        for (line_num, line) in source.lines().enumerate() {
+            self.line = line_num + 1;
+            let line = line.split(';').next().unwrap_or("").trim();
+            self.assemble_line(line)?;
+        }
+        Ok(self.object)
+    }
+
+    fn assemble_line(&mut self, line: &str) -> Result<()> {
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (word, rest) = match line.split_once(char::is_whitespace) {
+            Some((word, rest)) => (word, rest.trim()),
+            None => (line, ""),
+        };
+        if let Some(label) = word.strip_suffix(':') {
+            self.define(label)?;
+            return self.assemble_line(rest);
+        }
+        match word {
+            "section" => {
+                self.section = match rest {
+                    ".text" => Section::Text,
+                    ".data" => Section::Data,
+                    ".bss" => Section::Bss,
+                    _ => return Err(self.error(&format!("unknown section {}", rest))),
+                }
+            }
+            "global" => self.object.globals.push(rest.to_string()),
+            "db" | "dq" => self.data(word, rest)?,
+            "resb" | "resq" => {
+                let count = number(rest)
+                    .filter(|count| *count >= 0)
+                    .ok_or_else(|| self.error(&format!("bad size {}", rest)))?;
+                let size = if word == "resb" { 1 } else { 8 };
+                match self.section {
+                    Section::Bss => self.object.bss += count as usize * size,
+                    _ => return Err(self.error("res only belongs in .bss")),
+                }
+            }
+            _ => {
+                if self.section != Section::Text {
+                    return Err(self.error("instructions only belong in .text"));
+                }
+                let operands = match rest.is_empty() {
+                    true => Vec::new(),
+                    false => rest
+                        .split(',')
+                        .map(|operand| self.operand(operand.trim()))
+                        .collect::<Result<Vec<Operand>>>()?,
+                };
+                self.instruction(word, operands)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, label: &str) -> Result<()> {
+        if !is_symbol(label) || register(label).is_some() {
+            return Err(self.error(&format!("bad label {}", label)));
+        }
+        let offset = match self.section {
+            Section::Text => self.object.text.len(),
+            Section::Data => self.object.data.len(),
+            Section::Bss => self.object.bss,
+        };
+        match self
+            .object
+            .symbols
+            .insert(label.to_string(), (self.section, offset))
+        {
+            Some(_) => Err(self.error(&format!("label {} defined twice", label))),
+            None => Ok(()),
+        }
+    }
+
+    fn data(&mut self, kind: &str, values: &str) -> Result<()> {
+        if self.section != Section::Data {
+            return Err(self.error("data only belongs in .data"));
+        }
+        for value in values.split(',') {
+            let value = number(value.trim())
+                .ok_or_else(|| self.error(&format!("bad value {}", value.trim())))?;
+            match kind {
+                "db" => self.object.data.push(value as u8),
+                _ => self.object.data.extend_from_slice(&value.to_le_bytes()),
+            }
+        }
+        Ok(())
+    }
+
+    fn operand(&self, text: &str) -> Result<Operand> {
+        let (size, text) = match text.split_once(char::is_whitespace) {
+            Some((keyword @ ("byte" | "word" | "dword" | "qword"), rest)) => {
+                let size = match keyword {
+                    "byte" => 1,
+                    "word" => 2,
+                    "dword" => 4,
+                    _ => 8,
+                };
+                (Some(size), rest.trim())
+            }
+            _ => (None, text),
+        };
+        if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            return self.memory(inner, size);
+        }
+        if size.is_some() {
+            return Err(self.error(&format!("size given for {}", text)));
+        }
+        if let Some(reg) = register(text) {
+            return Ok(Operand::Reg(reg));
+        }
+        if let Some(value) = number(text) {
+            return Ok(Operand::Imm(Imm {
+                value,
+                symbol: None,
+            }));
+        }
+        if is_symbol(text) {
+            return Ok(Operand::Imm(Imm {
+                value: 0,
+                symbol: Some(text.to_string()),
+            }));
+        }
+        Err(self.error(&format!("bad operand {}", text)))
+    }
+
+    // [base + index * scale + disp + symbol], any of them can be left out
+    fn memory(&self, inner: &str, size: Option<u8>) -> Result<Operand> {
+        let mut mem = Mem {
+            base: None,
+            index: None,
+            disp: 0,
+            symbol: None,
+            size,
+        };
+        let spaced = inner.replace(' ', "").replace('-', "+-");
+        for term in spaced.split('+').map(str::trim) {
+            let bad = || self.error(&format!("bad address [{}]", inner));
+            if term.is_empty() {
+                continue;
+            }
+            if let Some((reg, scale)) = term.split_once('*') {
+                let reg = register(reg.trim())
+                    .filter(|reg| reg.size == 8)
+                    .ok_or_else(bad)?;
+                let scale = match number(scale.trim()) {
+                    Some(1) => 0,
+                    Some(2) => 1,
+                    Some(4) => 2,
+                    Some(8) => 3,
+                    _ => return Err(bad()),
+                };
+                if mem.index.is_some() || reg.num == 4 {
+                    return Err(bad());
+                }
+                mem.index = Some((reg.num, scale));
+            } else if let Some(reg) = register(term) {
+                if reg.size != 8 {
+                    return Err(bad());
+                }
+                match (mem.base, mem.index) {
+                    (None, _) => mem.base = Some(reg.num),
+                    (Some(_), None) if reg.num != 4 => mem.index = Some((reg.num, 0)),
+                    _ => return Err(bad()),
+                }
+            } else if let Some(value) = number(term) {
+                mem.disp += value;
+            } else if is_symbol(term) && mem.symbol.is_none() {
+                mem.symbol = Some(term.to_string());
+            } else {
+                return Err(bad());
+            }
+        }
+        Ok(Operand::Mem(mem))
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.object.text.extend_from_slice(bytes);
+    }
+
+    fn reloc(&mut self, symbol: &str, addend: i64, kind: RelocKind) {
+        self.object.relocs.push(Reloc {
+            offset: self.object.text.len(),
+            symbol: symbol.to_string(),
+            addend,
+            kind,
+        });
+    }
+
+    fn imm32(&mut self, imm: &Imm) -> Result<()> {
+        if let Some(symbol) = &imm.symbol {
+            self.reloc(symbol, imm.value, RelocKind::Abs32S);
+        } else if !fits_i32(imm.value) {
+            return Err(self.error(&format!("{} doesnt fit in 32 bits", imm.value)));
+        }
+        self.emit(&(imm.value as i32).to_le_bytes());
+        Ok(())
+    }
+
+    // the rex prefix, opcode and modrm for an instruction with a register or
+    // memory operand in rm and either a register or an opcode extension in
+    // the reg field. spl to dil would need an empty rex and are never used
+    fn encode(&mut self, size: u8, opcode: &[u8], reg: u8, rm: &Operand) -> Result<()> {
+        let w = (size == 8) as u8;
+        let r = (reg >> 3) & 1;
+        let (x, b) = match rm {
+            Operand::Reg(rm) => (0, (rm.num >> 3) & 1),
+            Operand::Mem(mem) => (
+                mem.index.map_or(0, |(index, _)| (index >> 3) & 1),
+                mem.base.map_or(0, |base| (base >> 3) & 1),
+            ),
+            Operand::Imm(_) => return Err(self.error("expected a register or memory operand")),
+        };
+        let rex = 0x40 | w << 3 | r << 2 | x << 1 | b;
+        if rex != 0x40 {
+            self.emit(&[rex]);
+        }
+        self.emit(opcode);
+        let reg = (reg & 7) << 3;
+        match rm {
+            Operand::Reg(rm) => self.emit(&[0xC0 | reg | (rm.num & 7)]),
+            Operand::Mem(mem) => self.encode_memory(reg, mem),
+            Operand::Imm(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn encode_memory(&mut self, reg: u8, mem: &Mem) {
+        let disp = |this: &mut Self, size: u8| match (&mem.symbol, size) {
+            (Some(symbol), _) => {
+                this.reloc(symbol, mem.disp, RelocKind::Abs32S);
+                this.emit(&[0; 4]);
+            }
+            (None, 1) => this.emit(&[mem.disp as i8 as u8]),
+            (None, _) => this.emit(&(mem.disp as i32).to_le_bytes()),
+        };
+        let Some(base) = mem.base else {
+            // no base, the sib byte with base 101 and mod 00 means disp32
+            let (index, scale) = mem.index.unwrap_or((4, 0));
+            self.emit(&[reg | 0x04, scale << 6 | (index & 7) << 3 | 0x05]);
+            return disp(self, 4);
+        };
+        let size = match () {
+            _ if mem.symbol.is_some() || !fits_i8(mem.disp) => 4,
+            // rbp and r13 have no form without a displacement
+            _ if mem.disp != 0 || base & 7 == 5 => 1,
+            _ => 0,
+        };
+        let mode = match size {
+            0 => 0x00,
+            1 => 0x40,
+            _ => 0x80,
+        };
+        match mem.index {
+            Some((index, scale)) => self.emit(&[
+                mode | reg | 0x04,
+                scale << 6 | (index & 7) << 3 | (base & 7),
+            ]),
+            // rsp and r12 need a sib byte
+            None if base & 7 == 4 => self.emit(&[mode | reg | 0x04, 0x24]),
+            None => self.emit(&[mode | reg | (base & 7)]),
+        }
+        if size != 0 {
+            disp(self, size);
+        }
+    }
+
+    // the size an instruction works on, from its registers or an explicit
+    // byte/qword on the memory operand
+    fn size(&self, operands: &[&Operand]) -> Result<u8> {
+        let mut size = None;
+        for operand in operands {
+            let this = match operand {
+                Operand::Reg(reg) => Some(reg.size),
+                Operand::Mem(mem) => mem.size,
+                Operand::Imm(_) => None,
+            };
+            match (size, this) {
+                (Some(a), Some(b)) if a != b => {
+                    return Err(self.error("operand sizes dont match"));
+                }
+                (None, Some(_)) => size = this,
+                _ => (),
+            }
+        }
+        let size = size.ok_or_else(|| self.error("operation size not specified"))?;
+        match size {
+            2 => Err(self.error("16 bit operands arent supported")),
+            size => Ok(size),
+        }
+    }
+
+    // ah, ch, dh and bh cant be reached once there is a rex prefix
+    fn check_high_bytes(&self, operands: &[&Operand]) -> Result<()> {
+        let high = operands.iter().any(|op| {
+            matches!(
+                op,
+                Operand::Reg(Reg {
+                    size: 1,
+                    num: 4..=7
+                })
+            )
+        });
+        let rex = operands.iter().any(|op| match op {
+            Operand::Reg(reg) => reg.num >= 8 || reg.size == 8,
+            Operand::Mem(mem) => {
+                mem.base.is_some_and(|base| base >= 8)
+                    || mem.index.is_some_and(|(index, _)| index >= 8)
+            }
+            Operand::Imm(_) => false,
+        });
+        match high && rex {
+            true => Err(self.error("ah, bh, ch and dh cant be used with a rex prefix")),
+            false => Ok(()),
+        }
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: Vec<Operand>) -> Result<()> {
+        let refs: Vec<&Operand> = operands.iter().collect();
+        self.check_high_bytes(&refs)?;
+        match (mnemonic, refs.as_slice()) {
+            ("syscall", []) => match self.hook.clone() {
+                Some(label) => {
+                    self.emit(&[0xE8]);
+                    self.reloc(&label, -4, RelocKind::Pc32);
+                    self.emit(&[0; 4]);
+                }
+                None => self.emit(&[0x0F, 0x05]),
+            },
+            ("ret", []) => self.emit(&[0xC3]),
+            ("cqo", []) => self.emit(&[0x48, 0x99]),
+            ("call", [Operand::Imm(imm)]) => {
+                self.emit(&[0xE8]);
+                self.jump(imm)?;
+            }
+            // indirect calls and jumps are always 64 bit so there is no rex.w
+            ("call" | "jmp", [rm @ (Operand::Reg(_) | Operand::Mem(_))]) => {
+                if !matches!(rm, Operand::Reg(Reg { size: 8, .. }) | Operand::Mem(_)) {
+                    return Err(self.error("indirect targets are 64 bit"));
+                }
+                let ext = if mnemonic == "call" { 2 } else { 4 };
+                self.encode(4, &[0xFF], ext, rm)?;
+            }
+            ("push", [Operand::Reg(reg)]) if reg.size == 8 => self.push_pop(0x50, reg),
+            ("pop", [Operand::Reg(reg)]) if reg.size == 8 => self.push_pop(0x58, reg),
+            ("push", [Operand::Imm(imm)]) => match (&imm.symbol, fits_i8(imm.value)) {
+                (None, true) => self.emit(&[0x6A, imm.value as u8]),
+                _ => {
+                    self.emit(&[0x68]);
+                    self.imm32(imm)?;
+                }
+            },
+            ("mov", [dst, src]) => self.mov(dst, src)?,
+            (
+                "add" | "or" | "and" | "sub" | "xor" | "cmp",
+                [dst @ (Operand::Reg(_) | Operand::Mem(_)), src],
+            ) => {
+                let op = match mnemonic {
+                    "add" => 0,
+                    "or" => 1,
+                    "and" => 4,
+                    "sub" => 5,
+                    "xor" => 6,
+                    _ => 7,
+                };
+                self.alu(op, dst, src)?;
+            }
+            ("inc" | "dec" | "not" | "neg" | "mul" | "imul" | "div" | "idiv", [rm]) => {
+                let size = self.size(&[rm])?;
+                let (opcode, ext) = match mnemonic {
+                    "inc" => (0xFE, 0),
+                    "dec" => (0xFE, 1),
+                    "not" => (0xF6, 2),
+                    "neg" => (0xF6, 3),
+                    "mul" => (0xF6, 4),
+                    "imul" => (0xF6, 5),
+                    "div" => (0xF6, 6),
+                    _ => (0xF6, 7),
+                };
+                self.encode(size, &[opcode + (size != 1) as u8], ext, rm)?;
+            }
+            ("rol" | "ror" | "shl" | "sal" | "shr" | "sar", [rm, count]) => {
+                let size = self.size(&[rm])?;
+                let ext = match mnemonic {
+                    "rol" => 0,
+                    "ror" => 1,
+                    "shl" | "sal" => 4,
+                    "shr" => 5,
+                    _ => 7,
+                };
+                let wide = (size != 1) as u8;
+                match count {
+                    Operand::Reg(Reg { num: 1, size: 1 }) => {
+                        self.encode(size, &[0xD2 + wide], ext, rm)?
+                    }
+                    Operand::Imm(Imm {
+                        value,
+                        symbol: None,
+                    }) if (0..=255).contains(value) => {
+                        self.encode(size, &[0xC0 + wide], ext, rm)?;
+                        self.emit(&[*value as u8]);
+                    }
+                    _ => return Err(self.error("shift counts are cl or a byte")),
+                }
+            }
+            ("imul", [Operand::Reg(reg), src @ (Operand::Reg(_) | Operand::Mem(_))]) => {
+                let size = self.size(&[&Operand::Reg(*reg), src])?;
+                if size == 1 {
+                    return Err(self.error("imul doesnt take bytes"));
+                }
+                self.encode(size, &[0x0F, 0xAF], reg.num, src)?;
+            }
+            ("lea", [Operand::Reg(reg), mem @ Operand::Mem(_)]) if reg.size == 8 => {
+                self.encode(8, &[0x8D], reg.num, mem)?
+            }
+            ("movzx", [Operand::Reg(reg), src @ (Operand::Reg(_) | Operand::Mem(_))])
+                if reg.size != 1 =>
+            {
+                if self.size(&[src])? != 1 {
+                    return Err(self.error("movzx only widens bytes"));
+                }
+                self.encode(reg.size, &[0x0F, 0xB6], reg.num, src)?;
+            }
+            ("jmp", [Operand::Imm(imm)]) => {
+                self.emit(&[0xE9]);
+                self.jump(imm)?;
+            }
+            (name, [dst @ Operand::Reg(reg), src @ (Operand::Reg(_) | Operand::Mem(_))])
+                if name.starts_with("cmov") && condition(&name[4..]).is_some() =>
+            {
+                let size = self.size(&[dst, src])?;
+                if size == 1 {
+                    return Err(self.error("cmov doesnt take bytes"));
+                }
+                let cc = condition(&name[4..]).unwrap();
+                self.encode(size, &[0x0F, 0x40 + cc], reg.num, src)?;
+            }
+            (name, [rm @ (Operand::Reg(_) | Operand::Mem(_))])
+                if name.starts_with("set") && condition(&name[3..]).is_some() =>
+            {
+                if self.size(&[rm])? != 1 {
+                    return Err(self.error("set only writes a byte"));
+                }
+                let cc = condition(&name[3..]).unwrap();
+                self.encode(1, &[0x0F, 0x90 + cc], 0, rm)?;
+            }
+            (name, [Operand::Imm(imm)])
+                if name.starts_with('j') && condition(&name[1..]).is_some() =>
+            {
+                self.emit(&[0x0F, 0x80 + condition(&name[1..]).unwrap()]);
+                self.jump(imm)?;
+            }
+            _ => return Err(self.error(&format!("cant assemble {} with these operands", mnemonic))),
+        }
+        Ok(())
+    }
+
+    fn push_pop(&mut self, opcode: u8, reg: &Reg) {
+        if reg.num >= 8 {
+            self.emit(&[0x41]);
+        }
+        self.emit(&[opcode + (reg.num & 7)]);
+    }
+
+    fn jump(&mut self, imm: &Imm) -> Result<()> {
+        let symbol = imm
+            .symbol
+            .as_ref()
+            .ok_or_else(|| self.error("jumps need a label"))?;
+        self.reloc(symbol, -4, RelocKind::Pc32);
+        self.emit(&[0; 4]);
+        Ok(())
+    }
+
+    fn mov(&mut self, dst: &Operand, src: &Operand) -> Result<()> {
+        match (dst, src) {
+            (Operand::Reg(_) | Operand::Mem(_), Operand::Reg(reg)) => {
+                let size = self.size(&[dst, src])?;
+                let opcode = 0x88 + (size != 1) as u8;
+                self.encode(size, &[opcode], reg.num, dst)
+            }
+            (Operand::Reg(reg), Operand::Mem(_)) => {
+                let size = self.size(&[dst, src])?;
+                let opcode = 0x8A + (size != 1) as u8;
+                self.encode(size, &[opcode], reg.num, src)
+            }
+            (Operand::Reg(reg), Operand::Imm(imm)) => match reg.size {
+                // the short sign extended form when it fits, movabs when not
+                8 if imm.symbol.is_some() || fits_i32(imm.value) => {
+                    self.encode(8, &[0xC7], 0, dst)?;
+                    self.imm32(imm)
+                }
+                8 => {
+                    self.emit(&[0x48 | (reg.num >> 3), 0xB8 + (reg.num & 7)]);
+                    self.emit(&imm.value.to_le_bytes());
+                    Ok(())
+                }
+                4 => {
+                    if reg.num >= 8 {
+                        self.emit(&[0x41]);
+                    }
+                    self.emit(&[0xB8 + (reg.num & 7)]);
+                    self.imm32(&Imm {
+                        value: imm.value as u32 as i32 as i64,
+                        symbol: imm.symbol.clone(),
+                    })
+                }
+                _ => {
+                    self.emit(&[0xB0 + reg.num]);
+                    self.emit(&[imm.value as u8]);
+                    Ok(())
+                }
+            },
+            (Operand::Mem(_), Operand::Imm(imm)) => {
+                let size = self.size(&[dst])?;
+                match size {
+                    1 => {
+                        self.encode(1, &[0xC6], 0, dst)?;
+                        self.emit(&[imm.value as u8]);
+                        Ok(())
+                    }
+                    _ => {
+                        self.encode(size, &[0xC7], 0, dst)?;
+                        self.imm32(imm)
+                    }
+                }
+            }
+            _ => Err(self.error("cant assemble mov with these operands")),
+        }
+    }
+
+    fn alu(&mut self, op: u8, dst: &Operand, src: &Operand) -> Result<()> {
+        let base = op << 3;
+        match (dst, src) {
+            (_, Operand::Reg(reg)) => {
+                let size = self.size(&[dst, src])?;
+                self.encode(size, &[base + (size != 1) as u8], reg.num, dst)
+            }
+            (Operand::Reg(reg), Operand::Mem(_)) => {
+                let size = self.size(&[dst, src])?;
+                self.encode(size, &[base + 2 + (size != 1) as u8], reg.num, src)
+            }
+            (_, Operand::Imm(imm)) => {
+                let size = self.size(&[dst])?;
+                match (size, &imm.symbol) {
+                    (1, None) => {
+                        self.encode(1, &[0x80], op, dst)?;
+                        self.emit(&[imm.value as u8]);
+                        Ok(())
+                    }
+                    (_, None) if fits_i8(imm.value) => {
+                        self.encode(size, &[0x83], op, dst)?;
+                        self.emit(&[imm.value as u8]);
+                        Ok(())
+                    }
+                    // a 32 bit immediate keeps its low bits like nasm does
+                    (4, None) => {
+                        self.encode(4, &[0x81], op, dst)?;
+                        self.imm32(&Imm {
+                            value: imm.value as u32 as i32 as i64,
+                            symbol: None,
+                        })
+                    }
+                    _ => {
+                        self.encode(size, &[0x81], op, dst)?;
+                        self.imm32(imm)
+                    }
+                }
+            }
+            _ => Err(self.error("cant use two memory operands")),
+        }
+    }
+}
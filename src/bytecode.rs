@@ -0,0 +1,536 @@
+use crate::error::{new_error, syntax_error};
+use crate::parser::{ExpressionNode, StatementNode};
+
+use std::collections::{HashMap, HashSet};
+use std::io::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Ne,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+impl BinOp {
+    pub(crate) fn from_str(op: &str) -> Result<Self> {
+        Ok(match op {
+            "+" => Self::Add,
+            "-" => Self::Sub,
+            "*" => Self::Mul,
+            "/" => Self::Div,
+            "%" => Self::Mod,
+            "==" => Self::Eq,
+            "!=" => Self::Ne,
+            "&" => Self::And,
+            "|" => Self::Or,
+            "^" => Self::Xor,
+            "<<" => Self::Shl,
+            ">>" => Self::Shr,
+            _ => return Err(new_error(&format!("unknown operator {}", op))),
+        })
+    }
+
+    #[inline]
+    pub fn apply(self, lh: i64, rh: i64) -> Result<i64> {
+        Ok(match self {
+            Self::Add => lh.wrapping_add(rh),
+            Self::Sub => lh.wrapping_sub(rh),
+            Self::Mul => lh.wrapping_mul(rh),
+            Self::Div | Self::Mod if rh == 0 => return Err(new_error("division by zero")),
+            Self::Div => lh.wrapping_div(rh),
+            Self::Mod => lh.wrapping_rem(rh),
+            Self::Eq => (lh == rh) as i64,
+            Self::Ne => (lh != rh) as i64,
+            Self::And => lh & rh,
+            Self::Or => lh | rh,
+            Self::Xor => lh ^ rh,
+            Self::Shl => lh.wrapping_shl(rh as u32),
+            Self::Shr => lh.wrapping_shr(rh as u32),
+        })
+    }
+}
+
+// slots are indexes into the current frame's locals, jump targets and call
+// targets are indexes into the op vec. every op fits in 16 bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Push(i64),
+    Pop,
+    Load(u32),
+    Store(u32),
+    // pops the index
+    LoadIndex(u32),
+    // pops the index then the value
+    StoreIndex(u32),
+    // pops the value and applies it to the slot in place
+    Apply(u32, BinOp),
+    // pops the index then the value and applies it to the element in place
+    ApplyIndex(u32, BinOp),
+    // pops this many values into the slot as an array
+    StoreArray(u32, u32),
+    // a preallocated array of this size
+    StoreEmpty(u32, u32),
+    // pops start, stop and step into the slot as an array
+    StoreRange(u32),
+    Binary(BinOp),
+    // stops with an error at this line if the top of the stack is zero
+    CheckDivisor(u32),
+    Not,
+    Jump(u32),
+    JumpIfZero(u32),
+    // loop var, hidden stop and step slots, where to go once the range is done
+    ForRange(u32, u32, u32),
+    // loop var, hidden step slot, the ForRange to go back to
+    NextRange(u32, u32, u32),
+    // loop var, hidden array and position slots, where to go at the sentinel
+    ForArray(u32, u32, u32),
+    // target and argument count
+    Call(u32, u32),
+    // sizes the frame at the start of the program and of each function
+    Enter(u32),
+    SetReturn,
+    Ret,
+    Print,
+    Exit,
+    Open(u32),
+    Read(u32),
+    Write(u32),
+    Close,
+    Unlink(u32),
+}
+
+#[derive(Debug)]
+enum Block {
+    If(usize),
+    While(usize, usize),
+    ForRange(usize, u32, u32),
+    ForArray(usize),
+    Func(usize, usize),
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    slots: HashMap<String, u32>,
+    size: u32,
+    // the slots an array has been assigned to
+    arrays: HashSet<u32>,
+}
+
+#[derive(Debug)]
+pub struct Compiler {
+    code: Vec<Op>,
+    blocks: Vec<Block>,
+    scopes: Vec<Scope>,
+    funcs: HashMap<String, (u32, u32)>,
+    // the line in the file of each statement, and the statement being compiled
+    lines: Vec<usize>,
+    line: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            code: vec![Op::Enter(0)],
+            blocks: Vec::new(),
+            scopes: vec![Scope::default()],
+            funcs: HashMap::new(),
+            lines: Vec::new(),
+            line: 0,
+        }
+    }
+
+    // without the lines, statements are counted from 1 like the parser does
+    pub fn with_lines(lines: &[usize]) -> Self {
+        Self {
+            lines: lines.to_vec(),
+            ..Self::new()
+        }
+    }
+
+    pub fn compile(mut self, program: Vec<StatementNode>) -> Result<Vec<Op>> {
+        for (i, statement) in program.into_iter().enumerate() {
+            self.line = i + 1;
+            self.compile_statement(statement)?;
+        }
+        if !self.blocks.is_empty() {
+            return Err(new_error("block is never closed"));
+        }
+        // falling off the end exits with 0
+        self.emit(Op::Push(0));
+        self.emit(Op::Exit);
+        self.code[0] = Op::Enter(self.scope().size);
+        Ok(self.code)
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.code.len() as u32
+    }
+
+    // point the jump at pc to the next op emitted
+    fn patch(&mut self, pc: usize) {
+        let target = self.here();
+        self.code[pc] = match self.code[pc] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfZero(_) => Op::JumpIfZero(target),
+            Op::ForRange(var, hidden, _) => Op::ForRange(var, hidden, target),
+            Op::ForArray(var, hidden, _) => Op::ForArray(var, hidden, target),
+            op => op,
+        }
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn slot(&mut self, name: &str) -> Result<u32> {
+        self.scope()
+            .slots
+            .get(name)
+            .copied()
+            .ok_or(new_error(&format!(
+                "variable {} not found in this scope",
+                name
+            )))
+    }
+
+    fn array_slot(&mut self, name: &str) -> Result<u32> {
+        let slot = self.slot(name)?;
+        match self.scope().arrays.contains(&slot) {
+            true => Ok(slot),
+            false => Err(new_error(&format!("{} is not an array", name))),
+        }
+    }
+
+    // variables get a slot the first time they are assigned
+    fn slot_or_new(&mut self, name: &str) -> u32 {
+        if let Ok(slot) = self.slot(name) {
+            return slot;
+        }
+        let slot = self.hidden(1);
+        self.scope().slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    // the divisor is on top of the stack, a zero is reported at its line
+    fn check_divisor(&mut self, op: BinOp) {
+        if let BinOp::Div | BinOp::Mod = op {
+            let line = self.lines.get(self.line - 1).copied();
+            self.emit(Op::CheckDivisor(line.unwrap_or(self.line) as u32));
+        }
+    }
+
+    fn hidden(&mut self, count: u32) -> u32 {
+        let scope = self.scope();
+        scope.size += count;
+        scope.size - count
+    }
+
+    fn close_error(&self, opener: &str) -> std::io::Error {
+        syntax_error(&format!("no {} to close", opener), self.line)
+    }
+
+    fn compile_statement(&mut self, statement: StatementNode) -> Result<()> {
+        match statement {
+            StatementNode::Exit(expr) => {
+                self.compile_expr(expr)?;
+                self.emit(Op::Exit);
+            }
+            StatementNode::Assign(name, expr) => {
+                let slot = self.slot_or_new(&name);
+                self.compile_assign(slot, expr)?;
+            }
+            StatementNode::AssignIndex(name, index_expr, assign_expr) => {
+                self.compile_expr(assign_expr)?;
+                self.compile_expr(index_expr)?;
+                let slot = self.array_slot(&name)?;
+                self.emit(Op::StoreIndex(slot));
+            }
+            StatementNode::AssignOp(name, op, expr) => {
+                let op = BinOp::from_str(&op)?;
+                self.compile_expr(expr)?;
+                self.check_divisor(op);
+                let slot = self.slot(&name)?;
+                self.emit(Op::Apply(slot, op));
+            }
+            StatementNode::AssignIndexOp(name, index_expr, op, assign_expr) => {
+                let op = BinOp::from_str(&op)?;
+                self.compile_expr(assign_expr)?;
+                self.check_divisor(op);
+                self.compile_expr(index_expr)?;
+                let slot = self.array_slot(&name)?;
+                self.emit(Op::ApplyIndex(slot, op));
+            }
+            StatementNode::If(expr) => {
+                self.compile_expr(expr)?;
+                let jump = self.emit(Op::JumpIfZero(0));
+                self.blocks.push(Block::If(jump));
+            }
+            StatementNode::EndIf => match self.blocks.pop() {
+                Some(Block::If(jump)) => self.patch(jump),
+                _ => return Err(self.close_error("if")),
+            },
+            StatementNode::While(expr) => {
+                let head = self.code.len();
+                self.compile_expr(expr)?;
+                let jump = self.emit(Op::JumpIfZero(0));
+                self.blocks.push(Block::While(head, jump));
+            }
+            StatementNode::EndWhile => match self.blocks.pop() {
+                Some(Block::While(head, jump)) => {
+                    self.emit(Op::Jump(head as u32));
+                    self.patch(jump);
+                }
+                _ => return Err(self.close_error("while")),
+            },
+            StatementNode::For(varname, expr) => self.compile_for(varname, expr)?,
+            StatementNode::EndFor => match self.blocks.pop() {
+                Some(Block::ForRange(head, var, hidden)) => {
+                    self.emit(Op::NextRange(var, hidden + 1, head as u32));
+                    self.patch(head);
+                }
+                Some(Block::ForArray(head)) => {
+                    self.emit(Op::Jump(head as u32));
+                    self.patch(head);
+                }
+                _ => return Err(self.close_error("for")),
+            },
+            StatementNode::Func(name, args, _) => {
+                let skip = self.emit(Op::Jump(0));
+                let enter = self.emit(Op::Enter(0));
+                self.funcs.insert(name, (enter as u32, args.len() as u32));
+                self.scopes.push(Scope::default());
+                for arg in args.iter() {
+                    self.slot_or_new(arg);
+                }
+                self.blocks.push(Block::Func(skip, enter));
+            }
+            StatementNode::EndFunc => match self.blocks.pop() {
+                Some(Block::Func(skip, enter)) => {
+                    self.emit(Op::Ret);
+                    self.code[enter] = Op::Enter(self.scope().size);
+                    self.scopes.pop();
+                    self.patch(skip);
+                }
+                _ => return Err(self.close_error("foo")),
+            },
+            // like the generated code return only stores the value
+            StatementNode::Return(expr) => {
+                self.compile_expr(expr)?;
+                self.emit(Op::SetReturn);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_for(&mut self, varname: String, expr: ExpressionNode) -> Result<()> {
+        let var = self.slot_or_new(&varname);
+        match expr {
+            ExpressionNode::Callable(name, args) if name == "range" => {
+                let (start, stop, step) =
+                    Self::range_args(args.into_iter().map(|arg| *arg).collect())?;
+                let hidden = self.hidden(2);
+                self.compile_expr(start)?;
+                self.emit(Op::Store(var));
+                self.compile_expr(stop)?;
+                self.emit(Op::Store(hidden));
+                self.compile_expr(step)?;
+                self.emit(Op::Store(hidden + 1));
+                let head = self.emit(Op::ForRange(var, hidden, 0));
+                self.blocks.push(Block::ForRange(head, var, hidden));
+            }
+            expr => {
+                let hidden = self.hidden(2);
+                self.compile_assign(hidden, expr)?;
+                self.emit(Op::Push(0));
+                self.emit(Op::Store(hidden + 1));
+                let head = self.emit(Op::ForArray(var, hidden, 0));
+                self.blocks.push(Block::ForArray(head));
+            }
+        }
+        Ok(())
+    }
+
+    fn range_args(
+        args: Vec<ExpressionNode>,
+    ) -> Result<(ExpressionNode, ExpressionNode, ExpressionNode)> {
+        let zero = || ExpressionNode::Value("0".to_string());
+        let one = || ExpressionNode::Value("1".to_string());
+        let mut args = args.into_iter();
+        match (args.next(), args.next(), args.next(), args.next()) {
+            (Some(stop), None, None, None) => Ok((zero(), stop, one())),
+            (Some(start), Some(stop), None, None) => Ok((start, stop, one())),
+            (Some(start), Some(stop), Some(step), None) => Ok((start, stop, step)),
+            _ => Err(new_error("range expects 1 to 3 arguments")),
+        }
+    }
+
+    // arrays can only be made by assigning them to a slot. a number assigned
+    // to an array goes in its first slot
+    fn compile_assign(&mut self, slot: u32, expr: ExpressionNode) -> Result<()> {
+        match expr {
+            ExpressionNode::Array(exprs) => {
+                let len = exprs.len() as u32;
+                for expr in exprs.into_iter() {
+                    self.compile_expr(*expr)?;
+                }
+                self.emit(Op::StoreArray(slot, len));
+                self.scope().arrays.insert(slot);
+            }
+            ExpressionNode::PreAllocArray(size) => {
+                self.emit(Op::StoreEmpty(slot, size as u32));
+                self.scope().arrays.insert(slot);
+            }
+            ExpressionNode::Callable(name, args) if name == "range" => {
+                let (start, stop, step) =
+                    Self::range_args(args.into_iter().map(|arg| *arg).collect())?;
+                self.compile_expr(start)?;
+                self.compile_expr(stop)?;
+                self.compile_expr(step)?;
+                self.emit(Op::StoreRange(slot));
+                self.scope().arrays.insert(slot);
+            }
+            expr if self.scope().arrays.contains(&slot) => {
+                self.compile_expr(expr)?;
+                self.emit(Op::Push(0));
+                self.emit(Op::StoreIndex(slot));
+            }
+            expr => {
+                self.compile_expr(expr)?;
+                self.emit(Op::Store(slot));
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_expr(&mut self, expr: ExpressionNode) -> Result<()> {
+        match expr {
+            ExpressionNode::Value(value) => {
+                let value = value
+                    .parse::<i64>()
+                    .map_err(|_| new_error(&format!("invalid integer {}", value)))?;
+                self.emit(Op::Push(value));
+            }
+            ExpressionNode::Var(name) => {
+                let slot = self.slot(&name)?;
+                self.emit(Op::Load(slot));
+            }
+            ExpressionNode::Index(name, index) => {
+                self.compile_expr(*index)?;
+                let slot = self.array_slot(&name)?;
+                self.emit(Op::LoadIndex(slot));
+            }
+            ExpressionNode::Infix(lh, op, rh) => {
+                let op = BinOp::from_str(&op)?;
+                self.compile_expr(*lh)?;
+                self.compile_expr(*rh)?;
+                self.check_divisor(op);
+                self.emit(Op::Binary(op));
+            }
+            ExpressionNode::Prefix(op, expr) => {
+                self.compile_expr(*expr)?;
+                match op.as_str() {
+                    "~" => self.emit(Op::Not),
+                    _ => return Err(new_error(&format!("unknown operator {}", op))),
+                };
+            }
+            ExpressionNode::Callable(name, args) => {
+                self.compile_call(name, args.into_iter().map(|arg| *arg).collect())?
+            }
+            ExpressionNode::Array(_) | ExpressionNode::PreAllocArray(_) => {
+                return Err(new_error("arrays can only be assigned or looped over"))
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_call(&mut self, name: String, args: Vec<ExpressionNode>) -> Result<()> {
+        match name.as_str() {
+            "open" | "read" | "write" | "close" | "unlink" => return self.compile_io(&name, args),
+            "range" => return Err(new_error("arrays can only be assigned or looped over")),
+            _ => (),
+        }
+        let argc = args.len();
+        for (i, arg) in args.into_iter().enumerate() {
+            self.compile_expr(arg)?;
+            // print shows the last argument, the rest are thrown away
+            if name == "print" && i + 1 < argc {
+                self.emit(Op::Pop);
+            }
+        }
+        if name == "print" {
+            if argc == 0 {
+                return Err(new_error("print expects an argument"));
+            }
+            self.emit(Op::Print);
+            return Ok(());
+        }
+        let (target, arity) = *self
+            .funcs
+            .get(&name)
+            .ok_or(new_error(&format!("function {} undefined", name)))?;
+        if arity as usize != argc {
+            return Err(new_error(&format!(
+                "{} expects {} arguments, got {}",
+                name, arity, argc
+            )));
+        }
+        self.emit(Op::Call(target, arity));
+        Ok(())
+    }
+
+    fn compile_io(&mut self, name: &str, mut args: Vec<ExpressionNode>) -> Result<()> {
+        let arity = match name {
+            "open" | "read" | "write" => 3,
+            _ => 1,
+        };
+        if args.len() != arity {
+            return Err(new_error(&format!(
+                "{} expects {} arguments, got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        let array = match name {
+            "open" | "unlink" => Some(args.remove(0)),
+            "read" | "write" => Some(args.remove(1)),
+            _ => None,
+        };
+        let slot = match array {
+            Some(ExpressionNode::Var(varname)) => self.slot(&varname)?,
+            Some(_) => return Err(new_error(&format!("{} expects an array variable", name))),
+            None => 0,
+        };
+        for arg in args.into_iter() {
+            self.compile_expr(arg)?;
+        }
+        self.emit(match name {
+            "open" => Op::Open(slot),
+            "read" => Op::Read(slot),
+            "write" => Op::Write(slot),
+            "close" => Op::Close,
+            _ => Op::Unlink(slot),
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,664 @@
+use crate::error::new_error;
+use crate::parser::{ExpressionNode, StatementNode};
+use crate::runtime::{DIV_ZERO, IOBUF_SIZE, SENTINEL};
+
+use std::collections::HashMap;
+use std::io::Result;
+
+// helpers every translation unit carries. arithmetic goes through unsigned
+// so overflow wraps like the nasm output instead of being undefined
+const PRELUDE: &str = r#"#define _POSIX_C_SOURCE 200809L
+#include <errno.h>
+#include <fcntl.h>
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+#define ZB_SENTINEL @SENTINEL@
+#define ZB_IOBUF @IOBUF@
+
+static char zb_iobuf[ZB_IOBUF];
+
+static inline int64_t zb_add(int64_t a, int64_t b) { return (int64_t)((uint64_t)a + (uint64_t)b); }
+static inline int64_t zb_sub(int64_t a, int64_t b) { return (int64_t)((uint64_t)a - (uint64_t)b); }
+static inline int64_t zb_mul(int64_t a, int64_t b) { return (int64_t)((uint64_t)a * (uint64_t)b); }
+
+static int64_t zb_div_zero(int64_t line) {
+    fprintf(stderr, "@DIV_ZERO_BEFORE@%" PRId64 "@DIV_ZERO_AFTER@", line);
+    exit(1);
+}
+
+static inline int64_t zb_div(int64_t a, int64_t b, int64_t line) {
+    return b == 0 ? zb_div_zero(line) : b == -1 ? zb_sub(0, a) : a / b;
+}
+static inline int64_t zb_rem(int64_t a, int64_t b, int64_t line) {
+    return b == 0 ? zb_div_zero(line) : b == -1 ? 0 : a % b;
+}
+
+static inline int64_t zb_shl(int64_t a, int64_t b) { return (int64_t)((uint64_t)a << (b & 63)); }
+static inline int64_t zb_shr(int64_t a, int64_t b) { return a >> (b & 63); }
+
+static inline int64_t zb_print(int64_t value) {
+    printf("%" PRId64 "\n", value);
+    return value;
+}
+
+static inline void zb_fill(int64_t *slots, int64_t count) {
+    for (int64_t i = 0; i < count; i++) slots[i] = ZB_SENTINEL;
+}
+
+static inline int zb_running(int64_t value, int64_t stop, int64_t step) {
+    return (step > 0 && value < stop) || (step < 0 && value > stop);
+}
+
+// regrows one buffer per range() so loops dont leak
+static inline int64_t *zb_range(int64_t *slots, int64_t *len, int64_t start, int64_t stop, int64_t step) {
+    int64_t n = 0;
+    for (int64_t value = start; zb_running(value, stop, step); value = zb_add(value, step)) n++;
+    slots = realloc(slots, (size_t)(n + 1) * sizeof *slots);
+    if (slots == NULL) {
+        perror("range");
+        exit(1);
+    }
+    for (int64_t i = 0; i < n; i++) slots[i] = zb_add(start, zb_mul(i, step));
+    slots[n] = ZB_SENTINEL;
+    *len = n;
+    return slots;
+}
+
+static inline int64_t zb_clamp(int64_t count, int64_t len) {
+    uint64_t max = (uint64_t)len < ZB_IOBUF ? (uint64_t)len : ZB_IOBUF;
+    return (int64_t)((uint64_t)count > max ? max : (uint64_t)count);
+}
+
+static inline void zb_path(const int64_t *slots) {
+    int64_t i = 0;
+    while (slots[i] != ZB_SENTINEL && i < ZB_IOBUF - 1) {
+        zb_iobuf[i] = (char)slots[i];
+        i++;
+    }
+    zb_iobuf[i] = 0;
+}
+
+// failures come back as -errno like the raw syscalls
+static inline int64_t zb_result(int64_t result) { return result < 0 ? -errno : result; }
+
+static inline int64_t zb_open(const int64_t *path, int64_t flags, int64_t mode) {
+    zb_path(path);
+    return zb_result(open(zb_iobuf, (int)flags, (mode_t)mode));
+}
+
+static inline int64_t zb_read(int64_t fd, int64_t *slots, int64_t len, int64_t count) {
+    int64_t got = zb_result(read((int)fd, zb_iobuf, (size_t)zb_clamp(count, len)));
+    for (int64_t i = 0; i < got; i++) slots[i] = (unsigned char)zb_iobuf[i];
+    return got;
+}
+
+static inline int64_t zb_write(int64_t fd, const int64_t *slots, int64_t len, int64_t count) {
+    int64_t n = zb_clamp(count, len);
+    for (int64_t i = 0; i < n; i++) zb_iobuf[i] = (char)slots[i];
+    fflush(stdout);
+    return zb_result(write((int)fd, zb_iobuf, (size_t)n));
+}
+
+static inline int64_t zb_close(int64_t fd) { return zb_result(close((int)fd)); }
+
+static inline int64_t zb_unlink(const int64_t *path) {
+    zb_path(path);
+    return zb_result(unlink(zb_iobuf));
+}
+"#;
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Int(String),
+    // pointer to the first slot and the length without the sentinel
+    Array(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Var {
+    Int,
+    Array,
+}
+
+#[derive(Debug, PartialEq)]
+enum Block {
+    If,
+    While,
+    For,
+}
+
+#[derive(Debug, Default)]
+struct Function {
+    header: String,
+    decls: String,
+    body: String,
+    vars: HashMap<String, Var>,
+    blocks: Vec<Block>,
+    temps: usize,
+    // range buffers to free when the function ends
+    ranges: Vec<String>,
+    in_func: bool,
+}
+
+// translates the parse tree to c99. every expression is split into temps so
+// evaluation order matches the other backends, and zeblang functions become
+// c functions returning int64_t
+#[derive(Debug)]
+pub struct CGenerator {
+    functions: Vec<Function>,
+    done: Vec<String>,
+    funcs: HashMap<String, usize>,
+    // the line in the file of each statement, and of the one being generated
+    lines: Vec<usize>,
+    line: usize,
+}
+
+impl CGenerator {
+    pub fn new() -> Self {
+        Self {
+            functions: vec![Function {
+                header: "int main(void) {".to_string(),
+                ..Default::default()
+            }],
+            done: Vec::new(),
+            funcs: HashMap::new(),
+            lines: Vec::new(),
+            line: 0,
+        }
+    }
+
+    // without the lines, statements are counted from 1 like the parser does
+    pub fn with_lines(lines: &[usize]) -> Self {
+        Self {
+            lines: lines.to_vec(),
+            ..Self::new()
+        }
+    }
+
+    fn current(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn temp(&mut self, prefix: &str) -> String {
+        let function = self.current();
+        function.temps += 1;
+        format!("{}{}", prefix, function.temps)
+    }
+
+    fn emit(&mut self, line: &str) {
+        let function = self.current();
+        let indent = "    ".repeat(function.blocks.len() + 1);
+        function.body += &format!("{}{}\n", indent, line);
+    }
+
+    // declarations go at the top of the function since zeblang variables
+    // outlive the block they were first assigned in
+    fn declare(&mut self, decl: &str) {
+        self.current().decls += &format!("    {}\n", decl);
+    }
+
+    fn assign_temp(&mut self, value: &str) -> String {
+        let temp = self.temp("t");
+        self.emit(&format!("int64_t {} = {};", temp, value));
+        temp
+    }
+
+    fn var(&mut self, name: &str) -> Result<Var> {
+        self.current()
+            .vars
+            .get(name)
+            .cloned()
+            .ok_or_else(|| new_error(&format!("variable {} not found in this scope", name)))
+    }
+
+    // the first slot of a variable as an lvalue, a number is its own
+    fn slot(&mut self, name: &str) -> Result<String> {
+        Ok(match self.var(name)? {
+            Var::Int => format!("v_{}", name),
+            Var::Array => format!("v_{}[0]", name),
+        })
+    }
+
+    // only arrays can be indexed, a number has no slots past its own
+    fn element(&mut self, name: &str, index: &str) -> Result<String> {
+        match self.var(name)? {
+            Var::Int => Err(new_error(&format!("{} is not an array", name))),
+            Var::Array => Ok(format!("v_{}[{}]", name, index)),
+        }
+    }
+
+    fn generate_int(&mut self, expr: ExpressionNode) -> Result<String> {
+        match self.generate_expr(expr)? {
+            Operand::Int(value) => Ok(value),
+            Operand::Array(..) => Err(new_error("arrays can only be assigned or looped over")),
+        }
+    }
+
+    fn generate_expr(&mut self, expr: ExpressionNode) -> Result<Operand> {
+        let value = match expr {
+            ExpressionNode::Value(value) => value
+                .parse::<i64>()
+                .map_err(|_| new_error(&format!("invalid integer {}", value)))?
+                .to_string(),
+            ExpressionNode::Var(name) => {
+                let slot = self.slot(&name)?;
+                self.assign_temp(&slot)
+            }
+            ExpressionNode::Index(name, index) => {
+                let index = self.generate_int(*index)?;
+                let slot = self.element(&name, &index)?;
+                self.assign_temp(&slot)
+            }
+            ExpressionNode::Infix(lh, op, rh) => {
+                let lh = self.generate_int(*lh)?;
+                let rh = self.generate_int(*rh)?;
+                let value = self.infix_op(&lh, &op, &rh)?;
+                self.assign_temp(&value)
+            }
+            ExpressionNode::Prefix(op, expr) => match op.as_str() {
+                "~" => {
+                    let value = self.generate_int(*expr)?;
+                    self.assign_temp(&format!("~{}", value))
+                }
+                _ => return Err(new_error(&format!("unknown operator {}", op))),
+            },
+            ExpressionNode::Callable(name, args) => {
+                return self.generate_call(&name, args.into_iter().map(|arg| *arg).collect())
+            }
+            ExpressionNode::Array(exprs) => {
+                let len = exprs.len();
+                let slots = self.generate_storage(len);
+                for (i, expr) in exprs.into_iter().enumerate() {
+                    let value = self.generate_int(*expr)?;
+                    self.emit(&format!("{}[{}] = {};", slots, i, value));
+                }
+                self.emit(&format!("{}[{}] = ZB_SENTINEL;", slots, len));
+                return Ok(Operand::Array(slots, len.to_string()));
+            }
+            ExpressionNode::PreAllocArray(size) => {
+                let slots = self.generate_storage(size);
+                self.emit(&format!("zb_fill({}, {});", slots, size + 1));
+                return Ok(Operand::Array(slots, size.to_string()));
+            }
+        };
+        Ok(Operand::Int(value))
+    }
+
+    fn generate_storage(&mut self, len: usize) -> String {
+        let slots = self.temp("a");
+        self.declare(&format!("int64_t {}[{}];", slots, len + 1));
+        slots
+    }
+
+    fn infix_op(&self, lh: &str, op: &str, rh: &str) -> Result<String> {
+        let helper = match op {
+            "+" => "zb_add",
+            "-" => "zb_sub",
+            "*" => "zb_mul",
+            "/" => "zb_div",
+            "%" => "zb_rem",
+            "<<" => "zb_shl",
+            ">>" => "zb_shr",
+            "==" | "!=" | "&" | "|" | "^" => return Ok(format!("{} {} {}", lh, op, rh)),
+            _ => return Err(new_error(&format!("unknown operator {}", op))),
+        };
+        // a zero divisor is reported at the line of the statement
+        if let "/" | "%" = op {
+            let line = self.lines.get(self.line).copied();
+            let line = line.unwrap_or(self.line + 1);
+            return Ok(format!("{}({}, {}, {})", helper, lh, rh, line));
+        }
+        Ok(format!("{}({}, {})", helper, lh, rh))
+    }
+
+    fn generate_call(&mut self, name: &str, args: Vec<ExpressionNode>) -> Result<Operand> {
+        let value = match name {
+            "open" | "read" | "write" | "close" | "unlink" => self.generate_io(name, args)?,
+            "range" => {
+                let (start, stop, step) = self.generate_range_args(args)?;
+                let slots = self.temp("r");
+                self.declare(&format!("int64_t *{} = NULL, {}_len = 0;", slots, slots));
+                self.emit(&format!(
+                    "{} = zb_range({}, &{}_len, {}, {}, {});",
+                    slots, slots, slots, start, stop, step
+                ));
+                self.current().ranges.push(slots.clone());
+                let len = format!("{}_len", slots);
+                return Ok(Operand::Array(slots, len));
+            }
+            "print" => {
+                let mut value = None;
+                for arg in args.into_iter() {
+                    value = Some(self.generate_int(arg)?);
+                }
+                let value = value.ok_or_else(|| new_error("print expects an argument"))?;
+                self.assign_temp(&format!("zb_print({})", value))
+            }
+            name => {
+                let arity = *self
+                    .funcs
+                    .get(name)
+                    .ok_or_else(|| new_error(&format!("function {} undefined", name)))?;
+                if arity != args.len() {
+                    return Err(new_error(&format!(
+                        "{} expects {} arguments, got {}",
+                        name,
+                        arity,
+                        args.len()
+                    )));
+                }
+                let values = args
+                    .into_iter()
+                    .map(|arg| self.generate_int(arg))
+                    .collect::<Result<Vec<String>>>()?;
+                self.assign_temp(&format!("zbf_{}({})", name, values.join(", ")))
+            }
+        };
+        Ok(Operand::Int(value))
+    }
+
+    fn generate_range_args(
+        &mut self,
+        args: Vec<ExpressionNode>,
+    ) -> Result<(String, String, String)> {
+        let args = args
+            .into_iter()
+            .map(|arg| self.generate_int(arg))
+            .collect::<Result<Vec<String>>>()?;
+        let one = || "1".to_string();
+        match &args[..] {
+            [stop] => Ok(("0".to_string(), stop.to_string(), one())),
+            [start, stop] => Ok((start.to_string(), stop.to_string(), one())),
+            [start, stop, step] => Ok((start.to_string(), stop.to_string(), step.to_string())),
+            _ => Err(new_error("range expects 1 to 3 arguments")),
+        }
+    }
+
+    fn generate_io(&mut self, name: &str, mut args: Vec<ExpressionNode>) -> Result<String> {
+        let arity = match name {
+            "open" | "read" | "write" => 3,
+            _ => 1,
+        };
+        if args.len() != arity {
+            return Err(new_error(&format!(
+                "{} expects {} arguments, got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        let call = match name {
+            "open" => {
+                let path = self.array_arg(name, &args[0])?;
+                let flags = self.generate_int(args.remove(1))?;
+                let mode = self.generate_int(args.remove(1))?;
+                format!("zb_open({}, {}, {})", path, flags, mode)
+            }
+            "read" | "write" => {
+                let slots = self.array_arg(name, &args[1])?;
+                let fd = self.generate_int(args.remove(0))?;
+                let count = self.generate_int(args.remove(1))?;
+                format!("zb_{}({}, {}, {}_len, {})", name, fd, slots, slots, count)
+            }
+            "close" => format!("zb_close({})", self.generate_int(args.remove(0))?),
+            "unlink" => format!("zb_unlink({})", self.array_arg(name, &args[0])?),
+            _ => unreachable!(),
+        };
+        Ok(self.assign_temp(&call))
+    }
+
+    fn array_arg(&mut self, name: &str, expr: &ExpressionNode) -> Result<String> {
+        let varname = match expr {
+            ExpressionNode::Var(varname) => varname,
+            _ => return Err(new_error(&format!("{} expects an array variable", name))),
+        };
+        match self.var(varname)? {
+            Var::Array => Ok(format!("v_{}", varname)),
+            Var::Int => Err(new_error(&format!(
+                "array {} not found in this scope",
+                varname
+            ))),
+        }
+    }
+
+    fn generate_assign(&mut self, name: String, node: ExpressionNode) -> Result<()> {
+        let operand = self.generate_expr(node)?;
+        self.assign(name, operand)
+    }
+
+    fn assign(&mut self, name: String, operand: Operand) -> Result<()> {
+        match (self.current().vars.get(&name).cloned(), &operand) {
+            (None, Operand::Int(_)) => {
+                self.declare(&format!("int64_t v_{} = 0;", name));
+                self.declare(&format!("(void)v_{};", name));
+                self.current().vars.insert(name.clone(), Var::Int);
+            }
+            (None, Operand::Array(..)) => {
+                self.declare(&format!("int64_t *v_{} = NULL, v_{}_len = 0;", name, name));
+                self.declare(&format!("(void)v_{}_len;", name));
+                self.current().vars.insert(name.clone(), Var::Array);
+            }
+            (Some(Var::Int), Operand::Array(..)) => {
+                return Err(new_error(&format!(
+                    "{} holds a number and cant be reassigned to an array",
+                    name
+                )))
+            }
+            _ => (),
+        }
+        match operand {
+            Operand::Int(value) => {
+                let slot = self.slot(&name)?;
+                self.emit(&format!("{} = {};", slot, value));
+            }
+            Operand::Array(slots, len) => {
+                self.emit(&format!("v_{} = {};", name, slots));
+                self.emit(&format!("v_{}_len = {};", name, len));
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_assign_index(
+        &mut self,
+        name: String,
+        index_expr: ExpressionNode,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        let value = self.generate_int(assign_expr)?;
+        let index = self.generate_int(index_expr)?;
+        let slot = self.element(&name, &index)?;
+        self.emit(&format!("{} = {};", slot, value));
+        Ok(())
+    }
+
+    fn generate_assign_op(
+        &mut self,
+        name: String,
+        index_expr: Option<ExpressionNode>,
+        op: String,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        let value = self.generate_int(assign_expr)?;
+        let slot = match index_expr {
+            Some(expr) => {
+                let index = self.generate_int(expr)?;
+                self.element(&name, &index)?
+            }
+            None => self.slot(&name)?,
+        };
+        let updated = self.infix_op(&slot, &op, &value)?;
+        self.emit(&format!("{} = {};", slot, updated));
+        Ok(())
+    }
+
+    fn generate_exit(&mut self, node: ExpressionNode) -> Result<()> {
+        let value = self.generate_int(node)?;
+        self.emit(&format!("exit((int){});", value));
+        Ok(())
+    }
+
+    fn generate_if(&mut self, node: ExpressionNode) -> Result<()> {
+        let value = self.generate_int(node)?;
+        self.emit(&format!("if ({}) {{", value));
+        self.current().blocks.push(Block::If);
+        Ok(())
+    }
+
+    // the condition has to be worked out again on every pass
+    fn generate_while(&mut self, node: ExpressionNode) -> Result<()> {
+        self.emit("for (;;) {");
+        self.current().blocks.push(Block::While);
+        let value = self.generate_int(node)?;
+        self.emit(&format!("if (!{}) break;", value));
+        Ok(())
+    }
+
+    fn generate_for(&mut self, varname: String, node: ExpressionNode) -> Result<()> {
+        match node {
+            ExpressionNode::Callable(name, args) if name == "range" => {
+                let args = args.into_iter().map(|arg| *arg).collect();
+                let (start, stop, step) = self.generate_range_args(args)?;
+                let (stop_var, step_var) = (self.temp("s"), self.temp("k"));
+                self.declare(&format!("int64_t {} = 0, {} = 0;", stop_var, step_var));
+                self.emit(&format!("{} = {};", stop_var, stop));
+                self.emit(&format!("{} = {};", step_var, step));
+                self.assign(varname.clone(), Operand::Int(start))?;
+                let var = self.slot(&varname)?;
+                self.emit(&format!(
+                    "for (;; {} = zb_add({}, {})) {{",
+                    var, var, step_var
+                ));
+                self.current().blocks.push(Block::For);
+                self.emit(&format!(
+                    "if (!zb_running({}, {}, {})) break;",
+                    var, stop_var, step_var
+                ));
+            }
+            node => {
+                let slots = match self.generate_expr(node)? {
+                    Operand::Array(slots, _) => slots,
+                    Operand::Int(value) => {
+                        let slots = self.generate_storage(1);
+                        self.emit(&format!("{}[0] = {};", slots, value));
+                        self.emit(&format!("{}[1] = ZB_SENTINEL;", slots));
+                        slots
+                    }
+                };
+                let position = self.temp("p");
+                self.declare(&format!("int64_t *{} = NULL;", position));
+                self.emit(&format!("{} = {};", position, slots));
+                self.assign(varname.clone(), Operand::Int("0".to_string()))?;
+                let var = self.slot(&varname)?;
+                self.emit("for (;;) {");
+                self.current().blocks.push(Block::For);
+                self.emit(&format!("{} = *{}++;", var, position));
+                self.emit(&format!("if ({} == ZB_SENTINEL) break;", var));
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_end(&mut self, block: Block, closer: &str) -> Result<()> {
+        match self.current().blocks.pop() {
+            Some(open) if open == block => (),
+            _ => return Err(new_error(&format!("no {} to close", closer))),
+        }
+        self.emit("}");
+        Ok(())
+    }
+
+    fn generate_func(&mut self, name: String, args: Vec<String>) -> Result<()> {
+        if self.functions.len() > 1 {
+            return Err(new_error("functions cant be defined inside functions"));
+        }
+        let params: Vec<String> = args
+            .iter()
+            .map(|arg| format!("int64_t v_{}", arg))
+            .collect();
+        let params = match params.is_empty() {
+            true => "void".to_string(),
+            false => params.join(", "),
+        };
+        self.funcs.insert(name.clone(), args.len());
+        self.functions.push(Function {
+            header: format!("static int64_t zbf_{}({}) {{", name, params),
+            vars: args.into_iter().map(|arg| (arg, Var::Int)).collect(),
+            in_func: true,
+            ..Default::default()
+        });
+        self.declare("int64_t zb_returned = 0;");
+        Ok(())
+    }
+
+    fn generate_end_func(&mut self) -> Result<()> {
+        if !self.current().in_func || !self.current().blocks.is_empty() {
+            return Err(new_error("no foo to close"));
+        }
+        for range in self.current().ranges.clone() {
+            self.emit(&format!("free({});", range));
+        }
+        self.emit("return zb_returned;");
+        let function = self.functions.pop().unwrap();
+        self.done.push(Self::finish(function));
+        Ok(())
+    }
+
+    fn generate_return(&mut self, node: ExpressionNode) -> Result<()> {
+        let value = self.generate_int(node)?;
+        if self.current().in_func {
+            self.emit(&format!("zb_returned = {};", value));
+        }
+        Ok(())
+    }
+
+    fn finish(function: Function) -> String {
+        format!(
+            "{}\n{}{}}}\n",
+            function.header, function.decls, function.body
+        )
+    }
+
+    pub fn generate(&mut self, program: Vec<StatementNode>) -> Result<String> {
+        for (i, line) in program.into_iter().enumerate() {
+            self.line = i;
+            match line {
+                StatementNode::Exit(expr_node) => self.generate_exit(expr_node)?,
+                StatementNode::Assign(name, expr_node) => self.generate_assign(name, expr_node)?,
+                StatementNode::For(var, expr_node) => self.generate_for(var, expr_node)?,
+                StatementNode::EndFor => self.generate_end(Block::For, "for")?,
+                StatementNode::While(expr_node) => self.generate_while(expr_node)?,
+                StatementNode::EndWhile => self.generate_end(Block::While, "while")?,
+                StatementNode::If(expr_node) => self.generate_if(expr_node)?,
+                StatementNode::EndIf => self.generate_end(Block::If, "if")?,
+                StatementNode::AssignIndex(name, index_expr, assign_expr) => {
+                    self.generate_assign_index(name, index_expr, assign_expr)?
+                }
+                StatementNode::AssignOp(name, op, expr_node) => {
+                    self.generate_assign_op(name, None, op, expr_node)?
+                }
+                StatementNode::AssignIndexOp(name, index_expr, op, assign_expr) => {
+                    self.generate_assign_op(name, Some(index_expr), op, assign_expr)?
+                }
+                StatementNode::EndFunc => self.generate_end_func()?,
+                StatementNode::Func(name, args, _) => self.generate_func(name, args)?,
+                StatementNode::Return(expr) => self.generate_return(expr)?,
+            };
+        }
+        if self.functions.len() > 1 || !self.current().blocks.is_empty() {
+            return Err(new_error("block is never closed"));
+        }
+        self.emit("return 0;");
+        let main = Self::finish(self.functions.pop().unwrap());
+        let prelude = PRELUDE
+            .replace("@SENTINEL@", &SENTINEL.to_string())
+            .replace("@IOBUF@", &IOBUF_SIZE.to_string())
+            .replace("@DIV_ZERO_BEFORE@", DIV_ZERO.0)
+            .replace("@DIV_ZERO_AFTER@", &DIV_ZERO.1.replace('\n', "\\n"));
+        Ok(format!("{}\n{}\n{}", prelude, self.done.join("\n"), main))
+    }
+}
@@ -0,0 +1,99 @@
+use crate::bytecode::BinOp;
+use crate::ir::{Function, Inst, Program, Temp};
+use crate::regalloc::{liveness, temps};
+
+use std::collections::HashSet;
+
+// whether dropping the instruction changes nothing but its dest. calls,
+// builtins and stores have effects, and division can fault
+fn pure(inst: &Inst) -> bool {
+    match inst {
+        Inst::Binary(_, BinOp::Div | BinOp::Mod, ..) => false,
+        Inst::Copy(..) | Inst::Binary(..) | Inst::Not(..) | Inst::Alloc(..) | Inst::Load(..) => {
+            true
+        }
+        Inst::Store(..) | Inst::Call(..) | Inst::Builtin(..) => false,
+    }
+}
+
+// the temps that something with an effect depends on, starting from the
+// terminators and the instructions that can't be dropped. a variable that
+// only feeds itself, like a counter nobody reads, never gets marked
+fn needed(function: &Function) -> HashSet<Temp> {
+    let mut needed: HashSet<Temp> = HashSet::new();
+    for block in function.blocks.iter() {
+        needed.extend(temps(block.term.operands()));
+        for inst in block.insts.iter().filter(|inst| !pure(inst)) {
+            needed.extend(temps(inst.operands()));
+        }
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for inst in function.blocks.iter().flat_map(|block| block.insts.iter()) {
+            if inst.dest().is_some_and(|dest| needed.contains(&dest)) {
+                for temp in temps(inst.operands()) {
+                    changed |= needed.insert(temp);
+                }
+            }
+        }
+    }
+    needed
+}
+
+// drops pure instructions whose result is never read. anything nothing
+// needs goes first, then each block is walked backwards for values that are
+// overwritten before they're read. removing one can leave the instructions
+// feeding it dead, so it goes round until nothing changes
+pub fn remove_dead_stores(function: &mut Function) {
+    let needed = needed(function);
+    for block in function.blocks.iter_mut() {
+        block
+            .insts
+            .retain(|inst| !pure(inst) || inst.dest().is_some_and(|dest| needed.contains(&dest)));
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let live = liveness(function);
+        for (block, (_, live_out)) in function.blocks.iter_mut().zip(live) {
+            let mut live = live_out;
+            live.extend(temps(block.term.operands()));
+            let mut keep = vec![true; block.insts.len()];
+            for (i, inst) in block.insts.iter().enumerate().rev() {
+                let dest = inst.dest();
+                if pure(inst) && dest.is_some_and(|dest| !live.contains(&dest)) {
+                    keep[i] = false;
+                    changed = true;
+                    continue;
+                }
+                if let Some(dest) = dest {
+                    live.remove(&dest);
+                }
+                live.extend(temps(inst.operands()));
+            }
+            let mut keep = keep.into_iter();
+            block.insts.retain(|_| keep.next().unwrap());
+        }
+    }
+}
+
+// drops every function that main can't reach through calls
+pub fn remove_unused_functions(program: &mut Program) {
+    let mut reached = HashSet::new();
+    let mut pending = vec![&program.main];
+    while let Some(function) = pending.pop() {
+        for inst in function.blocks.iter().flat_map(|block| block.insts.iter()) {
+            let Inst::Call(_, name, _) = inst else {
+                continue;
+            };
+            if reached.insert(name.clone()) {
+                pending.extend(program.functions.iter().filter(|f| f.name == *name));
+            }
+        }
+    }
+    program
+        .functions
+        .retain(|function| reached.contains(&function.name));
+}
@@ -0,0 +1,115 @@
+use crate::assembler::Assembler;
+use crate::elf;
+use crate::error::new_error;
+
+use std::env;
+use std::fs::{create_dir_all, remove_dir_all, remove_file, set_permissions, write, Permissions};
+use std::io::{ErrorKind, Result};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// keeps the build directories apart when one process runs several programs
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+// runs one of the external tools, saying which one is missing rather than
+// just failing with "No such file or directory"
+pub fn tool(name: &str, args: &[&str]) -> Result<()> {
+    let status = match Command::new(name).args(args).status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(new_error(&format!(
+                "{} was not found, install it or use --assembler builtin to build without it",
+                name
+            )))
+        }
+        Err(e) => return Err(e),
+    };
+    match status.success() {
+        true => Ok(()),
+        false => Err(new_error(&format!("{} {} failed", name, args.join(" ")))),
+    }
+}
+
+fn installed(name: &str) -> bool {
+    !matches!(Command::new(name).arg("-v").output(), Err(e) if e.kind() == ErrorKind::NotFound)
+}
+
+// whether to build with nasm and ld, or the built in assembler and linker.
+// without a choice it is nasm and ld when both are installed, and otherwise
+// the built in ones with a note saying so
+fn external(assembler: Option<&str>) -> Result<bool> {
+    match assembler {
+        Some("nasm") => Ok(true),
+        Some("builtin") => Ok(false),
+        Some(name) => Err(new_error(&format!(
+            "no assembler {}, use nasm or builtin",
+            name
+        ))),
+        None => match ["nasm", "ld"].into_iter().find(|name| !installed(name)) {
+            Some(name) => {
+                eprintln!(
+                    "{} was not found, building with the built in assembler and linker",
+                    name
+                );
+                Ok(false)
+            }
+            None => Ok(true),
+        },
+    }
+}
+
+// writes the assembly to out.asm, then nasm and ld turn it into the
+// executable out. the .asm and .o are removed afterwards unless keep_temps.
+// the built in assembler and linker can do the same job instead
+pub fn build(assembly: String, out: &str, keep_temps: bool, assembler: Option<&str>) -> Result<()> {
+    let (asm, object) = (format!("{}.asm", out), format!("{}.o", out));
+    if !external(assembler)? {
+        let assembled = Assembler::new().assemble(&assembly)?;
+        if keep_temps {
+            write(&asm, &assembly)?;
+            write(&object, elf::object(&assembled)?)?;
+        }
+        write(out, elf::link(&assembled)?)?;
+        return set_permissions(out, Permissions::from_mode(0o755));
+    }
+    write(&asm, assembly)?;
+    let built = tool("nasm", &["-felf64", &asm, "-o", &object])
+        .and_then(|_| tool("ld", &[&object, "-o", out]));
+    if !keep_temps {
+        let _ = remove_file(&asm);
+        let _ = remove_file(&object);
+    }
+    built
+}
+
+// builds the program in a temporary directory and runs it with args, giving
+// back its exit code. a program killed by a signal gets 128 + the signal like
+// in a shell
+pub fn run(
+    assembly: String,
+    name: &str,
+    args: &[String],
+    keep_temps: bool,
+    assembler: Option<&str>,
+) -> Result<i32> {
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    let dir = env::temp_dir().join(format!("zeblang-{}-{}", process::id(), run));
+    create_dir_all(&dir)?;
+    let name = Path::new(name).file_name().unwrap_or_default();
+    let out = dir.join(name).to_string_lossy().to_string();
+    let status = build(assembly, &out, keep_temps, assembler)
+        .and_then(|_| Command::new(&out).args(args).status());
+    match keep_temps {
+        true => eprintln!("kept the build in {}", dir.display()),
+        false => {
+            let _ = remove_dir_all(&dir);
+        }
+    }
+    let status = status?;
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
@@ -0,0 +1,314 @@
+use crate::assembler::{Layout, Object, RelocKind, Section};
+use crate::error::new_error;
+
+use std::io::Result;
+
+// where the executable is loaded, the same place ld puts a static binary
+const BASE: u64 = 0x400000;
+const PAGE: u64 = 0x1000;
+const EHDR_SIZE: u64 = 64;
+const PHDR_SIZE: u64 = 56;
+const PHDRS: u64 = 3;
+
+const PT_LOAD: u32 = 1;
+const PT_GNU_STACK: u32 = 0x6474E551;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const SHF_INFO_LINK: u64 = 0x40;
+const R_X86_64_PC32: u64 = 2;
+const R_X86_64_32S: u64 = 11;
+
+fn align(value: u64, to: u64) -> u64 {
+    value.div_ceil(to) * to
+}
+
+fn phdr(out: &mut Vec<u8>, kind: u32, flags: u32, offset: u64, vaddr: u64, sizes: (u64, u64)) {
+    out.extend_from_slice(&kind.to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    for field in [offset, vaddr, vaddr, sizes.0, sizes.1, PAGE] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+// links an assembled object into a static x86-64 linux executable. the
+// headers and text share a read and execute segment and the data and bss a
+// read and write one. execution starts at _start like ld
+pub fn link(object: &Object) -> Result<Vec<u8>> {
+    let text_offset = EHDR_SIZE + PHDR_SIZE * PHDRS;
+    let text_end = text_offset + object.text.len() as u64;
+    // the data follows the text in the file but starts a new page in memory
+    let data_offset = text_end;
+    let data_vaddr = align(BASE + text_end, PAGE) + data_offset % PAGE;
+    let bss_vaddr = align(data_vaddr + object.data.len() as u64, 16);
+    let data_memsz = bss_vaddr + object.bss as u64 - data_vaddr;
+
+    let layout = Layout {
+        text: BASE + text_offset,
+        data: data_vaddr,
+        bss: bss_vaddr,
+    };
+    let text = object.relocated_text(&layout)?;
+    let entry = object.address("_start", &layout)?;
+
+    let mut out = vec![0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    // executable, x86-64, version 1
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&0x3Eu16.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes());
+    out.extend_from_slice(&entry.to_le_bytes());
+    out.extend_from_slice(&EHDR_SIZE.to_le_bytes());
+    // no section headers
+    out.extend_from_slice(&0u64.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    for half in [EHDR_SIZE, PHDR_SIZE, PHDRS, 64, 0, 0] {
+        out.extend_from_slice(&(half as u16).to_le_bytes());
+    }
+    phdr(
+        &mut out,
+        PT_LOAD,
+        PF_R | PF_X,
+        0,
+        BASE,
+        (text_end, text_end),
+    );
+    let data_sizes = (object.data.len() as u64, data_memsz);
+    phdr(
+        &mut out,
+        PT_LOAD,
+        PF_R | PF_W,
+        data_offset,
+        data_vaddr,
+        data_sizes,
+    );
+    phdr(&mut out, PT_GNU_STACK, PF_R | PF_W, 0, 0, (0, 0));
+    out.extend(text);
+    out.extend_from_slice(&object.data);
+    Ok(out)
+}
+
+// a name added to a string table, giving back where it starts
+fn string(table: &mut Vec<u8>, name: &str) -> u32 {
+    let offset = table.len() as u32;
+    table.extend_from_slice(name.as_bytes());
+    table.push(0);
+    offset
+}
+
+// a section header. the address is left 0 since nothing is placed yet
+#[derive(Default)]
+struct Shdr {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl Shdr {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.name.to_le_bytes());
+        out.extend_from_slice(&self.kind.to_le_bytes());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.extend_from_slice(&0u64.to_le_bytes());
+        for field in [self.offset, self.size] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        out.extend_from_slice(&self.link.to_le_bytes());
+        out.extend_from_slice(&self.info.to_le_bytes());
+        for field in [self.align, self.entsize] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+}
+
+fn symbol(out: &mut Vec<u8>, name: u32, global: bool, shndx: u16, value: u64) {
+    out.extend_from_slice(&name.to_le_bytes());
+    out.push(if global { 1 << 4 } else { 0 });
+    out.push(0);
+    out.extend_from_slice(&shndx.to_le_bytes());
+    out.extend_from_slice(&value.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes());
+}
+
+// an assembled object as an x86-64 relocatable elf, the same kind of .o
+// nasm -felf64 writes, for ld or anything else to link. every label is a
+// symbol, with the globals after the rest as elf wants
+pub fn object(object: &Object) -> Result<Vec<u8>> {
+    let global = |name: &String| object.globals.contains(name);
+    let mut names: Vec<&String> = object.symbols.keys().collect();
+    names.sort_by_key(|name| (global(name), object.symbols[*name].1, *name));
+
+    // the first symbol is always the empty one
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; 24];
+    for name in names.iter() {
+        let (section, offset) = object.symbols[*name];
+        let shndx = match section {
+            Section::Text => 1,
+            Section::Data => 2,
+            Section::Bss => 3,
+        };
+        let at = string(&mut strtab, name);
+        symbol(&mut symtab, at, global(name), shndx, offset as u64);
+    }
+    let locals = 1 + names.iter().filter(|name| !global(name)).count() as u32;
+
+    let mut rela = Vec::new();
+    for reloc in object.relocs.iter() {
+        let symbol = 1 + names
+            .iter()
+            .position(|name| **name == reloc.symbol)
+            .ok_or_else(|| new_error(&format!("undefined symbol {}", reloc.symbol)))?;
+        let kind = match reloc.kind {
+            RelocKind::Pc32 => R_X86_64_PC32,
+            RelocKind::Abs32S => R_X86_64_32S,
+        };
+        rela.extend_from_slice(&(reloc.offset as u64).to_le_bytes());
+        rela.extend_from_slice(&((symbol as u64) << 32 | kind).to_le_bytes());
+        rela.extend_from_slice(&reloc.addend.to_le_bytes());
+    }
+
+    // the sections one after another after the elf header, each aligned
+    let mut body: Vec<u8> = Vec::new();
+    let mut place = |bytes: &[u8], to: u64| {
+        body.resize(align(body.len() as u64, to) as usize, 0);
+        let offset = EHDR_SIZE + body.len() as u64;
+        body.extend_from_slice(bytes);
+        (offset, bytes.len() as u64)
+    };
+    let mut shstrtab = vec![0];
+    let mut named = |name: &str, shdr: Shdr| Shdr {
+        name: string(&mut shstrtab, name),
+        ..shdr
+    };
+    let (offset, size) = place(&object.text, 16);
+    let text = named(
+        ".text",
+        Shdr {
+            kind: SHT_PROGBITS,
+            flags: SHF_ALLOC | SHF_EXECINSTR,
+            offset,
+            size,
+            align: 16,
+            ..Shdr::default()
+        },
+    );
+    let (offset, size) = place(&object.data, 8);
+    let data = named(
+        ".data",
+        Shdr {
+            kind: SHT_PROGBITS,
+            flags: SHF_ALLOC | SHF_WRITE,
+            offset,
+            size,
+            align: 8,
+            ..Shdr::default()
+        },
+    );
+    let bss = named(
+        ".bss",
+        Shdr {
+            kind: SHT_NOBITS,
+            flags: SHF_ALLOC | SHF_WRITE,
+            offset,
+            size: object.bss as u64,
+            align: 16,
+            ..Shdr::default()
+        },
+    );
+    // relocations for section 1 against the symbols in section 5
+    let (offset, size) = place(&rela, 8);
+    let rela = named(
+        ".rela.text",
+        Shdr {
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset,
+            size,
+            link: 5,
+            info: 1,
+            align: 8,
+            entsize: 24,
+            ..Shdr::default()
+        },
+    );
+    // names in section 6
+    let (offset, size) = place(&symtab, 8);
+    let symtab = named(
+        ".symtab",
+        Shdr {
+            kind: SHT_SYMTAB,
+            offset,
+            size,
+            link: 6,
+            info: locals,
+            align: 8,
+            entsize: 24,
+            ..Shdr::default()
+        },
+    );
+    let (offset, size) = place(&strtab, 1);
+    let strtab = named(
+        ".strtab",
+        Shdr {
+            kind: SHT_STRTAB,
+            offset,
+            size,
+            align: 1,
+            ..Shdr::default()
+        },
+    );
+    let shstrtab_name = named(".shstrtab", Shdr::default()).name;
+    let (offset, size) = place(&shstrtab, 1);
+    let shstrtab = Shdr {
+        name: shstrtab_name,
+        kind: SHT_STRTAB,
+        offset,
+        size,
+        align: 1,
+        ..Shdr::default()
+    };
+    let (shoff, _) = place(&[], 8);
+    for shdr in [
+        Shdr::default(),
+        text,
+        data,
+        bss,
+        rela,
+        symtab,
+        strtab,
+        shstrtab,
+    ] {
+        shdr.write(&mut body);
+    }
+
+    let mut out = vec![0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    // relocatable, x86-64, version 1, no entry or program headers
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&0x3Eu16.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes());
+    out.extend_from_slice(&shoff.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    // 8 section headers of 64 bytes, the names in the last
+    for half in [EHDR_SIZE, 0, 0, 64, 8, 7] {
+        out.extend_from_slice(&(half as u16).to_le_bytes());
+    }
+    out.extend(body);
+    Ok(out)
+}
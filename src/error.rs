@@ -10,3 +10,11 @@ pub fn syntax_error(msg: &str, line: usize) -> Error {
         &*format!("{}: {}", line, msg).as_str(),
     )
 }
+
+// an error while the program runs, worded like the compiled code's
+pub fn runtime_error(msg: &str, line: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("runtime error: line {}: {}", line, msg),
+    )
+}
@@ -0,0 +1,194 @@
+use crate::error::{new_error, syntax_error};
+use crate::parser::{parse, precedence, ExpressionNode, Inline, StatementNode};
+use crate::tokenizer::{Lexer, TokenKind};
+
+use std::collections::VecDeque;
+use std::fs::{read_to_string, write};
+use std::io::Result;
+
+const INDENT: &str = "  ";
+
+// writes one line's statement back out. literals come out as they were
+// written, since the lexer turns hex, chars and end into plain numbers
+struct Printer {
+    literals: VecDeque<String>,
+    increment: bool,
+}
+
+impl Printer {
+    fn new(tokens: &[(TokenKind, String)]) -> Self {
+        Self {
+            literals: tokens
+                .iter()
+                .filter(|(token, _)| matches!(token, TokenKind::Int(_)))
+                .map(|(_, text)| text.to_string())
+                .collect(),
+            increment: tokens
+                .iter()
+                .any(|(token, _)| matches!(token, TokenKind::Increment(_))),
+        }
+    }
+
+    // values are printed in the order they were parsed, so the next
+    // literal is this one's
+    fn value(&mut self, value: &str) -> String {
+        let literal = self.literals.pop_front();
+        match literal {
+            Some(literal)
+                if Lexer::lex(literal.clone()).ok()
+                    == Some(vec![TokenKind::Int(value.to_string())]) =>
+            {
+                literal
+            }
+            _ => value.to_string(),
+        }
+    }
+
+    // an operand of an infix op goes in brackets when it would otherwise
+    // bind differently. ops of the same precedence group to the right, so
+    // only the left one needs them then
+    fn operand(&mut self, expr: &ExpressionNode, op: &str, left: bool) -> String {
+        let text = self.expression(expr);
+        match expr {
+            ExpressionNode::Infix(_, inner, _) => {
+                let (inner, outer) = (precedence(inner), precedence(op));
+                match inner < outer || (left && inner == outer) {
+                    true => format!("({})", text),
+                    false => text,
+                }
+            }
+            _ => text,
+        }
+    }
+
+    fn list(&mut self, exprs: &[Box<ExpressionNode>]) -> String {
+        let exprs: Vec<String> = exprs.iter().map(|expr| self.expression(expr)).collect();
+        exprs.join(", ")
+    }
+
+    fn expression(&mut self, expr: &ExpressionNode) -> String {
+        match expr {
+            ExpressionNode::Value(value) => self.value(value),
+            ExpressionNode::Var(name) => name.to_string(),
+            ExpressionNode::Index(name, index) => format!("{}[{}]", name, self.expression(index)),
+            ExpressionNode::Callable(name, args) => format!("{}({})", name, self.list(args)),
+            ExpressionNode::Infix(lh, op, rh) => {
+                let lh = self.operand(lh, op, true);
+                format!("{} {} {}", lh, op, self.operand(rh, op, false))
+            }
+            // a prefix op only takes the single term after it
+            ExpressionNode::Prefix(op, expr) => match **expr {
+                ExpressionNode::Infix(..) => format!("{}({})", op, self.expression(expr)),
+                _ => format!("{}{}", op, self.expression(expr)),
+            },
+            ExpressionNode::Array(exprs) => format!("[{}]", self.list(exprs)),
+            ExpressionNode::PreAllocArray(size) => format!("[size {}]", size),
+        }
+    }
+
+    // x++ parses the same as x += 1 and is kept that way
+    fn update(&mut self, op: &str, expr: &ExpressionNode) -> String {
+        match self.increment {
+            true => format!("{}{}", op, op),
+            false => format!(" {}= {}", op, self.expression(expr)),
+        }
+    }
+
+    fn statement(&mut self, statement: &StatementNode) -> String {
+        match statement {
+            StatementNode::Return(expr) => format!("return {}", self.expression(expr)),
+            StatementNode::Exit(expr) => format!("exit {}", self.expression(expr)),
+            StatementNode::Assign(name, expr) => format!("{} = {}", name, self.expression(expr)),
+            StatementNode::AssignIndex(name, index, expr) => {
+                let index = self.expression(index);
+                format!("{}[{}] = {}", name, index, self.expression(expr))
+            }
+            StatementNode::AssignOp(name, op, expr) => format!("{}{}", name, self.update(op, expr)),
+            StatementNode::AssignIndexOp(name, index, op, expr) => {
+                let index = self.expression(index);
+                format!("{}[{}]{}", name, index, self.update(op, expr))
+            }
+            StatementNode::For(name, expr) => format!("for {} in {}", name, self.expression(expr)),
+            StatementNode::EndFor => "rof".to_string(),
+            StatementNode::If(expr) => format!("if {}", self.expression(expr)),
+            StatementNode::EndIf => "fi".to_string(),
+            StatementNode::While(expr) => format!("while {}", self.expression(expr)),
+            StatementNode::EndWhile => "elihw".to_string(),
+            StatementNode::Func(name, args, inline) => {
+                let annotation = match inline {
+                    Inline::Auto => "",
+                    Inline::Always => "inline ",
+                    Inline::Never => "noinline ",
+                };
+                format!("{}foo {}({})", annotation, name, args.join(", "))
+            }
+            StatementNode::EndFunc => "oof".to_string(),
+        }
+    }
+}
+
+// the source laid out the one way: block bodies indented by two spaces,
+// one space around operators and after commas, and runs of blank lines
+// squashed to one. zeblang has no comments, so blank lines are all there
+// is to keep besides the statements
+pub fn format(code: &[String]) -> Result<String> {
+    let mut out = String::new();
+    let mut depth: usize = 0;
+    let mut blank = false;
+    let mut line_num = 0;
+    for line in code.iter() {
+        if line.trim().is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        line_num += 1;
+        let tokens =
+            Lexer::lex_spelled(line.clone()).map_err(|e| syntax_error(&e.to_string(), line_num))?;
+        let mut printer = Printer::new(&tokens);
+        let tokens = tokens.into_iter().map(|(token, _)| token).collect();
+        let node = parse(tokens, line_num)?;
+        if matches!(
+            node,
+            StatementNode::EndFor
+                | StatementNode::EndIf
+                | StatementNode::EndWhile
+                | StatementNode::EndFunc
+        ) {
+            depth = depth.saturating_sub(1);
+        }
+        if blank {
+            out += "\n";
+            blank = false;
+        }
+        out += &format!("{}{}\n", INDENT.repeat(depth), printer.statement(&node));
+        if matches!(
+            node,
+            StatementNode::For(..)
+                | StatementNode::If(_)
+                | StatementNode::While(_)
+                | StatementNode::Func(..)
+        ) {
+            depth += 1;
+        }
+    }
+    Ok(out)
+}
+
+// formats each file in place, or with check only looks. gives back the
+// files that werent formatted already
+pub fn format_files(files: &[String], check: bool) -> Result<Vec<String>> {
+    let mut changed = Vec::new();
+    for file in files.iter() {
+        let code = read_to_string(file)?;
+        let lines: Vec<String> = code.lines().map(|line| line.to_string()).collect();
+        let formatted = format(&lines).map_err(|e| new_error(&format!("{}: {}", file, e)))?;
+        if formatted == code {
+            continue;
+        }
+        if !check {
+            write(file, formatted)?;
+        }
+        changed.push(file.to_string());
+    }
+    Ok(changed)
+}
@@ -0,0 +1,196 @@
+use crate::ir::{Cond, Function, Inst, Operand, Temp, Terminator};
+
+use std::collections::{HashMap, HashSet};
+
+// the temps with a known value at some point in a function
+type Known = HashMap<Temp, i64>;
+
+fn value(known: &Known, operand: Operand) -> Operand {
+    match operand {
+        Operand::Temp(temp) => known
+            .get(&temp)
+            .map_or(operand, |value| Operand::Const(*value)),
+        operand => operand,
+    }
+}
+
+// what an instruction works out to once its operands are known. division
+// by zero is left for the check in front of it to report at runtime
+fn evaluate(inst: &Inst) -> Option<i64> {
+    match inst {
+        Inst::Copy(_, Operand::Const(value)) => Some(*value),
+        Inst::Not(_, Operand::Const(value)) => Some(!value),
+        Inst::Binary(_, op, Operand::Const(lh), Operand::Const(rh)) => op.apply(*lh, *rh).ok(),
+        _ => None,
+    }
+}
+
+fn compare(cond: Cond, lh: i64, rh: i64) -> bool {
+    match cond {
+        Cond::Eq => lh == rh,
+        Cond::Ne => lh != rh,
+        Cond::Lt => lh < rh,
+        Cond::Le => lh <= rh,
+        Cond::Gt => lh > rh,
+        Cond::Ge => lh >= rh,
+    }
+}
+
+fn fold_inst(inst: &mut Inst, known: &mut Known) {
+    for operand in inst.operands_mut() {
+        *operand = value(known, *operand);
+    }
+    let Some(dest) = inst.dest() else {
+        return;
+    };
+    match evaluate(inst) {
+        Some(value) => {
+            *inst = Inst::Copy(dest, Operand::Const(value));
+            known.insert(dest, value);
+        }
+        None => {
+            known.remove(&dest);
+        }
+    }
+}
+
+fn fold_term(term: &mut Terminator, known: &Known) {
+    for operand in term.operands_mut() {
+        *operand = value(known, *operand);
+    }
+    if let Terminator::Branch(cond, Operand::Const(lh), Operand::Const(rh), then, other) = term {
+        *term = Terminator::Jump(match compare(*cond, *lh, *rh) {
+            true => *then,
+            false => *other,
+        });
+    }
+}
+
+// constant propagation over the blocks. a block starts knowing what every
+// way into it agrees on, and a branch that folds only passes that on to
+// the side it takes, so whatever is behind a constant condition is never
+// looked at
+fn propagate(function: &Function) -> Vec<Option<Known>> {
+    let mut entry: Vec<Option<Known>> = vec![None; function.blocks.len()];
+    entry[0] = Some(Known::new());
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, block) in function.blocks.iter().enumerate() {
+            let Some(mut known) = entry[id].clone() else {
+                continue;
+            };
+            let mut block = block.clone();
+            block
+                .insts
+                .iter_mut()
+                .for_each(|inst| fold_inst(inst, &mut known));
+            fold_term(&mut block.term, &known);
+            for target in block.term.successors() {
+                let merged = match &entry[target] {
+                    Some(old) => old
+                        .iter()
+                        .filter(|(temp, value)| known.get(temp) == Some(value))
+                        .map(|(temp, value)| (*temp, *value))
+                        .collect(),
+                    None => known.clone(),
+                };
+                if entry[target].as_ref() != Some(&merged) {
+                    entry[target] = Some(merged);
+                    changed = true;
+                }
+            }
+        }
+    }
+    entry
+}
+
+// drops the blocks not marked to keep, renumbering the jumps to the rest
+fn keep_blocks(function: &mut Function, keep: &[bool]) {
+    let mut ids = Vec::new();
+    let mut next = 0;
+    for keep in keep.iter() {
+        ids.push(next);
+        next += *keep as usize;
+    }
+    let blocks = std::mem::take(&mut function.blocks);
+    function.blocks = blocks
+        .into_iter()
+        .zip(keep.iter())
+        .filter(|(_, keep)| **keep)
+        .map(|(mut block, _)| {
+            for target in block.term.successors_mut() {
+                *target = ids[*target];
+            }
+            block
+        })
+        .collect();
+}
+
+// a block that is only ever jumped to from one other block is joined onto
+// the end of it
+fn merge_blocks(function: &mut Function) {
+    let mut preds = vec![0; function.blocks.len()];
+    for block in function.blocks.iter() {
+        block
+            .term
+            .successors()
+            .iter()
+            .for_each(|target| preds[*target] += 1);
+    }
+    let mut keep = vec![true; function.blocks.len()];
+    for id in 0..function.blocks.len() {
+        while let Terminator::Jump(target) = function.blocks[id].term {
+            if target == 0 || target == id || preds[target] != 1 || !keep[target] {
+                break;
+            }
+            let joined = function.blocks[target].clone();
+            let block = &mut function.blocks[id];
+            block.insts.extend(joined.insts);
+            block.term = joined.term;
+            keep[target] = false;
+        }
+    }
+    keep_blocks(function, &keep);
+}
+
+// folds constant expressions, swaps temps with a known value for the value
+// and turns branches on constants into jumps. blocks that can no longer be
+// reached are dropped and straight runs of blocks joined up, as are folded
+// copies nothing reads any more
+pub fn fold(function: &mut Function) {
+    let entry = propagate(function);
+    for (block, known) in function.blocks.iter_mut().zip(entry.iter()) {
+        if let Some(known) = known {
+            let mut known = known.clone();
+            block
+                .insts
+                .iter_mut()
+                .for_each(|inst| fold_inst(inst, &mut known));
+            fold_term(&mut block.term, &known);
+        }
+    }
+
+    let reachable: Vec<bool> = entry.iter().map(Option::is_some).collect();
+    keep_blocks(function, &reachable);
+    merge_blocks(function);
+
+    let read: HashSet<Temp> = function
+        .blocks
+        .iter()
+        .flat_map(|block| {
+            let insts = block.insts.iter().flat_map(Inst::operands);
+            insts.chain(block.term.operands())
+        })
+        .filter_map(|operand| match operand {
+            Operand::Temp(temp) => Some(temp),
+            Operand::Const(_) => None,
+        })
+        .collect();
+    for block in function.blocks.iter_mut() {
+        block.insts.retain(|inst| match inst {
+            Inst::Copy(dest, Operand::Const(_)) => read.contains(dest),
+            _ => true,
+        });
+    }
+}
@@ -0,0 +1,192 @@
+use crate::ir::{Block, Function, Inst, Operand, Program, Temp, Terminator};
+use crate::parser::Inline;
+
+use std::collections::HashSet;
+
+// how big a function can be, in instructions and terminators, and still be
+// copied into its callers without asking
+pub const THRESHOLD: usize = 16;
+
+fn size(function: &Function) -> usize {
+    function
+        .blocks
+        .iter()
+        .map(|block| block.insts.len() + 1)
+        .sum()
+}
+
+fn callees(function: &Function) -> Vec<&str> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| block.insts.iter())
+        .filter_map(|inst| match inst {
+            Inst::Call(_, name, _) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+// functions that can end up calling themselves. copying one into its
+// callers would never stop
+fn recursive(program: &Program) -> HashSet<String> {
+    let find = |name: &str| program.functions.iter().find(|f| f.name == name);
+    let mut recursive = HashSet::new();
+    for function in program.functions.iter() {
+        let mut seen = HashSet::new();
+        let mut pending = callees(function);
+        while let Some(name) = pending.pop() {
+            if name == function.name {
+                recursive.insert(name.to_string());
+                break;
+            }
+            if seen.insert(name) {
+                pending.extend(find(name).map(callees).unwrap_or_default());
+            }
+        }
+    }
+    recursive
+}
+
+// every function after the ones it calls, so callees have already had
+// their own calls inlined when they're copied
+fn order(program: &Program) -> Vec<usize> {
+    fn visit(program: &Program, id: usize, seen: &mut Vec<bool>, order: &mut Vec<usize>) {
+        if seen[id] {
+            return;
+        }
+        seen[id] = true;
+        for name in callees(&program.functions[id]) {
+            if let Some(callee) = program.functions.iter().position(|f| f.name == name) {
+                visit(program, callee, seen, order);
+            }
+        }
+        order.push(id);
+    }
+    let mut seen = vec![false; program.functions.len()];
+    let mut order = Vec::new();
+    for id in 0..program.functions.len() {
+        visit(program, id, &mut seen, &mut order);
+    }
+    order
+}
+
+fn inlinable(callee: &Function, recursive: &HashSet<String>) -> bool {
+    // an array sized at runtime grows the stack each time it runs and only
+    // the callee's ret gives it back
+    let grows_stack = callee
+        .blocks
+        .iter()
+        .flat_map(|block| block.insts.iter())
+        .any(|inst| matches!(inst, Inst::Alloc(_, Operand::Temp(_))));
+    if recursive.contains(&callee.name) || grows_stack {
+        return false;
+    }
+    match callee.inline {
+        Inline::Always => true,
+        Inline::Never => false,
+        Inline::Auto => size(callee) <= THRESHOLD,
+    }
+}
+
+fn rename(inst: &Inst, offset: usize) -> Inst {
+    let temp = |temp: &Temp| Temp(temp.0 + offset);
+    let operand = |operand: &Operand| match operand {
+        Operand::Temp(t) => Operand::Temp(temp(t)),
+        Operand::Const(value) => Operand::Const(*value),
+    };
+    let operands = |args: &Vec<Operand>| args.iter().map(operand).collect();
+    match inst {
+        Inst::Copy(dest, value) => Inst::Copy(temp(dest), operand(value)),
+        Inst::Binary(dest, op, lh, rh) => Inst::Binary(temp(dest), *op, operand(lh), operand(rh)),
+        Inst::Not(dest, value) => Inst::Not(temp(dest), operand(value)),
+        Inst::Alloc(dest, count) => Inst::Alloc(temp(dest), operand(count)),
+        Inst::Load(dest, array, index) => Inst::Load(temp(dest), temp(array), operand(index)),
+        Inst::Store(array, index, value) => {
+            Inst::Store(temp(array), operand(index), operand(value))
+        }
+        Inst::Call(dest, name, args) => Inst::Call(temp(dest), name.clone(), operands(args)),
+        Inst::Builtin(dest, builtin, args) => Inst::Builtin(temp(dest), *builtin, operands(args)),
+    }
+}
+
+// replaces the call at insts[at] of the block with the callee's blocks,
+// renumbered after the caller's own. the arguments are copied into the
+// callee's params on the way in and every ret copies its value into the
+// call's dest and jumps to what came after the call
+fn inline_call(caller: &mut Function, block: usize, at: usize, callee: &Function) {
+    let offset = caller.types.len();
+    caller.types.extend(callee.types.iter().copied());
+    let base = caller.blocks.len();
+    let after = base + callee.blocks.len();
+
+    let rest = caller.blocks[block].insts.split_off(at + 1);
+    let Some(Inst::Call(dest, _, args)) = caller.blocks[block].insts.pop() else {
+        unreachable!("inline_call is only given calls");
+    };
+    let term = std::mem::replace(&mut caller.blocks[block].term, Terminator::Jump(base));
+    for (param, arg) in callee.params.iter().zip(args) {
+        let param = Temp(param.0 + offset);
+        caller.blocks[block].insts.push(Inst::Copy(param, arg));
+    }
+
+    for callee_block in callee.blocks.iter() {
+        let mut insts: Vec<Inst> = callee_block
+            .insts
+            .iter()
+            .map(|inst| rename(inst, offset))
+            .collect();
+        let mut term = callee_block.term.clone();
+        for operand in term.operands_mut() {
+            if let Operand::Temp(temp) = operand {
+                *temp = Temp(temp.0 + offset);
+            }
+        }
+        for target in term.successors_mut() {
+            *target += base;
+        }
+        if let Terminator::Return(value) = term {
+            insts.push(Inst::Copy(dest, value));
+            term = Terminator::Jump(after);
+        }
+        caller.blocks.push(Block { insts, term });
+    }
+    caller.blocks.push(Block { insts: rest, term });
+}
+
+fn inline_calls(caller: &mut Function, functions: &[Function], recursive: &HashSet<String>) {
+    let mut block = 0;
+    while block < caller.blocks.len() {
+        let call =
+            caller.blocks[block]
+                .insts
+                .iter()
+                .enumerate()
+                .find_map(|(at, inst)| match inst {
+                    Inst::Call(_, name, _) => functions
+                        .iter()
+                        .find(|f| f.name == *name && inlinable(f, recursive))
+                        .map(|callee| (at, callee)),
+                    _ => None,
+                });
+        match call {
+            // the rest of the block moves to a new one at the end, which
+            // gets looked at in its turn
+            Some((at, callee)) => inline_call(caller, block, at, callee),
+            None => block += 1,
+        }
+    }
+}
+
+// copies small functions, and ones marked inline, into their callers so the
+// calls cost nothing. functions that are left with no callers are removed
+// by dce afterwards
+pub fn inline(program: &mut Program) {
+    let recursive = recursive(program);
+    for id in order(program) {
+        let mut caller = std::mem::replace(&mut program.functions[id], Function::new(""));
+        inline_calls(&mut caller, &program.functions, &recursive);
+        program.functions[id] = caller;
+    }
+    inline_calls(&mut program.main, &program.functions, &recursive);
+}
@@ -0,0 +1,572 @@
+use crate::error::{new_error, runtime_error, syntax_error};
+use crate::parser::{ExpressionNode, StatementNode};
+use crate::runtime::{self, SENTINEL};
+use crate::syscall;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, Result, Write};
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i64),
+    // the elements followed by the sentinel slot, laid out like the stack
+    Array(Vec<i64>),
+}
+
+// exit unwinds through expressions and calls as an error carrying the code
+#[derive(Debug)]
+struct Exit(i64);
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exit {}", self.0)
+    }
+}
+
+impl std::error::Error for Exit {}
+
+#[derive(Debug)]
+struct Func {
+    start: usize,
+    args: Vec<String>,
+}
+
+#[derive(Debug)]
+enum Loop {
+    Array(Vec<i64>, usize),
+    Range(i64, i64),
+}
+
+// calls recurse through run_from on the rust stack, so programs run on a
+// thread with room for this many and a deeper one is an error
+const MAX_DEPTH: usize = 10_000;
+const STACK_SIZE: usize = 256 << 20;
+
+#[derive(Debug, Default)]
+struct Frame {
+    variables: HashMap<String, Value>,
+    loops: HashMap<usize, Loop>,
+    returned: i64,
+}
+
+// walks the statements from make_parsetree directly, following the same
+// rules as the compiled code
+pub struct Interpreter<W: Write> {
+    program: Arc<Vec<StatementNode>>,
+    blocks: Vec<usize>,
+    funcs: HashMap<String, Func>,
+    frames: Vec<Frame>,
+    out: W,
+    // the line each statement is on and the statement running, for errors
+    lines: Vec<usize>,
+    at: usize,
+}
+
+impl<W: Write + Send> Interpreter<W> {
+    pub fn new(program: Vec<StatementNode>, out: W) -> Result<Self> {
+        Self::with_lines(program, &[], out)
+    }
+
+    // without the lines, statements are counted from 1 like the parser does
+    pub fn with_lines(program: Vec<StatementNode>, lines: &[usize], out: W) -> Result<Self> {
+        Ok(Self {
+            blocks: Self::match_blocks(&program)?,
+            program: Arc::new(program),
+            funcs: HashMap::new(),
+            frames: vec![Frame::default()],
+            out,
+            lines: lines.to_vec(),
+            at: 0,
+        })
+    }
+
+    // runs the program and gives back its exit code, falling off the end is 0
+    pub fn run(&mut self) -> Result<i64> {
+        Ok(self.resume(0)?.unwrap_or(0))
+    }
+
+    // appends statements to the program and runs just those, keeping the
+    // variables and functions from before. gives back the code if they exit
+    pub fn extend(&mut self, statements: Vec<StatementNode>) -> Result<Option<i64>> {
+        let start = self.program.len();
+        let mut program = self.program.to_vec();
+        program.extend(statements);
+        self.blocks = Self::match_blocks(&program)?;
+        self.program = Arc::new(program);
+        self.resume(start)
+    }
+
+    // evaluates a lone expression against the current variables
+    pub fn evaluate(&mut self, expr: &ExpressionNode) -> Result<i64> {
+        let value = self.deep(|interpreter| interpreter.eval_int(expr));
+        self.out.flush()?;
+        value
+    }
+
+    // the code an error carries when it came from exit
+    pub fn exit_code(e: &Error) -> Option<i64> {
+        e.get_ref()
+            .and_then(|inner| inner.downcast_ref::<Exit>())
+            .map(|Exit(code)| *code)
+    }
+
+    pub fn out(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    // runs f on a thread with room for MAX_DEPTH calls
+    fn deep<T: Send>(&mut self, f: impl FnOnce(&mut Self) -> Result<T> + Send) -> Result<T> {
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || f(self))?
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    fn line(&self) -> usize {
+        self.lines.get(self.at).copied().unwrap_or(self.at + 1)
+    }
+
+    fn resume(&mut self, pc: usize) -> Result<Option<i64>> {
+        let code = match self.deep(|interpreter| interpreter.run_from(pc)) {
+            Ok(()) => None,
+            Err(e) => match Self::exit_code(&e) {
+                Some(code) => Some(code),
+                None => {
+                    self.out.flush()?;
+                    return Err(e);
+                }
+            },
+        };
+        self.out.flush()?;
+        Ok(code)
+    }
+
+    // pair every block opener with its closer so jumps are a lookup
+    fn match_blocks(program: &[StatementNode]) -> Result<Vec<usize>> {
+        let mut blocks = vec![0; program.len()];
+        let mut open: Vec<usize> = Vec::new();
+        for (i, statement) in program.iter().enumerate() {
+            let opener = match statement {
+                StatementNode::If(_)
+                | StatementNode::While(_)
+                | StatementNode::For(..)
+                | StatementNode::Func(..) => {
+                    open.push(i);
+                    continue;
+                }
+                StatementNode::EndIf => "if",
+                StatementNode::EndWhile => "while",
+                StatementNode::EndFor => "for",
+                StatementNode::EndFunc => "foo",
+                _ => continue,
+            };
+            let start = open
+                .pop()
+                .ok_or(syntax_error(&format!("no {} to close", opener), i + 1))?;
+            match (&program[start], opener) {
+                (StatementNode::If(_), "if")
+                | (StatementNode::While(_), "while")
+                | (StatementNode::For(..), "for")
+                | (StatementNode::Func(..), "foo") => (),
+                _ => return Err(syntax_error(&format!("no {} to close", opener), i + 1)),
+            }
+            blocks[start] = i;
+            blocks[i] = start;
+        }
+        match open.pop() {
+            Some(start) => Err(syntax_error("block is never closed", start + 1)),
+            None => Ok(blocks),
+        }
+    }
+
+    // runs until the program ends or the oof of a call
+    fn run_from(&mut self, mut pc: usize) -> Result<()> {
+        let program = Arc::clone(&self.program);
+        while pc < program.len() {
+            self.at = pc;
+            pc = match &program[pc] {
+                StatementNode::Exit(expr) => {
+                    let code = self.eval_int(expr)?;
+                    return Err(Error::other(Exit(code)));
+                }
+                StatementNode::Assign(name, expr) => {
+                    let value = self.eval(expr)?;
+                    let variables = &mut self.frame().variables;
+                    // a number assigned to an array goes in its first slot
+                    match (variables.get_mut(name), value) {
+                        (Some(Value::Array(slots)), Value::Int(value)) => slots[0] = value,
+                        (_, value) => {
+                            variables.insert(name.to_string(), value);
+                        }
+                    }
+                    pc + 1
+                }
+                StatementNode::AssignIndex(name, index_expr, assign_expr) => {
+                    let value = self.eval_int(assign_expr)?;
+                    let index = self.eval_int(index_expr)?;
+                    *self.element(name, index)? = value;
+                    pc + 1
+                }
+                StatementNode::AssignOp(name, op, expr) => {
+                    let value = self.eval_int(expr)?;
+                    let current = *self.slot(name)?;
+                    let value = self.apply(current, op, value)?;
+                    *self.slot(name)? = value;
+                    pc + 1
+                }
+                StatementNode::AssignIndexOp(name, index_expr, op, assign_expr) => {
+                    let value = self.eval_int(assign_expr)?;
+                    let index = self.eval_int(index_expr)?;
+                    let current = *self.element(name, index)?;
+                    let value = self.apply(current, op, value)?;
+                    *self.element(name, index)? = value;
+                    pc + 1
+                }
+                StatementNode::If(expr) | StatementNode::While(expr) => {
+                    match self.eval_int(expr)? {
+                        0 => self.blocks[pc] + 1,
+                        _ => pc + 1,
+                    }
+                }
+                StatementNode::EndIf => pc + 1,
+                StatementNode::EndWhile => self.blocks[pc],
+                StatementNode::For(varname, expr) => self.start_for(pc, varname, expr)?,
+                StatementNode::EndFor => self.next_for(pc)?,
+                StatementNode::Func(name, args, _) => {
+                    let func = Func {
+                        start: pc + 1,
+                        args: args.clone(),
+                    };
+                    self.funcs.insert(name.to_string(), func);
+                    self.blocks[pc] + 1
+                }
+                StatementNode::EndFunc => return Ok(()),
+                // like the generated code return only stores the value, the
+                // body carries on until oof
+                StatementNode::Return(expr) => {
+                    self.frame().returned = self.eval_int(expr)?;
+                    pc + 1
+                }
+            };
+        }
+        Ok(())
+    }
+
+    fn start_for(&mut self, pc: usize, varname: &str, expr: &ExpressionNode) -> Result<usize> {
+        let state = match expr {
+            ExpressionNode::Callable(name, args) if name == "range" => {
+                let (start, stop, step) = self.eval_range(args)?;
+                self.set_int(varname, start);
+                Loop::Range(stop, step)
+            }
+            expr => match self.eval(expr)? {
+                Value::Array(slots) => Loop::Array(slots, 0),
+                Value::Int(value) => Loop::Array(vec![value, SENTINEL], 0),
+            },
+        };
+        self.frame().loops.insert(pc, state);
+        self.step_for(pc, varname, true)
+    }
+
+    fn next_for(&mut self, pc: usize) -> Result<usize> {
+        let start = self.blocks[pc];
+        let varname = match &self.program[start] {
+            StatementNode::For(varname, _) => varname.to_string(),
+            _ => unreachable!(),
+        };
+        self.step_for(start, &varname, false)
+    }
+
+    // moves the loop var on and picks where to carry on from
+    fn step_for(&mut self, pc: usize, varname: &str, first: bool) -> Result<usize> {
+        let current = match self.frame().variables.get(varname) {
+            Some(Value::Int(value)) => *value,
+            Some(Value::Array(slots)) => slots[0],
+            None => 0,
+        };
+        let next = match self.frame().loops.get_mut(&pc) {
+            Some(Loop::Range(stop, step)) => {
+                let value = match first {
+                    true => current,
+                    false => current.wrapping_add(*step),
+                };
+                let running = match (*step).cmp(&0) {
+                    std::cmp::Ordering::Greater => value < *stop,
+                    std::cmp::Ordering::Less => value > *stop,
+                    std::cmp::Ordering::Equal => false,
+                };
+                (value, running)
+            }
+            Some(Loop::Array(slots, position)) => {
+                let value = slots.get(*position).copied().unwrap_or(SENTINEL);
+                *position += 1;
+                (value, value != SENTINEL)
+            }
+            None => unreachable!(),
+        };
+        self.set_int(varname, next.0);
+        match next.1 {
+            true => Ok(pc + 1),
+            false => {
+                self.frame().loops.remove(&pc);
+                Ok(self.blocks[pc] + 1)
+            }
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn set_int(&mut self, name: &str, value: i64) {
+        self.frame()
+            .variables
+            .insert(name.to_string(), Value::Int(value));
+    }
+
+    fn variable(&mut self, name: &str) -> Result<&mut Value> {
+        self.frame()
+            .variables
+            .get_mut(name)
+            .ok_or_else(|| new_error(&format!("variable {} not found in this scope", name)))
+    }
+
+    // the first slot of a variable, a number is its own
+    fn slot(&mut self, name: &str) -> Result<&mut i64> {
+        Ok(match self.variable(name)? {
+            Value::Int(value) => value,
+            Value::Array(slots) => &mut slots[0],
+        })
+    }
+
+    fn element(&mut self, name: &str, index: i64) -> Result<&mut i64> {
+        let slots = match self.variable(name)? {
+            Value::Int(_) => return Err(new_error(&format!("{} is not an array", name))),
+            Value::Array(slots) => slots,
+        };
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| slots.get_mut(index))
+            .ok_or_else(|| new_error(&format!("index {} is outside of {}", index, name)))
+    }
+
+    fn array(&mut self, name: &str) -> Result<&mut Vec<i64>> {
+        match self.frame().variables.get_mut(name) {
+            Some(Value::Array(slots)) => Ok(slots),
+            _ => Err(new_error(&format!(
+                "array {} not found in this scope",
+                name
+            ))),
+        }
+    }
+
+    fn eval_int(&mut self, expr: &ExpressionNode) -> Result<i64> {
+        match self.eval(expr)? {
+            Value::Int(value) => Ok(value),
+            Value::Array(_) => Err(new_error("arrays can only be assigned or looped over")),
+        }
+    }
+
+    fn eval(&mut self, expr: &ExpressionNode) -> Result<Value> {
+        let value = match expr {
+            ExpressionNode::Value(value) => value
+                .parse::<i64>()
+                .map_err(|_| new_error(&format!("invalid integer {}", value)))?,
+            ExpressionNode::Var(name) => *self.slot(name)?,
+            ExpressionNode::Index(name, index) => {
+                let index = self.eval_int(index)?;
+                *self.element(name, index)?
+            }
+            ExpressionNode::Infix(lh, op, rh) => {
+                let lh = self.eval_int(lh)?;
+                let rh = self.eval_int(rh)?;
+                self.apply(lh, op, rh)?
+            }
+            ExpressionNode::Prefix(op, expr) => match op.as_str() {
+                "~" => !self.eval_int(expr)?,
+                _ => return Err(new_error(&format!("unknown operator {}", op))),
+            },
+            ExpressionNode::Callable(name, args) => return self.eval_call(name, args),
+            ExpressionNode::Array(exprs) => {
+                let mut slots = exprs
+                    .iter()
+                    .map(|expr| self.eval_int(expr))
+                    .collect::<Result<Vec<i64>>>()?;
+                slots.push(SENTINEL);
+                return Ok(Value::Array(slots));
+            }
+            ExpressionNode::PreAllocArray(size) => {
+                return Ok(Value::Array(vec![SENTINEL; size + 1]))
+            }
+        };
+        Ok(Value::Int(value))
+    }
+
+    fn apply(&self, lh: i64, op: &str, rh: i64) -> Result<i64> {
+        Ok(match op {
+            "+" => lh.wrapping_add(rh),
+            "-" => lh.wrapping_sub(rh),
+            "*" => lh.wrapping_mul(rh),
+            "/" | "%" if rh == 0 => return Err(runtime_error("division by zero", self.line())),
+            "/" => lh.wrapping_div(rh),
+            "%" => lh.wrapping_rem(rh),
+            "==" => (lh == rh) as i64,
+            "!=" => (lh != rh) as i64,
+            "&" => lh & rh,
+            "|" => lh | rh,
+            "^" => lh ^ rh,
+            "<<" => lh.wrapping_shl(rh as u32),
+            ">>" => lh.wrapping_shr(rh as u32),
+            _ => return Err(new_error(&format!("unknown operator {}", op))),
+        })
+    }
+
+    fn eval_call(&mut self, name: &str, args: &[Box<ExpressionNode>]) -> Result<Value> {
+        let value = match name {
+            "open" | "read" | "write" | "close" | "unlink" => self.eval_io(name, args)?,
+            "range" => {
+                let (start, stop, step) = self.eval_range(args)?;
+                let mut slots = Vec::new();
+                let mut value = start;
+                while (step > 0 && value < stop) || (step < 0 && value > stop) {
+                    slots.push(value);
+                    value += step;
+                }
+                slots.push(SENTINEL);
+                return Ok(Value::Array(slots));
+            }
+            "print" => {
+                let mut value = None;
+                for arg in args.iter() {
+                    value = Some(self.eval_int(arg)?);
+                }
+                let value = value.ok_or(new_error("print expects an argument"))?;
+                writeln!(self.out, "{}", value)?;
+                value
+            }
+            name => self.call(name, args)?,
+        };
+        Ok(Value::Int(value))
+    }
+
+    fn eval_range(&mut self, args: &[Box<ExpressionNode>]) -> Result<(i64, i64, i64)> {
+        let args = args
+            .iter()
+            .map(|arg| self.eval_int(arg))
+            .collect::<Result<Vec<i64>>>()?;
+        match args[..] {
+            [stop] => Ok((0, stop, 1)),
+            [start, stop] => Ok((start, stop, 1)),
+            [start, stop, step] => Ok((start, stop, step)),
+            _ => Err(new_error("range expects 1 to 3 arguments")),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Box<ExpressionNode>]) -> Result<i64> {
+        let values = args
+            .iter()
+            .map(|arg| self.eval_int(arg))
+            .collect::<Result<Vec<i64>>>()?;
+        let func = self
+            .funcs
+            .get(name)
+            .ok_or(new_error(&format!("function {} undefined", name)))?;
+        if func.args.len() != values.len() {
+            return Err(new_error(&format!(
+                "{} expects {} arguments, got {}",
+                name,
+                func.args.len(),
+                values.len()
+            )));
+        }
+        let mut frame = Frame::default();
+        for (arg, value) in func.args.iter().zip(values) {
+            frame.variables.insert(arg.to_string(), Value::Int(value));
+        }
+        if self.frames.len() > MAX_DEPTH {
+            return Err(runtime_error("call stack too deep", self.line()));
+        }
+        let (start, at) = (func.start, self.at);
+        self.frames.push(frame);
+        let out = self.run_from(start);
+        let frame = self.frames.pop().unwrap();
+        self.at = at;
+        out?;
+        Ok(frame.returned)
+    }
+
+    fn eval_io(&mut self, name: &str, args: &[Box<ExpressionNode>]) -> Result<i64> {
+        let arity = match name {
+            "open" | "read" | "write" => 3,
+            _ => 1,
+        };
+        if args.len() != arity {
+            return Err(new_error(&format!(
+                "{} expects {} arguments, got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        match name {
+            "open" => {
+                let path = self.path(name, &args[0])?;
+                let flags = self.eval_int(&args[1])?;
+                let mode = self.eval_int(&args[2])?;
+                Ok(syscall::open(&path, flags, mode))
+            }
+            "read" | "write" => {
+                let buffer = Self::array_arg(name, &args[1])?;
+                let fd = self.eval_int(&args[0])?;
+                let count = self.eval_int(&args[2])?;
+                let length = self.array(&buffer)?.len() - 1;
+                let count = runtime::clamp(count, length);
+                let slots = self.array(&buffer)?;
+                if name == "read" {
+                    return Ok(runtime::read(fd, slots, count));
+                }
+                let bytes = runtime::bytes(slots, count);
+                match fd {
+                    1 => {
+                        self.out.write_all(&bytes)?;
+                        Ok(bytes.len() as i64)
+                    }
+                    fd => Ok(syscall::write(fd, &bytes)),
+                }
+            }
+            "close" => {
+                let fd = self.eval_int(&args[0])?;
+                Ok(syscall::close(fd))
+            }
+            _ => {
+                let path = self.path(name, &args[0])?;
+                Ok(syscall::unlink(&path))
+            }
+        }
+    }
+
+    fn array_arg(name: &str, expr: &ExpressionNode) -> Result<String> {
+        match expr {
+            ExpressionNode::Var(varname) => Ok(varname.to_string()),
+            _ => Err(new_error(&format!("{} expects an array variable", name))),
+        }
+    }
+
+    fn path(&mut self, name: &str, expr: &ExpressionNode) -> Result<Vec<u8>> {
+        let varname = Self::array_arg(name, expr)?;
+        match self.frame().variables.get(&varname) {
+            Some(Value::Array(slots)) => Ok(runtime::path(slots)),
+            Some(Value::Int(value)) => Ok(runtime::path(&[*value])),
+            None => Err(new_error(&format!(
+                "variable {} not found in this scope",
+                varname
+            ))),
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use crate::bytecode::BinOp;
+use crate::error::new_error;
+use crate::parser::Inline;
+
+use std::fmt;
+use std::io::Result;
+
+// a virtual register. variables are temps too and can be assigned more
+// than once, only arrays live in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Temp(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ty {
+    Int,
+    // the first slot of an array, slots are 8 bytes
+    Ptr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Temp(Temp),
+    Const(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// the runtime routines every backend provides
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    // prints the value and hands it back
+    Print,
+    // ptr, count. sets count slots to the sentinel
+    Fill,
+    // start, stop, step. how many values the range has
+    RangeLen,
+    // ptr, start, step, len. writes the range and its sentinel
+    RangeFill,
+    // ptr. copies the array into iobuf as a c string and gives its address
+    Path,
+    // fd, ptr, len, count
+    Read,
+    Write,
+    // number and three arguments, straight to the kernel
+    Syscall,
+    // line. reports a division by zero there and exits
+    DivZero,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Copy(Temp, Operand),
+    Binary(Temp, BinOp, Operand, Operand),
+    Not(Temp, Operand),
+    // room for this many slots on the stack. a constant count is part of
+    // the frame, anything else grows the stack each time it runs
+    Alloc(Temp, Operand),
+    // dest, array, index
+    Load(Temp, Temp, Operand),
+    // array, index, value
+    Store(Temp, Operand, Operand),
+    Call(Temp, String, Vec<Operand>),
+    Builtin(Temp, Builtin, Vec<Operand>),
+}
+
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    // compares the operands, going to the first block when cond holds
+    Branch(Cond, Operand, Operand, BlockId, BlockId),
+    Return(Operand),
+    Exit(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+
+// the entry is block 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Temp>,
+    // indexed by temp
+    pub types: Vec<Ty>,
+    pub blocks: Vec<Block>,
+    pub inline: Inline,
+}
+
+// main is the top level of the script and ends by exiting
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub main: Function,
+}
+
+impl Inst {
+    pub fn dest(&self) -> Option<Temp> {
+        match self {
+            Inst::Copy(dest, _)
+            | Inst::Binary(dest, ..)
+            | Inst::Not(dest, _)
+            | Inst::Alloc(dest, _)
+            | Inst::Load(dest, ..)
+            | Inst::Call(dest, ..)
+            | Inst::Builtin(dest, ..) => Some(*dest),
+            Inst::Store(..) => None,
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Inst::Copy(_, value) | Inst::Not(_, value) | Inst::Alloc(_, value) => vec![*value],
+            Inst::Binary(_, _, lh, rh) => vec![*lh, *rh],
+            Inst::Load(_, array, index) => vec![Operand::Temp(*array), *index],
+            Inst::Store(array, index, value) => vec![Operand::Temp(*array), *index, *value],
+            Inst::Call(_, _, args) | Inst::Builtin(_, _, args) => args.clone(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Copy(_, value) | Inst::Not(_, value) | Inst::Alloc(_, value) => vec![value],
+            Inst::Binary(_, _, lh, rh) => vec![lh, rh],
+            Inst::Load(_, _, index) => vec![index],
+            Inst::Store(_, index, value) => vec![index, value],
+            Inst::Call(_, _, args) | Inst::Builtin(_, _, args) => args.iter_mut().collect(),
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(.., then, other) => vec![*then, *other],
+            Terminator::Return(_) | Terminator::Exit(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch(_, lh, rh, ..) => vec![*lh, *rh],
+            Terminator::Return(value) | Terminator::Exit(value) => vec![*value],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch(_, lh, rh, ..) => vec![lh, rh],
+            Terminator::Return(value) | Terminator::Exit(value) => vec![value],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(.., then, other) => vec![then, other],
+            Terminator::Return(_) | Terminator::Exit(_) => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            params: Vec::new(),
+            types: Vec::new(),
+            blocks: Vec::new(),
+            inline: Inline::Auto,
+        }
+    }
+
+    pub fn temp(&mut self, ty: Ty) -> Temp {
+        self.types.push(ty);
+        Temp(self.types.len() - 1)
+    }
+
+    // every temp is declared, used at its type and every branch lands on a
+    // block. the lowering should never get this wrong, passes might
+    pub fn verify(&self) -> Result<()> {
+        let fail = |msg: String| Err(new_error(&format!("{}: {}", self.name, msg)));
+        let ty = |operand: &Operand| match operand {
+            Operand::Temp(temp) => self.types.get(temp.0).copied(),
+            Operand::Const(_) => Some(Ty::Int),
+        };
+        if self.blocks.is_empty() {
+            return fail("no blocks".to_string());
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            for inst in block.insts.iter() {
+                let operands = inst.operands();
+                if operands
+                    .iter()
+                    .chain(inst.dest().map(Operand::Temp).iter())
+                    .any(|o| ty(o).is_none())
+                {
+                    return fail(format!("undeclared temp in {}", inst));
+                }
+                let wanted: Vec<Ty> = match inst {
+                    Inst::Copy(dest, _) => vec![self.types[dest.0]],
+                    Inst::Load(..) => vec![Ty::Ptr, Ty::Int],
+                    Inst::Store(..) => vec![Ty::Ptr, Ty::Int, Ty::Int],
+                    Inst::Builtin(_, builtin, _) => match builtin {
+                        Builtin::Fill | Builtin::Path => vec![Ty::Ptr, Ty::Int],
+                        Builtin::RangeFill => vec![Ty::Ptr, Ty::Int, Ty::Int, Ty::Int],
+                        Builtin::Read | Builtin::Write => vec![Ty::Int, Ty::Ptr, Ty::Int, Ty::Int],
+                        _ => vec![Ty::Int; 4],
+                    },
+                    _ => vec![Ty::Int; operands.len()],
+                };
+                if operands
+                    .iter()
+                    .zip(wanted.iter())
+                    .any(|(o, want)| ty(o) != Some(*want))
+                {
+                    return fail(format!("operand of the wrong type in {}", inst));
+                }
+                let made = match inst {
+                    Inst::Alloc(..) => Ty::Ptr,
+                    Inst::Copy(_, value) => ty(value).unwrap(),
+                    _ => Ty::Int,
+                };
+                if inst.dest().is_some_and(|dest| self.types[dest.0] != made) {
+                    return fail(format!("result of the wrong type in {}", inst));
+                }
+            }
+            if block.term.operands().iter().any(|o| ty(o) != Some(Ty::Int)) {
+                return fail(format!("b{} ends on something that isnt a number", id));
+            }
+            if block
+                .term
+                .successors()
+                .iter()
+                .any(|target| *target >= self.blocks.len())
+            {
+                return fail(format!("b{} jumps to a block that doesnt exist", id));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    pub fn verify(&self) -> Result<()> {
+        self.functions
+            .iter()
+            .chain(std::iter::once(&self.main))
+            .try_for_each(Function::verify)
+    }
+}
+
+fn op_name(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "add",
+        BinOp::Sub => "sub",
+        BinOp::Mul => "mul",
+        BinOp::Div => "div",
+        BinOp::Mod => "mod",
+        BinOp::Eq => "eq",
+        BinOp::Ne => "ne",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+        BinOp::Xor => "xor",
+        BinOp::Shl => "shl",
+        BinOp::Shr => "shr",
+    }
+}
+
+fn ty_name(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Int => "int",
+        Ty::Ptr => "ptr",
+    }
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Temp(temp) => write!(f, "{}", temp),
+            Operand::Const(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Cond::Eq => "eq",
+            Cond::Ne => "ne",
+            Cond::Lt => "lt",
+            Cond::Le => "le",
+            Cond::Gt => "gt",
+            Cond::Ge => "ge",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Builtin::Print => "print",
+            Builtin::Fill => "fill",
+            Builtin::RangeLen => "range_len",
+            Builtin::RangeFill => "range_fill",
+            Builtin::Path => "path",
+            Builtin::Read => "read",
+            Builtin::Write => "write",
+            Builtin::Syscall => "syscall",
+            Builtin::DivZero => "div_zero",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn list(operands: &[Operand]) -> String {
+    let operands: Vec<String> = operands.iter().map(Operand::to_string).collect();
+    operands.join(", ")
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inst::Copy(dest, value) => write!(f, "{} = {}", dest, value),
+            Inst::Binary(dest, op, lh, rh) => {
+                write!(f, "{} = {} {}, {}", dest, op_name(*op), lh, rh)
+            }
+            Inst::Not(dest, value) => write!(f, "{} = not {}", dest, value),
+            Inst::Alloc(dest, count) => write!(f, "{} = alloc {}", dest, count),
+            Inst::Load(dest, array, index) => write!(f, "{} = load {}[{}]", dest, array, index),
+            Inst::Store(array, index, value) => write!(f, "store {}[{}], {}", array, index, value),
+            Inst::Call(dest, name, args) => write!(f, "{} = call {}({})", dest, name, list(args)),
+            Inst::Builtin(dest, builtin, args) => {
+                write!(f, "{} = {} {}", dest, builtin, list(args))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jmp b{}", target),
+            Terminator::Branch(cond, lh, rh, then, other) => {
+                write!(f, "br {} {}, {}, b{}, b{}", cond, lh, rh, then, other)
+            }
+            Terminator::Return(value) => write!(f, "ret {}", value),
+            Terminator::Exit(value) => write!(f, "exit {}", value),
+        }
+    }
+}
+
+// temps are shown with their type where they are first written
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|param| format!("{}: {}", param, ty_name(self.types[param.0])))
+            .collect();
+        let annotation = match self.inline {
+            Inline::Auto => "",
+            Inline::Always => "inline ",
+            Inline::Never => "noinline ",
+        };
+        writeln!(
+            f,
+            "{}func {}({}) {{",
+            annotation,
+            self.name,
+            params.join(", ")
+        )?;
+        let mut declared = vec![false; self.types.len()];
+        for param in self.params.iter() {
+            declared[param.0] = true;
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", id)?;
+            for inst in block.insts.iter() {
+                let text = inst.to_string();
+                match inst.dest() {
+                    Some(dest) if !declared[dest.0] => {
+                        declared[dest.0] = true;
+                        let ty = ty_name(self.types[dest.0]);
+                        let (dest, rest) = text.split_once(" = ").unwrap();
+                        writeln!(f, "    {}: {} = {}", dest, ty, rest)?
+                    }
+                    _ => writeln!(f, "    {}", text)?,
+                }
+            }
+            writeln!(f, "    {}", block.term)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for function in self.functions.iter() {
+            writeln!(f, "{}", function)?;
+        }
+        write!(f, "{}", self.main)
+    }
+}
@@ -0,0 +1,246 @@
+use crate::assembler::{Assembler, Layout};
+use crate::error::new_error;
+use crate::optimize::Options;
+use crate::parser::StatementNode;
+use crate::syscall;
+use crate::x86::compile;
+
+use std::io::Result;
+
+const PROT_READ: i64 = 1;
+const PROT_WRITE: i64 = 2;
+const PROT_EXEC: i64 = 4;
+// keeps the absolute 32 bit addresses the assembler uses valid
+const MAP_32BIT: i64 = 0x40;
+const MAP_NORESERVE: i64 = 0x4000;
+const PAGE: usize = 4096;
+// the same as the default main thread stack
+const STACK_SIZE: usize = 8 << 20;
+
+// the way in and out of the jitted code. jit_enter saves
+// the rust side's registers, swaps to the program's stack and jumps to
+// _start with the registers cleared, as a new process would have them.
+// jit_syscall stands in for every syscall, it keeps the registers
+// the kernel would, calls handle_syscall and leaves through jit_leave when
+// the program exits
+const RUNTIME: &str = "
+.jit_enter:
+    push rbx
+    push rbp
+    push r12
+    push r13
+    push r14
+    push r15
+    mov [.jit_host_rsp], rsp
+    mov rsp, rsi
+    mov r11, rdi
+    xor rax, rax
+    xor rbx, rbx
+    xor rcx, rcx
+    xor rdx, rdx
+    xor rsi, rsi
+    xor rdi, rdi
+    xor rbp, rbp
+    xor r8, r8
+    xor r9, r9
+    xor r10, r10
+    xor r12, r12
+    xor r13, r13
+    xor r14, r14
+    xor r15, r15
+    jmp r11
+.jit_syscall:
+    push rdi
+    push rsi
+    push rdx
+    push r8
+    push r9
+    push r10
+    push rbp
+    mov rbp, rsp
+    and rsp, -16
+    mov rcx, rdx
+    mov rdx, rsi
+    mov rsi, rdi
+    mov rdi, rax
+    mov r8, [.jit_out]
+    call [.jit_handler]
+    mov rsp, rbp
+    pop rbp
+    cmp rdx, 0
+    jne .jit_leave
+    pop r10
+    pop r9
+    pop r8
+    pop rdx
+    pop rsi
+    pop rdi
+    ret
+.jit_leave:
+    mov rsp, [.jit_host_rsp]
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbp
+    pop rbx
+    ret
+section .bss
+    .jit_host_rsp: resq 1
+    .jit_handler: resq 1
+    .jit_out: resq 1
+";
+
+// anonymous memory that is unmapped when dropped
+#[derive(Debug)]
+struct Mapping {
+    address: i64,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(len: usize, flags: i64) -> Result<Self> {
+        let len = len.max(1).div_ceil(PAGE) * PAGE;
+        let address = syscall::mmap(len, PROT_READ | PROT_WRITE, flags);
+        match address {
+            -4095..=-1 => Err(new_error(&format!("mmap failed with {}", address))),
+            address => Ok(Self { address, len }),
+        }
+    }
+
+    fn protect(&self, offset: usize, len: usize, prot: i64) -> Result<()> {
+        match syscall::mprotect(self.address + offset as i64, len, prot) {
+            0 => Ok(()),
+            e => Err(new_error(&format!("mprotect failed with {}", e))),
+        }
+    }
+
+    fn bytes(&mut self) -> &mut [u8] {
+        // safety: the mapping is ours, writable and len bytes long until drop
+        unsafe { std::slice::from_raw_parts_mut(self.address as *mut u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        syscall::munmap(self.address, self.len);
+    }
+}
+
+// handle_syscall's result comes back in rax and rdx, leave is set when the
+// program exits
+#[repr(C)]
+struct Outcome {
+    value: i64,
+    leave: i64,
+}
+
+// exit ends the run and anything written to stdout is kept, the rest go
+// to the kernel
+extern "C" fn handle_syscall(
+    number: i64,
+    arg_1: i64,
+    arg_2: i64,
+    arg_3: i64,
+    out: *mut Vec<u8>,
+) -> Outcome {
+    let value = match (number, arg_1) {
+        (60, code) => {
+            return Outcome {
+                value: code,
+                leave: 1,
+            }
+        }
+        (1, 1) => {
+            // safety: the program only writes from its own memory, and out
+            // is the jit's buffer which lives for the whole run
+            unsafe {
+                let bytes = std::slice::from_raw_parts(arg_2 as *const u8, arg_3 as usize);
+                (*out).extend_from_slice(bytes);
+            }
+            arg_3
+        }
+        _ => syscall::forward(number, arg_1, arg_2, arg_3),
+    };
+    Outcome { value, leave: 0 }
+}
+
+// runs the generator's machine code inside this process instead of writing
+// an executable. only works on x86-64 linux
+#[derive(Debug)]
+pub struct Jit {
+    text: Mapping,
+    data: Mapping,
+    stack: Mapping,
+    // the data as it starts, copied in before every run
+    image: Vec<u8>,
+    start: u64,
+    // where jit_enter is in the text
+    enter: u64,
+    handler_slot: usize,
+    out_slot: usize,
+    out: Vec<u8>,
+}
+
+impl Jit {
+    pub fn new(program: Vec<StatementNode>, lines: &[usize], options: Options) -> Result<Self> {
+        let assembly = compile(program, lines, options)? + RUNTIME;
+        let object = Assembler::with_syscall_hook(".jit_syscall").assemble(&assembly)?;
+        let bss_offset = object.data.len().div_ceil(16) * 16;
+        let mut text = Mapping::new(object.text.len(), MAP_32BIT)?;
+        let data = Mapping::new(bss_offset + object.bss, MAP_32BIT)?;
+        // the lowest page is left as a guard so running out of stack faults
+        let stack = Mapping::new(STACK_SIZE + PAGE, MAP_NORESERVE)?;
+        stack.protect(0, PAGE, 0)?;
+
+        let layout = Layout {
+            text: text.address as u64,
+            data: data.address as u64,
+            bss: (data.address + bss_offset as i64) as u64,
+        };
+        let code = object.relocated_text(&layout)?;
+        text.bytes()[..code.len()].copy_from_slice(&code);
+        text.protect(0, text.len, PROT_READ | PROT_EXEC)?;
+
+        let mut image = object.data.clone();
+        image.resize(data.len, 0);
+        let slot =
+            |name| -> Result<usize> { Ok((object.address(name, &layout)? - layout.data) as usize) };
+        Ok(Self {
+            start: object.address("_start", &layout)?,
+            enter: object.address(".jit_enter", &layout)? - layout.text,
+            handler_slot: slot(".jit_handler")?,
+            out_slot: slot(".jit_out")?,
+            text,
+            data,
+            stack,
+            image,
+            out: Vec::new(),
+        })
+    }
+
+    // runs the program from the top, returning the value it exits with and
+    // what it wrote to stdout
+    pub fn run(&mut self) -> Result<(i64, Vec<u8>)> {
+        self.out.clear();
+        let handler: extern "C" fn(i64, i64, i64, i64, *mut Vec<u8>) -> Outcome = handle_syscall;
+        let handler = handler as usize as u64;
+        let out = &mut self.out as *mut Vec<u8> as u64;
+        let (handler_slot, out_slot) = (self.handler_slot, self.out_slot);
+        let data = self.data.bytes();
+        data.copy_from_slice(&self.image);
+        data[handler_slot..handler_slot + 8].copy_from_slice(&handler.to_le_bytes());
+        data[out_slot..out_slot + 8].copy_from_slice(&out.to_le_bytes());
+
+        let stack_top = (self.stack.address + self.stack.len as i64) as u64;
+        // safety: enter is jit_enter in the text mapped above. it hands back
+        // rust's registers and stack untouched when the program exits, and
+        // the text always ends in an exit
+        let code = unsafe {
+            let enter = (self.text.address as u64 + self.enter) as usize;
+            let enter: extern "C" fn(u64, u64) -> i64 = std::mem::transmute(enter);
+            enter(self.start, stack_top)
+        };
+        Ok((code, std::mem::take(&mut self.out)))
+    }
+}
@@ -1,4 +1,4 @@
-use std::io::Result;
+use std::io::{stdout, Result};
 
 mod tokenizer;
 use tokenizer::Lexer;
@@ -6,7 +6,48 @@ use tokenizer::Lexer;
 mod error;
 
 pub mod parser;
-use parser::parse;
+use parser::{parse, source_lines};
+
+mod syscall;
+
+mod runtime;
+
+pub mod bytecode;
+use bytecode::Compiler;
+
+pub mod vm;
+use vm::Vm;
+
+pub mod interpreter;
+use interpreter::Interpreter;
+
+mod assembler;
+
+mod ir;
+
+mod lower;
+
+mod loops;
+
+mod fold;
+
+mod inline;
+
+mod optimize;
+
+mod regalloc;
+
+mod tco;
+
+mod x86;
+
+mod peephole;
+
+mod dce;
+
+pub mod jit;
+use jit::Jit;
+use optimize::Options;
 
 pub type StatementNode = parser::StatementNode;
 pub type ExpressionNode = parser::ExpressionNode;
@@ -16,8 +57,33 @@ pub fn make_parsetree(src: String) -> Result<Vec<StatementNode>> {
     lines
         .into_iter()
         .filter(|line| !line.trim().is_empty())
-        .map(Lexer::lex)
         .enumerate()
-        .map(|(line_num, line)| parse(line?, line_num + 1))
+        .map(|(line_num, line)| parse(Lexer::lex_line(line, line_num + 1)?, line_num + 1))
         .collect()
 }
+
+// run a program without compiling it, print writes to stdout and the exit
+// code is handed back instead of ending the process
+pub fn interpret(src: String) -> Result<i64> {
+    let code: Vec<String> = src.lines().map(|line| line.to_string()).collect();
+    Interpreter::with_lines(make_parsetree(src)?, &source_lines(&code), stdout())?.run()
+}
+
+// the same as interpret but compiles to bytecode first and runs that on the vm
+pub fn run_bytecode(src: String) -> Result<i64> {
+    let code: Vec<String> = src.lines().map(|line| line.to_string()).collect();
+    let ops = Compiler::with_lines(&source_lines(&code)).compile(make_parsetree(src)?)?;
+    Vm::new(ops, stdout()).run()
+}
+
+// runs the compiled machine code in this process, giving back the exit code
+// and everything printed rather than writing it to stdout
+pub fn run_jit(src: String) -> Result<(i64, Vec<u8>)> {
+    let code: Vec<String> = src.lines().map(|line| line.to_string()).collect();
+    Jit::new(
+        make_parsetree(src)?,
+        &source_lines(&code),
+        Options::default(),
+    )?
+    .run()
+}
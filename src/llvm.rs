@@ -0,0 +1,881 @@
+use crate::error::new_error;
+use crate::parser::{ExpressionNode, StatementNode};
+use crate::runtime::{DIV_ZERO, IOBUF_SIZE, SENTINEL};
+
+use std::collections::HashMap;
+use std::io::Result;
+
+// helpers every module carries. arrays are i64 slots ending in the sentinel
+// and the file builtins go straight to the kernel like the nasm output
+const PRELUDE: &str = r#"@zb.fmt = private unnamed_addr constant [6 x i8] c"%lld\0A\00"
+@zb.iobuf = internal global [IOBUF x i8] zeroinitializer
+@zb.div_zero_fmt = private unnamed_addr constant [DIV_ZERO_LEN x i8] c"DIV_ZERO_TEXT"
+
+declare i32 @printf(i8*, ...)
+declare i32 @dprintf(i32, i8*, ...)
+declare i32 @fflush(i8*)
+declare void @exit(i32)
+
+define internal i64 @zb.syscall(i64 %n, i64 %a, i64 %b, i64 %c) {
+entry:
+  %r = call i64 asm sideeffect "syscall", "={rax},{rax},{rdi},{rsi},{rdx},~{rcx},~{r11},~{memory}"(i64 %n, i64 %a, i64 %b, i64 %c)
+  ret i64 %r
+}
+
+define internal void @zb.div_zero(i64 %line) {
+entry:
+  %flushed = call i32 @fflush(i8* null)
+  %fmt = getelementptr [DIV_ZERO_LEN x i8], [DIV_ZERO_LEN x i8]* @zb.div_zero_fmt, i64 0, i64 0
+  %r = call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %fmt, i64 %line)
+  call void @exit(i32 1)
+  unreachable
+}
+
+; sdiv and srem trap on MIN / -1, so -1 is done by hand like the asm does.
+; a zero divisor stops the program with the line it was on
+define internal i64 @zb.div(i64 %a, i64 %b, i64 %line) {
+entry:
+  %by_zero = icmp eq i64 %b, 0
+  br i1 %by_zero, label %trap, label %check
+trap:
+  call void @zb.div_zero(i64 %line)
+  unreachable
+check:
+  %minus = icmp eq i64 %b, -1
+  br i1 %minus, label %negate, label %divide
+negate:
+  %neg = sub i64 0, %a
+  ret i64 %neg
+divide:
+  %q = sdiv i64 %a, %b
+  ret i64 %q
+}
+
+define internal i64 @zb.rem(i64 %a, i64 %b, i64 %line) {
+entry:
+  %by_zero = icmp eq i64 %b, 0
+  br i1 %by_zero, label %trap, label %check
+trap:
+  call void @zb.div_zero(i64 %line)
+  unreachable
+check:
+  %minus = icmp eq i64 %b, -1
+  br i1 %minus, label %zero, label %divide
+zero:
+  ret i64 0
+divide:
+  %r = srem i64 %a, %b
+  ret i64 %r
+}
+
+define internal i64 @zb.print(i64 %value) {
+entry:
+  %fmt = getelementptr [6 x i8], [6 x i8]* @zb.fmt, i64 0, i64 0
+  %r = call i32 (i8*, ...) @printf(i8* %fmt, i64 %value)
+  ret i64 %value
+}
+
+define internal void @zb.fill(i64* %slots, i64 %count) {
+entry:
+  br label %loop
+loop:
+  %i = phi i64 [0, %entry], [%next, %body]
+  %more = icmp slt i64 %i, %count
+  br i1 %more, label %body, label %done
+body:
+  %slot = getelementptr i64, i64* %slots, i64 %i
+  store i64 SENTINEL, i64* %slot
+  %next = add i64 %i, 1
+  br label %loop
+done:
+  ret void
+}
+
+define internal i1 @zb.running(i64 %value, i64 %stop, i64 %step) {
+entry:
+  %up = icmp sgt i64 %step, 0
+  %below = icmp slt i64 %value, %stop
+  %a = and i1 %up, %below
+  %down = icmp slt i64 %step, 0
+  %above = icmp sgt i64 %value, %stop
+  %b = and i1 %down, %above
+  %r = or i1 %a, %b
+  ret i1 %r
+}
+
+define internal i64 @zb.range.len(i64 %start, i64 %stop, i64 %step) {
+entry:
+  br label %loop
+loop:
+  %value = phi i64 [%start, %entry], [%next, %body]
+  %n = phi i64 [0, %entry], [%count, %body]
+  %more = call i1 @zb.running(i64 %value, i64 %stop, i64 %step)
+  br i1 %more, label %body, label %done
+body:
+  %next = add i64 %value, %step
+  %count = add i64 %n, 1
+  br label %loop
+done:
+  ret i64 %n
+}
+
+define internal void @zb.range.fill(i64* %slots, i64 %start, i64 %step, i64 %len) {
+entry:
+  br label %loop
+loop:
+  %i = phi i64 [0, %entry], [%next, %body]
+  %value = phi i64 [%start, %entry], [%after, %body]
+  %slot = getelementptr i64, i64* %slots, i64 %i
+  %more = icmp slt i64 %i, %len
+  br i1 %more, label %body, label %done
+body:
+  store i64 %value, i64* %slot
+  %after = add i64 %value, %step
+  %next = add i64 %i, 1
+  br label %loop
+done:
+  store i64 SENTINEL, i64* %slot
+  ret void
+}
+
+define internal i64 @zb.clamp(i64 %count, i64 %len) {
+entry:
+  %small = icmp ult i64 %len, IOBUF
+  %max = select i1 %small, i64 %len, i64 IOBUF
+  %big = icmp ugt i64 %count, %max
+  %r = select i1 %big, i64 %max, i64 %count
+  ret i64 %r
+}
+
+define internal void @zb.path(i64* %slots) {
+entry:
+  br label %loop
+loop:
+  %i = phi i64 [0, %entry], [%next, %copy]
+  %slot = getelementptr i64, i64* %slots, i64 %i
+  %c = load i64, i64* %slot
+  %end = icmp eq i64 %c, SENTINEL
+  %full = icmp eq i64 %i, IOBUF_LAST
+  %stop = or i1 %end, %full
+  %byte = getelementptr [IOBUF x i8], [IOBUF x i8]* @zb.iobuf, i64 0, i64 %i
+  br i1 %stop, label %done, label %copy
+copy:
+  %char = trunc i64 %c to i8
+  store i8 %char, i8* %byte
+  %next = add i64 %i, 1
+  br label %loop
+done:
+  store i8 0, i8* %byte
+  ret void
+}
+
+define internal i64 @zb.read(i64 %fd, i64* %slots, i64 %len, i64 %count) {
+entry:
+  %n = call i64 @zb.clamp(i64 %count, i64 %len)
+  %buf = ptrtoint [IOBUF x i8]* @zb.iobuf to i64
+  %got = call i64 @zb.syscall(i64 0, i64 %fd, i64 %buf, i64 %n)
+  br label %loop
+loop:
+  %i = phi i64 [0, %entry], [%next, %copy]
+  %more = icmp slt i64 %i, %got
+  br i1 %more, label %copy, label %done
+copy:
+  %byte = getelementptr [IOBUF x i8], [IOBUF x i8]* @zb.iobuf, i64 0, i64 %i
+  %char = load i8, i8* %byte
+  %value = zext i8 %char to i64
+  %slot = getelementptr i64, i64* %slots, i64 %i
+  store i64 %value, i64* %slot
+  %next = add i64 %i, 1
+  br label %loop
+done:
+  ret i64 %got
+}
+
+define internal i64 @zb.write(i64 %fd, i64* %slots, i64 %len, i64 %count) {
+entry:
+  %n = call i64 @zb.clamp(i64 %count, i64 %len)
+  br label %loop
+loop:
+  %i = phi i64 [0, %entry], [%next, %copy]
+  %more = icmp ult i64 %i, %n
+  br i1 %more, label %copy, label %done
+copy:
+  %slot = getelementptr i64, i64* %slots, i64 %i
+  %value = load i64, i64* %slot
+  %char = trunc i64 %value to i8
+  %byte = getelementptr [IOBUF x i8], [IOBUF x i8]* @zb.iobuf, i64 0, i64 %i
+  store i8 %char, i8* %byte
+  %next = add i64 %i, 1
+  br label %loop
+done:
+  ; keep anything print has buffered ahead of the raw write
+  %flushed = call i32 @fflush(i8* null)
+  %buf = ptrtoint [IOBUF x i8]* @zb.iobuf to i64
+  %r = call i64 @zb.syscall(i64 1, i64 %fd, i64 %buf, i64 %n)
+  ret i64 %r
+}
+"#;
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Int(String),
+    // pointer to the first slot and the length without the sentinel
+    Array(String, String),
+}
+
+#[derive(Debug, Clone)]
+enum Var {
+    // address of the value
+    Int(String),
+    // addresses holding the slot pointer and the length
+    Array(String, String),
+}
+
+#[derive(Debug)]
+enum Block {
+    If(String),
+    While(String, String),
+    // head, end, loop var address and step address
+    Range(String, String, String, String),
+    // head and end
+    Array(String, String),
+}
+
+#[derive(Debug, Default)]
+struct Function {
+    header: String,
+    allocas: String,
+    body: String,
+    vars: HashMap<String, Var>,
+    blocks: Vec<Block>,
+    temps: usize,
+    terminated: bool,
+    returned: Option<String>,
+}
+
+// lowers the parse tree to textual llvm ir. every variable is an alloca so
+// clang and opt can promote them to registers, and zeblang functions become
+// llvm functions returning i64
+#[derive(Debug)]
+pub struct LlvmGenerator {
+    functions: Vec<Function>,
+    done: Vec<String>,
+    funcs: HashMap<String, usize>,
+    labels: usize,
+    // the line in the file of each statement, and of the one being generated
+    lines: Vec<usize>,
+    line: usize,
+}
+
+impl LlvmGenerator {
+    pub fn new() -> Self {
+        Self {
+            functions: vec![Function {
+                header: "define i32 @main() {".to_string(),
+                ..Default::default()
+            }],
+            done: Vec::new(),
+            funcs: HashMap::new(),
+            labels: 0,
+            lines: Vec::new(),
+            line: 0,
+        }
+    }
+
+    // without the lines, statements are counted from 1 like the parser does
+    pub fn with_lines(lines: &[usize]) -> Self {
+        Self {
+            lines: lines.to_vec(),
+            ..Self::new()
+        }
+    }
+
+    fn current(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn temp(&mut self) -> String {
+        let function = self.current();
+        function.temps += 1;
+        format!("%t{}", function.temps)
+    }
+
+    fn label(&mut self, kind: &str) -> String {
+        self.labels += 1;
+        format!("{}{}", kind, self.labels)
+    }
+
+    // code after exit or a jump still needs a block to live in
+    fn emit(&mut self, line: &str) {
+        if self.current().terminated {
+            let label = self.label("dead");
+            self.start_block(&label);
+        }
+        self.current().body += &format!("  {}\n", line);
+    }
+
+    fn terminate(&mut self, line: &str) {
+        self.emit(line);
+        self.current().terminated = true;
+    }
+
+    fn start_block(&mut self, label: &str) {
+        if !self.current().terminated {
+            self.current().body += &format!("  br label %{}\n", label);
+        }
+        let function = self.current();
+        function.body += &format!("{}:\n", label);
+        function.terminated = false;
+    }
+
+    // allocas all go in the entry block so loops dont grow the stack
+    fn alloca(&mut self, ty: &str) -> String {
+        let addr = self.temp();
+        self.current().allocas += &format!("  {} = alloca {}\n", addr, ty);
+        addr
+    }
+
+    fn assign_temp(&mut self, instruction: &str) -> String {
+        let temp = self.temp();
+        self.emit(&format!("{} = {}", temp, instruction));
+        temp
+    }
+
+    fn var(&mut self, name: &str) -> Result<Var> {
+        self.current()
+            .vars
+            .get(name)
+            .cloned()
+            .ok_or_else(|| new_error(&format!("variable {} not found in this scope", name)))
+    }
+
+    // address of the first slot of a variable, a number is its own
+    fn slot(&mut self, name: &str) -> Result<String> {
+        match self.var(name)? {
+            Var::Int(addr) => Ok(addr),
+            Var::Array(..) => self.element(name, "0"),
+        }
+    }
+
+    // only arrays can be indexed, a number has no slots past its own
+    fn element(&mut self, name: &str, index: &str) -> Result<String> {
+        let base = match self.var(name)? {
+            Var::Int(_) => return Err(new_error(&format!("{} is not an array", name))),
+            Var::Array(cell, _) => self.assign_temp(&format!("load i64*, i64** {}", cell)),
+        };
+        Ok(self.assign_temp(&format!("getelementptr i64, i64* {}, i64 {}", base, index)))
+    }
+
+    fn generate_int(&mut self, expr: ExpressionNode) -> Result<String> {
+        match self.generate_expr(expr)? {
+            Operand::Int(value) => Ok(value),
+            Operand::Array(..) => Err(new_error("arrays can only be assigned or looped over")),
+        }
+    }
+
+    fn generate_expr(&mut self, expr: ExpressionNode) -> Result<Operand> {
+        let value = match expr {
+            ExpressionNode::Value(value) => value
+                .parse::<i64>()
+                .map_err(|_| new_error(&format!("invalid integer {}", value)))?
+                .to_string(),
+            ExpressionNode::Var(name) => {
+                let slot = self.slot(&name)?;
+                self.assign_temp(&format!("load i64, i64* {}", slot))
+            }
+            ExpressionNode::Index(name, index) => {
+                let index = self.generate_int(*index)?;
+                let slot = self.element(&name, &index)?;
+                self.assign_temp(&format!("load i64, i64* {}", slot))
+            }
+            ExpressionNode::Infix(lh, op, rh) => {
+                let lh = self.generate_int(*lh)?;
+                let rh = self.generate_int(*rh)?;
+                self.generate_infix_op(&lh, &op, &rh)?
+            }
+            ExpressionNode::Prefix(op, expr) => match op.as_str() {
+                "~" => {
+                    let value = self.generate_int(*expr)?;
+                    self.assign_temp(&format!("xor i64 {}, -1", value))
+                }
+                _ => return Err(new_error(&format!("unknown operator {}", op))),
+            },
+            ExpressionNode::Callable(name, args) => {
+                return self.generate_call(&name, args.into_iter().map(|arg| *arg).collect())
+            }
+            ExpressionNode::Array(exprs) => {
+                let len = exprs.len();
+                let slots = self.generate_storage(len)?;
+                for (i, expr) in exprs.into_iter().enumerate() {
+                    let value = self.generate_int(*expr)?;
+                    let slot =
+                        self.assign_temp(&format!("getelementptr i64, i64* {}, i64 {}", slots, i));
+                    self.emit(&format!("store i64 {}, i64* {}", value, slot));
+                }
+                let slot =
+                    self.assign_temp(&format!("getelementptr i64, i64* {}, i64 {}", slots, len));
+                self.emit(&format!("store i64 {}, i64* {}", SENTINEL, slot));
+                return Ok(Operand::Array(slots, len.to_string()));
+            }
+            ExpressionNode::PreAllocArray(size) => {
+                let slots = self.generate_storage(size)?;
+                self.emit(&format!(
+                    "call void @zb.fill(i64* {}, i64 {})",
+                    slots,
+                    size + 1
+                ));
+                return Ok(Operand::Array(slots, size.to_string()));
+            }
+        };
+        Ok(Operand::Int(value))
+    }
+
+    fn generate_storage(&mut self, len: usize) -> Result<String> {
+        let array = self.alloca(&format!("[{} x i64]", len + 1));
+        Ok(self.assign_temp(&format!(
+            "getelementptr [{} x i64], [{} x i64]* {}, i64 0, i64 0",
+            len + 1,
+            len + 1,
+            array
+        )))
+    }
+
+    fn generate_infix_op(&mut self, lh: &str, op: &str, rh: &str) -> Result<String> {
+        let instruction = match op {
+            "+" => "add",
+            "-" => "sub",
+            "*" => "mul",
+            "/" | "%" => {
+                let helper = if op == "/" { "div" } else { "rem" };
+                let line = self.lines.get(self.line).copied();
+                let line = line.unwrap_or(self.line + 1);
+                return Ok(self.assign_temp(&format!(
+                    "call i64 @zb.{}(i64 {}, i64 {}, i64 {})",
+                    helper, lh, rh, line
+                )));
+            }
+            "&" => "and",
+            "|" => "or",
+            "^" => "xor",
+            "==" | "!=" => {
+                let cond = if op == "==" { "eq" } else { "ne" };
+                let flag = self.assign_temp(&format!("icmp {} i64 {}, {}", cond, lh, rh));
+                return Ok(self.assign_temp(&format!("zext i1 {} to i64", flag)));
+            }
+            // shift counts wrap at 64 like the cl register does
+            "<<" | ">>" => {
+                let count = self.assign_temp(&format!("and i64 {}, 63", rh));
+                let instruction = if op == "<<" { "shl" } else { "ashr" };
+                return Ok(self.assign_temp(&format!("{} i64 {}, {}", instruction, lh, count)));
+            }
+            _ => return Err(new_error(&format!("unknown operator {}", op))),
+        };
+        Ok(self.assign_temp(&format!("{} i64 {}, {}", instruction, lh, rh)))
+    }
+
+    fn generate_call(&mut self, name: &str, args: Vec<ExpressionNode>) -> Result<Operand> {
+        let value = match name {
+            "open" | "read" | "write" | "close" | "unlink" => self.generate_io(name, args)?,
+            "range" => {
+                let (start, stop, step) = self.generate_range_args(args)?;
+                let len = self.assign_temp(&format!(
+                    "call i64 @zb.range.len(i64 {}, i64 {}, i64 {})",
+                    start, stop, step
+                ));
+                let size = self.assign_temp(&format!("add i64 {}, 1", len));
+                let slots = self.assign_temp(&format!("alloca i64, i64 {}", size));
+                self.emit(&format!(
+                    "call void @zb.range.fill(i64* {}, i64 {}, i64 {}, i64 {})",
+                    slots, start, step, len
+                ));
+                return Ok(Operand::Array(slots, len));
+            }
+            "print" => {
+                let mut value = None;
+                for arg in args.into_iter() {
+                    value = Some(self.generate_int(arg)?);
+                }
+                let value = value.ok_or_else(|| new_error("print expects an argument"))?;
+                self.assign_temp(&format!("call i64 @zb.print(i64 {})", value))
+            }
+            name => {
+                let arity = *self
+                    .funcs
+                    .get(name)
+                    .ok_or_else(|| new_error(&format!("function {} undefined", name)))?;
+                if arity != args.len() {
+                    return Err(new_error(&format!(
+                        "{} expects {} arguments, got {}",
+                        name,
+                        arity,
+                        args.len()
+                    )));
+                }
+                let values = args
+                    .into_iter()
+                    .map(|arg| Ok(format!("i64 {}", self.generate_int(arg)?)))
+                    .collect::<Result<Vec<String>>>()?;
+                self.assign_temp(&format!("call i64 @zbf.{}({})", name, values.join(", ")))
+            }
+        };
+        Ok(Operand::Int(value))
+    }
+
+    fn generate_range_args(
+        &mut self,
+        args: Vec<ExpressionNode>,
+    ) -> Result<(String, String, String)> {
+        let args = args
+            .into_iter()
+            .map(|arg| self.generate_int(arg))
+            .collect::<Result<Vec<String>>>()?;
+        let one = || "1".to_string();
+        match &args[..] {
+            [stop] => Ok(("0".to_string(), stop.to_string(), one())),
+            [start, stop] => Ok((start.to_string(), stop.to_string(), one())),
+            [start, stop, step] => Ok((start.to_string(), stop.to_string(), step.to_string())),
+            _ => Err(new_error("range expects 1 to 3 arguments")),
+        }
+    }
+
+    fn generate_io(&mut self, name: &str, mut args: Vec<ExpressionNode>) -> Result<String> {
+        let arity = match name {
+            "open" | "read" | "write" => 3,
+            _ => 1,
+        };
+        if args.len() != arity {
+            return Err(new_error(&format!(
+                "{} expects {} arguments, got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        let buffer = format!("ptrtoint ([{} x i8]* @zb.iobuf to i64)", IOBUF_SIZE);
+        let syscall = match name {
+            "open" => {
+                let (slots, _) = self.array_arg(name, &args[0])?;
+                let flags = self.generate_int(args.remove(1))?;
+                let mode = self.generate_int(args.remove(1))?;
+                self.emit(&format!("call void @zb.path(i64* {})", slots));
+                format!("2, i64 {}, i64 {}, i64 {}", buffer, flags, mode)
+            }
+            "read" | "write" => {
+                let (slots, len) = self.array_arg(name, &args[1])?;
+                let fd = self.generate_int(args.remove(0))?;
+                let count = self.generate_int(args.remove(1))?;
+                return Ok(self.assign_temp(&format!(
+                    "call i64 @zb.{}(i64 {}, i64* {}, i64 {}, i64 {})",
+                    name, fd, slots, len, count
+                )));
+            }
+            "close" => format!(
+                "3, i64 {}, i64 0, i64 0",
+                self.generate_int(args.remove(0))?
+            ),
+            "unlink" => {
+                let (slots, _) = self.array_arg(name, &args[0])?;
+                self.emit(&format!("call void @zb.path(i64* {})", slots));
+                format!("87, i64 {}, i64 0, i64 0", buffer)
+            }
+            _ => unreachable!(),
+        };
+        Ok(self.assign_temp(&format!("call i64 @zb.syscall(i64 {})", syscall)))
+    }
+
+    fn array_arg(&mut self, name: &str, expr: &ExpressionNode) -> Result<(String, String)> {
+        let varname = match expr {
+            ExpressionNode::Var(varname) => varname,
+            _ => return Err(new_error(&format!("{} expects an array variable", name))),
+        };
+        match self.var(varname)? {
+            Var::Array(cell, len) => Ok((
+                self.assign_temp(&format!("load i64*, i64** {}", cell)),
+                self.assign_temp(&format!("load i64, i64* {}", len)),
+            )),
+            Var::Int(_) => Err(new_error(&format!(
+                "array {} not found in this scope",
+                varname
+            ))),
+        }
+    }
+
+    fn generate_assign(&mut self, name: String, node: ExpressionNode) -> Result<()> {
+        let operand = self.generate_expr(node)?;
+        self.assign(name, operand)
+    }
+
+    fn assign(&mut self, name: String, operand: Operand) -> Result<()> {
+        match (self.current().vars.get(&name).cloned(), operand) {
+            (None, Operand::Int(value)) => {
+                let addr = self.alloca("i64");
+                self.emit(&format!("store i64 {}, i64* {}", value, addr));
+                self.current().vars.insert(name, Var::Int(addr));
+            }
+            (None, Operand::Array(slots, len)) => {
+                let cell = self.alloca("i64*");
+                let len_cell = self.alloca("i64");
+                self.emit(&format!("store i64* {}, i64** {}", slots, cell));
+                self.emit(&format!("store i64 {}, i64* {}", len, len_cell));
+                self.current().vars.insert(name, Var::Array(cell, len_cell));
+            }
+            (Some(_), Operand::Int(value)) => {
+                let slot = self.slot(&name)?;
+                self.emit(&format!("store i64 {}, i64* {}", value, slot));
+            }
+            (Some(Var::Array(cell, len_cell)), Operand::Array(slots, len)) => {
+                self.emit(&format!("store i64* {}, i64** {}", slots, cell));
+                self.emit(&format!("store i64 {}, i64* {}", len, len_cell));
+            }
+            (Some(Var::Int(_)), Operand::Array(..)) => {
+                return Err(new_error(&format!(
+                    "{} holds a number and cant be reassigned to an array",
+                    name
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_assign_index(
+        &mut self,
+        name: String,
+        index_expr: ExpressionNode,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        let value = self.generate_int(assign_expr)?;
+        let index = self.generate_int(index_expr)?;
+        let slot = self.element(&name, &index)?;
+        self.emit(&format!("store i64 {}, i64* {}", value, slot));
+        Ok(())
+    }
+
+    fn generate_assign_op(
+        &mut self,
+        name: String,
+        index_expr: Option<ExpressionNode>,
+        op: String,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        let value = self.generate_int(assign_expr)?;
+        let slot = match index_expr {
+            Some(expr) => {
+                let index = self.generate_int(expr)?;
+                self.element(&name, &index)?
+            }
+            None => self.slot(&name)?,
+        };
+        let current = self.assign_temp(&format!("load i64, i64* {}", slot));
+        let updated = self.generate_infix_op(&current, &op, &value)?;
+        self.emit(&format!("store i64 {}, i64* {}", updated, slot));
+        Ok(())
+    }
+
+    fn generate_exit(&mut self, node: ExpressionNode) -> Result<()> {
+        let value = self.generate_int(node)?;
+        let code = self.assign_temp(&format!("trunc i64 {} to i32", value));
+        self.emit(&format!("call void @exit(i32 {})", code));
+        self.terminate("unreachable");
+        Ok(())
+    }
+
+    fn generate_branch(&mut self, node: ExpressionNode, body: &str, end: &str) -> Result<()> {
+        let value = self.generate_int(node)?;
+        let cond = self.assign_temp(&format!("icmp ne i64 {}, 0", value));
+        self.terminate(&format!("br i1 {}, label %{}, label %{}", cond, body, end));
+        self.start_block(body);
+        Ok(())
+    }
+
+    fn generate_if(&mut self, node: ExpressionNode) -> Result<()> {
+        let (body, end) = (self.label("then"), self.label("endif"));
+        self.generate_branch(node, &body, &end)?;
+        self.current().blocks.push(Block::If(end));
+        Ok(())
+    }
+
+    fn generate_while(&mut self, node: ExpressionNode) -> Result<()> {
+        let (head, body, end) = (self.label("wexp"), self.label("loop"), self.label("exit"));
+        self.start_block(&head);
+        self.generate_branch(node, &body, &end)?;
+        self.current().blocks.push(Block::While(head, end));
+        Ok(())
+    }
+
+    fn generate_for(&mut self, varname: String, node: ExpressionNode) -> Result<()> {
+        let (head, body, end) = (self.label("for"), self.label("body"), self.label("endfor"));
+        let block = match node {
+            ExpressionNode::Callable(name, args) if name == "range" => {
+                let args = args.into_iter().map(|arg| *arg).collect();
+                let (start, stop, step) = self.generate_range_args(args)?;
+                let stop_addr = self.alloca("i64");
+                let step_addr = self.alloca("i64");
+                self.emit(&format!("store i64 {}, i64* {}", stop, stop_addr));
+                self.emit(&format!("store i64 {}, i64* {}", step, step_addr));
+                self.assign(varname.clone(), Operand::Int(start))?;
+                let var = self.slot(&varname)?;
+                self.start_block(&head);
+                let value = self.assign_temp(&format!("load i64, i64* {}", var));
+                let stop = self.assign_temp(&format!("load i64, i64* {}", stop_addr));
+                let step = self.assign_temp(&format!("load i64, i64* {}", step_addr));
+                let running = self.assign_temp(&format!(
+                    "call i1 @zb.running(i64 {}, i64 {}, i64 {})",
+                    value, stop, step
+                ));
+                self.terminate(&format!(
+                    "br i1 {}, label %{}, label %{}",
+                    running, body, end
+                ));
+                Block::Range(head, end, var, step_addr)
+            }
+            node => {
+                let slots = match self.generate_expr(node)? {
+                    Operand::Array(slots, _) => slots,
+                    Operand::Int(value) => {
+                        let slots = self.generate_storage(1)?;
+                        let sentinel =
+                            self.assign_temp(&format!("getelementptr i64, i64* {}, i64 1", slots));
+                        self.emit(&format!("store i64 {}, i64* {}", value, slots));
+                        self.emit(&format!("store i64 {}, i64* {}", SENTINEL, sentinel));
+                        slots
+                    }
+                };
+                let position = self.alloca("i64*");
+                self.emit(&format!("store i64* {}, i64** {}", slots, position));
+                self.assign(varname.clone(), Operand::Int("0".to_string()))?;
+                let var = self.slot(&varname)?;
+                self.start_block(&head);
+                let slot = self.assign_temp(&format!("load i64*, i64** {}", position));
+                let value = self.assign_temp(&format!("load i64, i64* {}", slot));
+                self.emit(&format!("store i64 {}, i64* {}", value, var));
+                let next = self.assign_temp(&format!("getelementptr i64, i64* {}, i64 1", slot));
+                self.emit(&format!("store i64* {}, i64** {}", next, position));
+                let done = self.assign_temp(&format!("icmp eq i64 {}, {}", value, SENTINEL));
+                self.terminate(&format!("br i1 {}, label %{}, label %{}", done, end, body));
+                Block::Array(head, end)
+            }
+        };
+        self.start_block(&body);
+        self.current().blocks.push(block);
+        Ok(())
+    }
+
+    fn generate_end(&mut self, closer: &str) -> Result<()> {
+        let block = self.current().blocks.pop();
+        let (head, end) = match (block, closer) {
+            (Some(Block::If(end)), "if") => (None, end),
+            (Some(Block::While(head, end)), "while") => (Some(head), end),
+            (Some(Block::Array(head, end)), "for") => (Some(head), end),
+            (Some(Block::Range(head, end, var, step)), "for") => {
+                let value = self.assign_temp(&format!("load i64, i64* {}", var));
+                let step = self.assign_temp(&format!("load i64, i64* {}", step));
+                let next = self.assign_temp(&format!("add i64 {}, {}", value, step));
+                self.emit(&format!("store i64 {}, i64* {}", next, var));
+                (Some(head), end)
+            }
+            _ => return Err(new_error(&format!("no {} to close", closer))),
+        };
+        if let Some(head) = head {
+            self.terminate(&format!("br label %{}", head));
+        }
+        self.start_block(&end);
+        Ok(())
+    }
+
+    fn generate_func(&mut self, name: String, args: Vec<String>) -> Result<()> {
+        if self.functions.len() > 1 {
+            return Err(new_error("functions cant be defined inside functions"));
+        }
+        let params: Vec<String> = args.iter().map(|arg| format!("i64 %arg.{}", arg)).collect();
+        self.funcs.insert(name.clone(), args.len());
+        self.functions.push(Function {
+            header: format!(
+                "define internal i64 @zbf.{}({}) {{",
+                name,
+                params.join(", ")
+            ),
+            ..Default::default()
+        });
+        for arg in args.into_iter() {
+            let addr = self.alloca("i64");
+            self.emit(&format!("store i64 %arg.{}, i64* {}", arg, addr));
+            self.current().vars.insert(arg, Var::Int(addr));
+        }
+        let returned = self.alloca("i64");
+        self.emit(&format!("store i64 0, i64* {}", returned));
+        self.current().returned = Some(returned);
+        Ok(())
+    }
+
+    fn generate_end_func(&mut self) -> Result<()> {
+        let returned = match self.current().returned.clone() {
+            Some(returned) if self.current().blocks.is_empty() => returned,
+            _ => return Err(new_error("no foo to close")),
+        };
+        let value = self.assign_temp(&format!("load i64, i64* {}", returned));
+        self.terminate(&format!("ret i64 {}", value));
+        let function = self.functions.pop().unwrap();
+        self.done.push(Self::finish(function));
+        Ok(())
+    }
+
+    fn generate_return(&mut self, node: ExpressionNode) -> Result<()> {
+        let value = self.generate_int(node)?;
+        if let Some(returned) = self.current().returned.clone() {
+            self.emit(&format!("store i64 {}, i64* {}", value, returned));
+        }
+        Ok(())
+    }
+
+    fn finish(function: Function) -> String {
+        format!(
+            "{}\nentry:\n{}{}}}\n",
+            function.header, function.allocas, function.body
+        )
+    }
+
+    pub fn generate(&mut self, program: Vec<StatementNode>) -> Result<String> {
+        for (i, line) in program.into_iter().enumerate() {
+            self.line = i;
+            match line {
+                StatementNode::Exit(expr_node) => self.generate_exit(expr_node)?,
+                StatementNode::Assign(name, expr_node) => self.generate_assign(name, expr_node)?,
+                StatementNode::For(var, expr_node) => self.generate_for(var, expr_node)?,
+                StatementNode::EndFor => self.generate_end("for")?,
+                StatementNode::While(expr_node) => self.generate_while(expr_node)?,
+                StatementNode::EndWhile => self.generate_end("while")?,
+                StatementNode::If(expr_node) => self.generate_if(expr_node)?,
+                StatementNode::EndIf => self.generate_end("if")?,
+                StatementNode::AssignIndex(name, index_expr, assign_expr) => {
+                    self.generate_assign_index(name, index_expr, assign_expr)?
+                }
+                StatementNode::AssignOp(name, op, expr_node) => {
+                    self.generate_assign_op(name, None, op, expr_node)?
+                }
+                StatementNode::AssignIndexOp(name, index_expr, op, assign_expr) => {
+                    self.generate_assign_op(name, Some(index_expr), op, assign_expr)?
+                }
+                StatementNode::EndFunc => self.generate_end_func()?,
+                StatementNode::Func(name, args, _) => self.generate_func(name, args)?,
+                StatementNode::Return(expr) => self.generate_return(expr)?,
+            };
+        }
+        if self.functions.len() > 1 || !self.current().blocks.is_empty() {
+            return Err(new_error("block is never closed"));
+        }
+        self.terminate("ret i32 0");
+        let main = Self::finish(self.functions.pop().unwrap());
+        let prelude = PRELUDE
+            .replace("IOBUF_LAST", &(IOBUF_SIZE - 1).to_string())
+            .replace("IOBUF", &IOBUF_SIZE.to_string())
+            .replace("SENTINEL", &SENTINEL.to_string());
+        let div_zero = format!("{}%lld{}\0", DIV_ZERO.0, DIV_ZERO.1);
+        let prelude = prelude
+            .replace("DIV_ZERO_LEN", &div_zero.len().to_string())
+            .replace(
+                "DIV_ZERO_TEXT",
+                &div_zero.replace('\n', "\\0A").replace('\0', "\\00"),
+            );
+        Ok(format!("{}\n{}\n{}", prelude, self.done.join("\n"), main))
+    }
+}
@@ -1,6 +1,8 @@
 use serde_json;
-use std::fs::{read_to_string, File};
+use std::fs::{read_to_string, set_permissions, File, Permissions};
 use std::io::{Result, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 
 use crate::parser::StatementNode;
 
@@ -11,12 +13,19 @@ pub fn read_file(filename: &str) -> Vec<String> {
     }
 }
 
+// the source's name without .zb, where zeblang build puts the executable
+pub fn output_name(filename: &str) -> String {
+    filename.strip_suffix(".zb").unwrap_or(filename).to_string()
+}
+
+// the source's path with the extension swapped, or taken off for "". only
+// the file name's extension goes, so ./prog.zb keeps its directory
+fn next_to(filename: &str, extension: &str) -> PathBuf {
+    Path::new(filename).with_extension(extension.trim_start_matches('.'))
+}
+
 pub fn write_json(filename: &str, program: Result<Vec<StatementNode>>) -> Result<()> {
-    let mut file = File::create(format!(
-        "{}{}",
-        filename.split(".").next().unwrap(),
-        ".json"
-    ))?;
+    let mut file = File::create(next_to(filename, ".json"))?;
     let json = match program {
         Ok(program) => serde_json::to_string_pretty(&program)?,
         Err(e) => serde_json::to_string_pretty(&e.to_string())?,
@@ -26,7 +35,21 @@ pub fn write_json(filename: &str, program: Result<Vec<StatementNode>>) -> Result
 }
 
 pub fn write_assembly_file(filename: &str, body: String) -> Result<()> {
-    let mut file = File::create(format!("{}{}", filename.split(".").next().unwrap(), ".asm"))?;
+    let mut file = File::create(next_to(filename, ".asm"))?;
     file.write_all(body.as_bytes())?;
     Ok(())
 }
+
+// writes body next to the source with the extension swapped, eg ".ll"
+pub fn write_output_file(filename: &str, extension: &str, body: impl AsRef<[u8]>) -> Result<()> {
+    let mut file = File::create(next_to(filename, extension))?;
+    file.write_all(body.as_ref())?;
+    Ok(())
+}
+
+// writes an executable next to the source, named after it with no extension
+pub fn write_executable(filename: &str, body: Vec<u8>) -> Result<()> {
+    let path = next_to(filename, "");
+    File::create(&path)?.write_all(&body)?;
+    set_permissions(path, Permissions::from_mode(0o755))
+}
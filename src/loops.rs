@@ -0,0 +1,274 @@
+use crate::bytecode::BinOp;
+use crate::ir::{Block, BlockId, Function, Inst, Operand, Temp, Terminator, Ty};
+use crate::regalloc::{liveness, temps};
+
+use std::collections::HashMap;
+
+// a natural loop, the blocks that can get back to the header without
+// going through it
+struct Loop {
+    header: BlockId,
+    body: Vec<bool>,
+}
+
+fn predecessors(function: &Function) -> Vec<Vec<BlockId>> {
+    let mut preds = vec![Vec::new(); function.blocks.len()];
+    for (id, block) in function.blocks.iter().enumerate() {
+        for target in block.term.successors() {
+            preds[target].push(id);
+        }
+    }
+    preds
+}
+
+// dominators[b][d] when every way into b goes through d
+fn dominators(function: &Function, preds: &[Vec<BlockId>]) -> Vec<Vec<bool>> {
+    let count = function.blocks.len();
+    let mut dominators = vec![vec![true; count]; count];
+    dominators[0] = vec![false; count];
+    dominators[0][0] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in 1..count {
+            let mut doms = vec![true; count];
+            for pred in preds[id].iter() {
+                doms.iter_mut()
+                    .zip(dominators[*pred].iter())
+                    .for_each(|(dom, pred)| *dom &= *pred);
+            }
+            doms[id] = true;
+            if doms != dominators[id] {
+                dominators[id] = doms;
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+// every loop, innermost first. back edges to the same header share a loop
+fn loops(function: &Function) -> Vec<Loop> {
+    let preds = predecessors(function);
+    let dominators = dominators(function, &preds);
+    let mut loops: Vec<Loop> = Vec::new();
+    for (tail, block) in function.blocks.iter().enumerate() {
+        for header in block.term.successors() {
+            if !dominators[tail][header] {
+                continue;
+            }
+            let index = match loops.iter().position(|l| l.header == header) {
+                Some(index) => index,
+                None => {
+                    let mut body = vec![false; function.blocks.len()];
+                    body[header] = true;
+                    loops.push(Loop { header, body });
+                    loops.len() - 1
+                }
+            };
+            let body = &mut loops[index].body;
+            let mut pending = vec![tail];
+            while let Some(id) = pending.pop() {
+                if !body[id] {
+                    body[id] = true;
+                    pending.extend(preds[id].iter().copied());
+                }
+            }
+        }
+    }
+    loops.sort_by_key(|l| l.body.iter().filter(|inside| **inside).count());
+    loops
+}
+
+// a block that runs once before the loop and jumps straight into it, for
+// hoisted code to go in. it is added at the end so no block moves
+fn preheader(function: &mut Function, header: BlockId, body: &[bool]) -> BlockId {
+    let preheader = function.blocks.len();
+    function.blocks.push(Block {
+        insts: Vec::new(),
+        term: Terminator::Jump(header),
+    });
+    for (block, inside) in function.blocks.iter_mut().zip(body) {
+        if *inside {
+            continue;
+        }
+        for target in block.term.successors_mut() {
+            if *target == header {
+                *target = preheader;
+            }
+        }
+    }
+    preheader
+}
+
+fn defs(function: &Function, body: &[bool]) -> HashMap<Temp, usize> {
+    let mut defs = HashMap::new();
+    let insts = function
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|(id, _)| body[*id])
+        .flat_map(|(_, block)| block.insts.iter());
+    for dest in insts.filter_map(Inst::dest) {
+        *defs.entry(dest).or_insert(0) += 1;
+    }
+    defs
+}
+
+// moves instructions whose operands don't change in the loop out to the
+// preheader. only ones that can't fault or have effects are moved, since
+// the loop might not have run them at all, and only when their dest isn't
+// read before it's set or after the loop
+fn hoist_invariants(function: &mut Function, l: &Loop, preheader: BlockId) {
+    let live = liveness(function);
+    let mut defs = defs(function, &l.body);
+    let exits: Vec<BlockId> = (0..l.body.len())
+        .filter(|id| l.body[*id])
+        .flat_map(|id| function.blocks[id].term.successors())
+        .filter(|target| !l.body[*target])
+        .collect();
+    let escapes = |temp: Temp| {
+        live[l.header].0.contains(&temp) || exits.iter().any(|exit| live[*exit].0.contains(&temp))
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in (0..l.body.len()).filter(|id| l.body[*id]) {
+            let mut at = 0;
+            while at < function.blocks[id].insts.len() {
+                let inst = &function.blocks[id].insts[at];
+                let dest = match inst {
+                    Inst::Binary(_, BinOp::Div | BinOp::Mod, ..) => None,
+                    Inst::Copy(dest, _) | Inst::Binary(dest, ..) | Inst::Not(dest, _) => {
+                        Some(*dest)
+                    }
+                    _ => None,
+                };
+                let invariant = temps(inst.operands()).all(|temp| !defs.contains_key(&temp));
+                let movable = dest.filter(|dest| defs.get(dest) == Some(&1) && !escapes(*dest));
+                if let Some(dest) = movable.filter(|_| invariant) {
+                    let inst = function.blocks[id].insts.remove(at);
+                    function.blocks[preheader].insts.push(inst);
+                    defs.remove(&dest);
+                    changed = true;
+                } else {
+                    at += 1;
+                }
+            }
+        }
+    }
+}
+
+// the temps changed only by adding a constant once in the loop, with the
+// block and place they're changed and the step
+fn induction_variables(function: &Function, l: &Loop) -> HashMap<Temp, (BlockId, usize, i64)> {
+    let defs = defs(function, &l.body);
+    let mut variables = HashMap::new();
+    for id in (0..l.body.len()).filter(|id| l.body[*id]) {
+        for (at, inst) in function.blocks[id].insts.iter().enumerate() {
+            let (dest, step) = match *inst {
+                Inst::Binary(dest, BinOp::Add, Operand::Temp(var), Operand::Const(step))
+                | Inst::Binary(dest, BinOp::Add, Operand::Const(step), Operand::Temp(var))
+                    if dest == var =>
+                {
+                    (dest, step)
+                }
+                Inst::Binary(dest, BinOp::Sub, Operand::Temp(var), Operand::Const(step))
+                    if dest == var =>
+                {
+                    (dest, step.wrapping_neg())
+                }
+                _ => continue,
+            };
+            if defs.get(&dest) == Some(&1) {
+                variables.insert(dest, (id, at, step));
+            }
+        }
+    }
+    variables
+}
+
+// multiplying an induction variable by a constant becomes a running total
+// that goes up with it. the total starts as the product in the preheader
+// and is stepped straight after the variable, so it is always equal to it
+fn reduce_strength(function: &mut Function, l: &Loop, preheader: BlockId) {
+    let variables = induction_variables(function, l);
+    // each product's total, and where to step it
+    let mut totals: HashMap<(Temp, i64), Temp> = HashMap::new();
+    let mut steps: Vec<(BlockId, usize, Inst)> = Vec::new();
+    for id in (0..l.body.len()).filter(|id| l.body[*id]) {
+        for at in 0..function.blocks[id].insts.len() {
+            let (dest, var, factor) = match function.blocks[id].insts[at] {
+                Inst::Binary(dest, BinOp::Mul, Operand::Temp(var), Operand::Const(factor))
+                | Inst::Binary(dest, BinOp::Mul, Operand::Const(factor), Operand::Temp(var)) => {
+                    (dest, var, factor)
+                }
+                Inst::Binary(dest, BinOp::Shl, Operand::Temp(var), Operand::Const(shift))
+                    if (0..63).contains(&shift) =>
+                {
+                    (dest, var, 1 << shift)
+                }
+                _ => continue,
+            };
+            let Some((block, def, step)) = variables.get(&var).copied() else {
+                continue;
+            };
+            let total = *totals.entry((var, factor)).or_insert_with(|| {
+                let total = function.temp(Ty::Int);
+                function.blocks[preheader].insts.push(Inst::Binary(
+                    total,
+                    BinOp::Mul,
+                    Operand::Temp(var),
+                    Operand::Const(factor),
+                ));
+                let stepped = Operand::Const(step.wrapping_mul(factor));
+                steps.push((
+                    block,
+                    def,
+                    Inst::Binary(total, BinOp::Add, Operand::Temp(total), stepped),
+                ));
+                total
+            });
+            function.blocks[id].insts[at] = Inst::Copy(dest, Operand::Temp(total));
+        }
+    }
+    // from the back so the places of the earlier ones stay right
+    steps.sort_by_key(|(block, def, _)| (*block, *def));
+    for (block, def, inst) in steps.into_iter().rev() {
+        function.blocks[block].insts.insert(def + 1, inst);
+    }
+}
+
+// loop invariant code motion then strength reduction on each loop, inner
+// loops first so what they hoist can carry on out of the outer ones
+pub fn optimize_loops(function: &mut Function) {
+    // the entry block can't have a preheader before it, so a loop back to
+    // the top of the function gets a new entry that jumps in
+    if !predecessors(function)[0].is_empty() {
+        for block in function.blocks.iter_mut() {
+            block
+                .term
+                .successors_mut()
+                .into_iter()
+                .for_each(|target| *target += 1);
+        }
+        function.blocks.insert(
+            0,
+            Block {
+                insts: Vec::new(),
+                term: Terminator::Jump(1),
+            },
+        );
+    }
+    let mut loops = loops(function);
+    for i in 0..loops.len() {
+        let header = loops[i].header;
+        let preheader = preheader(function, header, &loops[i].body);
+        // the preheader is part of every loop around this one
+        for l in loops.iter_mut() {
+            l.body.push(l.body[header] && l.header != header);
+        }
+        hoist_invariants(function, &loops[i], preheader);
+        reduce_strength(function, &loops[i], preheader);
+    }
+}
@@ -0,0 +1,691 @@
+use crate::bytecode::BinOp;
+use crate::error::new_error;
+use crate::ir::{
+    Block, BlockId, Builtin, Cond, Function, Inst, Operand, Program, Temp, Terminator, Ty,
+};
+use crate::parser::{ExpressionNode, Inline, StatementNode};
+use crate::runtime::SENTINEL;
+
+use std::collections::HashMap;
+use std::io::Result;
+
+#[derive(Debug, Clone, Copy)]
+enum Var {
+    Int(Temp),
+    // the first slot and the length without the sentinel
+    Array(Temp, Temp),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Int(Operand),
+    Array(Temp, Operand),
+}
+
+// a block statement waiting for its closer
+#[derive(Debug)]
+enum Open {
+    If(BlockId),
+    // head and end
+    While(BlockId, BlockId),
+    // head, end, loop var and step
+    Range(BlockId, BlockId, String, Temp),
+    Array(BlockId, BlockId),
+}
+
+#[derive(Debug)]
+struct Builder {
+    function: Function,
+    // blocks get their terminator once they are finished
+    blocks: Vec<(Vec<Inst>, Option<Terminator>)>,
+    current: BlockId,
+    vars: HashMap<String, Var>,
+    open: Vec<Open>,
+    // what a function hands back, return only sets it
+    returned: Option<Temp>,
+}
+
+impl Builder {
+    fn new(name: &str) -> Self {
+        Self {
+            function: Function::new(name),
+            blocks: vec![(Vec::new(), None)],
+            current: 0,
+            vars: HashMap::new(),
+            open: Vec::new(),
+            returned: None,
+        }
+    }
+
+    fn temp(&mut self, ty: Ty) -> Temp {
+        self.function.temp(ty)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        self.blocks.len() - 1
+    }
+
+    // carries on in block, falling through to it if the current one is
+    // still open
+    fn switch_to(&mut self, block: BlockId) {
+        if self.blocks[self.current].1.is_none() {
+            self.terminate(Terminator::Jump(block));
+        }
+        self.current = block;
+    }
+
+    // code after exit still needs a block to live in
+    fn emit(&mut self, inst: Inst) {
+        if self.blocks[self.current].1.is_some() {
+            self.current = self.new_block();
+        }
+        self.blocks[self.current].0.push(inst);
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        if self.blocks[self.current].1.is_some() {
+            self.current = self.new_block();
+        }
+        self.blocks[self.current].1 = Some(term);
+    }
+
+    fn assign_temp(&mut self, ty: Ty, make: impl FnOnce(Temp) -> Inst) -> Temp {
+        let temp = self.temp(ty);
+        self.emit(make(temp));
+        temp
+    }
+
+    fn finish(mut self) -> Function {
+        self.function.blocks = self
+            .blocks
+            .into_iter()
+            .map(|(insts, term)| Block {
+                insts,
+                term: term.expect("every block is terminated before finishing"),
+            })
+            .collect();
+        self.function
+    }
+}
+
+// turns the parse tree into the ir. variables become temps, arrays get
+// stack slots ending in the sentinel and zeblang functions become ir
+// functions, so recursion works
+#[derive(Debug)]
+pub struct Lowerer {
+    builders: Vec<Builder>,
+    functions: Vec<Function>,
+    funcs: HashMap<String, usize>,
+    // the line in the file of each statement, and of the one being lowered
+    lines: Vec<usize>,
+    line: usize,
+}
+
+impl Lowerer {
+    pub fn new() -> Self {
+        Self {
+            builders: vec![Builder::new("_start")],
+            functions: Vec::new(),
+            funcs: HashMap::new(),
+            lines: Vec::new(),
+            line: 0,
+        }
+    }
+
+    // without the lines, statements are counted from 1 like the parser does
+    pub fn with_lines(lines: &[usize]) -> Self {
+        Self {
+            lines: lines.to_vec(),
+            ..Self::new()
+        }
+    }
+
+    fn current(&mut self) -> &mut Builder {
+        self.builders.last_mut().unwrap()
+    }
+
+    fn var(&mut self, name: &str) -> Result<Var> {
+        self.current()
+            .vars
+            .get(name)
+            .copied()
+            .ok_or_else(|| new_error(&format!("variable {} not found in this scope", name)))
+    }
+
+    fn array(&mut self, name: &str) -> Result<(Temp, Temp)> {
+        match self.var(name)? {
+            Var::Array(slots, len) => Ok((slots, len)),
+            Var::Int(_) => Err(new_error(&format!("{} is not an array", name))),
+        }
+    }
+
+    fn lower_int(&mut self, expr: ExpressionNode) -> Result<Operand> {
+        match self.lower_expr(expr)? {
+            Value::Int(value) => Ok(value),
+            Value::Array(..) => Err(new_error("arrays can only be assigned or looped over")),
+        }
+    }
+
+    // a divisor that might be zero is checked first, and if it is the
+    // program stops there with the line instead of faulting
+    fn check_divisor(&mut self, op: BinOp, rh: Operand) {
+        if !matches!(op, BinOp::Div | BinOp::Mod) || matches!(rh, Operand::Const(rh) if rh != 0) {
+            return;
+        }
+        let builder = self.current();
+        let ok = builder.new_block();
+        let trap = builder.new_block();
+        builder.terminate(Terminator::Branch(
+            Cond::Eq,
+            rh,
+            Operand::Const(0),
+            trap,
+            ok,
+        ));
+        builder.switch_to(trap);
+        let line = Operand::Const(self.line as i64);
+        self.builtin(Builtin::DivZero, vec![line]);
+        let builder = self.current();
+        builder.terminate(Terminator::Exit(Operand::Const(1)));
+        builder.switch_to(ok);
+    }
+
+    fn binary(&mut self, op: BinOp, lh: Operand, rh: Operand) -> Operand {
+        self.check_divisor(op, rh);
+        let temp = self
+            .current()
+            .assign_temp(Ty::Int, |temp| Inst::Binary(temp, op, lh, rh));
+        Operand::Temp(temp)
+    }
+
+    fn lower_expr(&mut self, expr: ExpressionNode) -> Result<Value> {
+        let value = match expr {
+            ExpressionNode::Value(value) => Operand::Const(
+                value
+                    .parse::<i64>()
+                    .map_err(|_| new_error(&format!("invalid integer {}", value)))?,
+            ),
+            // an array used as a number is its first slot
+            ExpressionNode::Var(name) => match self.var(&name)? {
+                Var::Int(temp) => Operand::Temp(temp),
+                Var::Array(slots, _) => self.load(slots, Operand::Const(0)),
+            },
+            ExpressionNode::Index(name, index) => {
+                let index = self.lower_int(*index)?;
+                let (slots, _) = self.array(&name)?;
+                self.load(slots, index)
+            }
+            ExpressionNode::Infix(lh, op, rh) => {
+                let lh = self.lower_int(*lh)?;
+                let rh = self.lower_int(*rh)?;
+                self.binary(BinOp::from_str(&op)?, lh, rh)
+            }
+            ExpressionNode::Prefix(op, expr) => match op.as_str() {
+                "~" => {
+                    let value = self.lower_int(*expr)?;
+                    let temp = self
+                        .current()
+                        .assign_temp(Ty::Int, |temp| Inst::Not(temp, value));
+                    Operand::Temp(temp)
+                }
+                _ => return Err(new_error(&format!("unknown operator {}", op))),
+            },
+            ExpressionNode::Callable(name, args) => {
+                return self.lower_call(&name, args.into_iter().map(|arg| *arg).collect())
+            }
+            ExpressionNode::Array(exprs) => {
+                let len = exprs.len();
+                let slots = self.alloc(Operand::Const(len as i64 + 1));
+                for (i, expr) in exprs.into_iter().enumerate() {
+                    let value = self.lower_int(*expr)?;
+                    self.current()
+                        .emit(Inst::Store(slots, Operand::Const(i as i64), value));
+                }
+                self.current().emit(Inst::Store(
+                    slots,
+                    Operand::Const(len as i64),
+                    Operand::Const(SENTINEL),
+                ));
+                return Ok(Value::Array(slots, Operand::Const(len as i64)));
+            }
+            ExpressionNode::PreAllocArray(size) => {
+                let count = Operand::Const(size as i64 + 1);
+                let slots = self.alloc(count);
+                self.builtin(Builtin::Fill, vec![Operand::Temp(slots), count]);
+                return Ok(Value::Array(slots, Operand::Const(size as i64)));
+            }
+        };
+        Ok(Value::Int(value))
+    }
+
+    fn load(&mut self, slots: Temp, index: Operand) -> Operand {
+        let temp = self
+            .current()
+            .assign_temp(Ty::Int, |temp| Inst::Load(temp, slots, index));
+        Operand::Temp(temp)
+    }
+
+    fn alloc(&mut self, count: Operand) -> Temp {
+        self.current()
+            .assign_temp(Ty::Ptr, |temp| Inst::Alloc(temp, count))
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: Vec<Operand>) -> Operand {
+        let temp = self
+            .current()
+            .assign_temp(Ty::Int, |temp| Inst::Builtin(temp, builtin, args));
+        Operand::Temp(temp)
+    }
+
+    fn lower_call(&mut self, name: &str, args: Vec<ExpressionNode>) -> Result<Value> {
+        let value = match name {
+            "open" | "read" | "write" | "close" | "unlink" => self.lower_io(name, args)?,
+            "range" => {
+                let (start, stop, step) = self.lower_range_args(args)?;
+                let len = self.builtin(Builtin::RangeLen, vec![start, stop, step]);
+                let count = self.binary(BinOp::Add, len, Operand::Const(1));
+                let slots = self.alloc(count);
+                let fill = vec![Operand::Temp(slots), start, step, len];
+                self.builtin(Builtin::RangeFill, fill);
+                return Ok(Value::Array(slots, len));
+            }
+            // every argument is worked out and the last is printed
+            "print" => {
+                let mut value = None;
+                for arg in args.into_iter() {
+                    value = Some(self.lower_int(arg)?);
+                }
+                let value = value.ok_or_else(|| new_error("print expects an argument"))?;
+                self.builtin(Builtin::Print, vec![value])
+            }
+            name => {
+                let arity = *self
+                    .funcs
+                    .get(name)
+                    .ok_or_else(|| new_error(&format!("function {} undefined", name)))?;
+                if arity != args.len() {
+                    return Err(new_error(&format!(
+                        "{} expects {} arguments, got {}",
+                        name,
+                        arity,
+                        args.len()
+                    )));
+                }
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.lower_int(arg))
+                    .collect::<Result<Vec<Operand>>>()?;
+                let name = name.to_string();
+                let temp = self
+                    .current()
+                    .assign_temp(Ty::Int, |temp| Inst::Call(temp, name, args));
+                Operand::Temp(temp)
+            }
+        };
+        Ok(Value::Int(value))
+    }
+
+    fn lower_range_args(
+        &mut self,
+        args: Vec<ExpressionNode>,
+    ) -> Result<(Operand, Operand, Operand)> {
+        let args = args
+            .into_iter()
+            .map(|arg| self.lower_int(arg))
+            .collect::<Result<Vec<Operand>>>()?;
+        let (zero, one) = (Operand::Const(0), Operand::Const(1));
+        match args[..] {
+            [stop] => Ok((zero, stop, one)),
+            [start, stop] => Ok((start, stop, one)),
+            [start, stop, step] => Ok((start, stop, step)),
+            _ => Err(new_error("range expects 1 to 3 arguments")),
+        }
+    }
+
+    fn lower_io(&mut self, name: &str, mut args: Vec<ExpressionNode>) -> Result<Operand> {
+        let arity = match name {
+            "open" | "read" | "write" => 3,
+            _ => 1,
+        };
+        if args.len() != arity {
+            return Err(new_error(&format!(
+                "{} expects {} arguments, got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        let syscall = match name {
+            "open" => {
+                let (slots, _) = self.array_arg(name, &args[0])?;
+                let flags = self.lower_int(args.remove(1))?;
+                let mode = self.lower_int(args.remove(1))?;
+                let path = self.builtin(Builtin::Path, vec![Operand::Temp(slots)]);
+                vec![Operand::Const(2), path, flags, mode]
+            }
+            "read" | "write" => {
+                let (slots, len) = self.array_arg(name, &args[1])?;
+                let fd = self.lower_int(args.remove(0))?;
+                let count = self.lower_int(args.remove(1))?;
+                let builtin = match name {
+                    "read" => Builtin::Read,
+                    _ => Builtin::Write,
+                };
+                let args = vec![fd, Operand::Temp(slots), Operand::Temp(len), count];
+                return Ok(self.builtin(builtin, args));
+            }
+            "close" => {
+                let fd = self.lower_int(args.remove(0))?;
+                vec![Operand::Const(3), fd, Operand::Const(0), Operand::Const(0)]
+            }
+            "unlink" => {
+                let (slots, _) = self.array_arg(name, &args[0])?;
+                let path = self.builtin(Builtin::Path, vec![Operand::Temp(slots)]);
+                vec![
+                    Operand::Const(87),
+                    path,
+                    Operand::Const(0),
+                    Operand::Const(0),
+                ]
+            }
+            _ => unreachable!(),
+        };
+        Ok(self.builtin(Builtin::Syscall, syscall))
+    }
+
+    fn array_arg(&mut self, name: &str, expr: &ExpressionNode) -> Result<(Temp, Temp)> {
+        match expr {
+            ExpressionNode::Var(varname) => self
+                .array(varname)
+                .map_err(|_| new_error(&format!("array {} not found in this scope", varname))),
+            _ => Err(new_error(&format!("{} expects an array variable", name))),
+        }
+    }
+
+    fn assign(&mut self, name: String, value: Value) -> Result<()> {
+        let builder = self.current();
+        match (builder.vars.get(&name).copied(), value) {
+            (None, Value::Int(value)) => {
+                let temp = builder.assign_temp(Ty::Int, |temp| Inst::Copy(temp, value));
+                builder.vars.insert(name, Var::Int(temp));
+            }
+            (None, Value::Array(slots, len)) => {
+                let slots =
+                    builder.assign_temp(Ty::Ptr, |temp| Inst::Copy(temp, Operand::Temp(slots)));
+                let len = builder.assign_temp(Ty::Int, |temp| Inst::Copy(temp, len));
+                builder.vars.insert(name, Var::Array(slots, len));
+            }
+            (Some(Var::Int(temp)), Value::Int(value)) => builder.emit(Inst::Copy(temp, value)),
+            (Some(Var::Array(slots, _)), Value::Int(value)) => {
+                builder.emit(Inst::Store(slots, Operand::Const(0), value))
+            }
+            (Some(Var::Array(cell, len_cell)), Value::Array(slots, len)) => {
+                builder.emit(Inst::Copy(cell, Operand::Temp(slots)));
+                builder.emit(Inst::Copy(len_cell, len));
+            }
+            (Some(Var::Int(_)), Value::Array(..)) => {
+                return Err(new_error(&format!(
+                    "{} holds a number and cant be reassigned to an array",
+                    name
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn lower_assign_index(
+        &mut self,
+        name: String,
+        index_expr: ExpressionNode,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        let value = self.lower_int(assign_expr)?;
+        let index = self.lower_int(index_expr)?;
+        let (slots, _) = self.array(&name)?;
+        self.current().emit(Inst::Store(slots, index, value));
+        Ok(())
+    }
+
+    fn lower_assign_op(
+        &mut self,
+        name: String,
+        index_expr: Option<ExpressionNode>,
+        op: String,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        let op = BinOp::from_str(&op)?;
+        let value = self.lower_int(assign_expr)?;
+        let index = match index_expr {
+            Some(expr) => Some(self.lower_int(expr)?),
+            None => None,
+        };
+        match (self.var(&name)?, index) {
+            (Var::Int(temp), None) => {
+                let current = Operand::Temp(temp);
+                self.check_divisor(op, value);
+                self.current().emit(Inst::Binary(temp, op, current, value));
+            }
+            (Var::Int(_), Some(_)) => return Err(new_error(&format!("{} is not an array", name))),
+            (Var::Array(slots, _), index) => {
+                let index = index.unwrap_or(Operand::Const(0));
+                let current = self.load(slots, index);
+                let updated = self.binary(op, current, value);
+                self.current().emit(Inst::Store(slots, index, updated));
+            }
+        }
+        Ok(())
+    }
+
+    // jumps to body when the expression isnt 0. comparisons branch on the
+    // operands directly
+    fn lower_branch(&mut self, node: ExpressionNode, body: BlockId, end: BlockId) -> Result<()> {
+        let term = match node {
+            ExpressionNode::Infix(lh, op, rh) if op == "==" || op == "!=" => {
+                let lh = self.lower_int(*lh)?;
+                let rh = self.lower_int(*rh)?;
+                let cond = if op == "==" { Cond::Eq } else { Cond::Ne };
+                Terminator::Branch(cond, lh, rh, body, end)
+            }
+            node => {
+                let value = self.lower_int(node)?;
+                Terminator::Branch(Cond::Ne, value, Operand::Const(0), body, end)
+            }
+        };
+        self.current().terminate(term);
+        self.current().switch_to(body);
+        Ok(())
+    }
+
+    fn lower_if(&mut self, node: ExpressionNode) -> Result<()> {
+        let body = self.current().new_block();
+        let end = self.current().new_block();
+        self.lower_branch(node, body, end)?;
+        self.current().open.push(Open::If(end));
+        Ok(())
+    }
+
+    fn lower_while(&mut self, node: ExpressionNode) -> Result<()> {
+        let head = self.current().new_block();
+        let body = self.current().new_block();
+        let end = self.current().new_block();
+        self.current().switch_to(head);
+        self.lower_branch(node, body, end)?;
+        self.current().open.push(Open::While(head, end));
+        Ok(())
+    }
+
+    fn lower_for(&mut self, varname: String, node: ExpressionNode) -> Result<()> {
+        let head = self.current().new_block();
+        let body = self.current().new_block();
+        let end = self.current().new_block();
+        let open = match node {
+            ExpressionNode::Callable(name, args) if name == "range" => {
+                let args = args.into_iter().map(|arg| *arg).collect();
+                let (start, stop, step) = self.lower_range_args(args)?;
+                let builder = self.current();
+                // stop and step are fixed when the loop starts
+                let stop = builder.assign_temp(Ty::Int, |temp| Inst::Copy(temp, stop));
+                let step = builder.assign_temp(Ty::Int, |temp| Inst::Copy(temp, step));
+                self.assign(varname.clone(), Value::Int(start))?;
+                self.current().switch_to(head);
+                let value = self.lower_int(ExpressionNode::Var(varname.clone()))?;
+
+                // a step of 0 never runs, otherwise stop is exclusive in the
+                // direction of the step
+                let builder = self.current();
+                let check = builder.new_block();
+                let up = builder.new_block();
+                let down = builder.new_block();
+                let (zero, stop, by) =
+                    (Operand::Const(0), Operand::Temp(stop), Operand::Temp(step));
+                builder.terminate(Terminator::Branch(Cond::Eq, by, zero, end, check));
+                builder.switch_to(check);
+                builder.terminate(Terminator::Branch(Cond::Gt, by, zero, up, down));
+                builder.switch_to(up);
+                builder.terminate(Terminator::Branch(Cond::Lt, value, stop, body, end));
+                builder.switch_to(down);
+                builder.terminate(Terminator::Branch(Cond::Gt, value, stop, body, end));
+                Open::Range(head, end, varname, step)
+            }
+            node => {
+                let slots = match self.lower_expr(node)? {
+                    Value::Array(slots, _) => slots,
+                    Value::Int(value) => {
+                        let slots = self.alloc(Operand::Const(2));
+                        let builder = self.current();
+                        builder.emit(Inst::Store(slots, Operand::Const(0), value));
+                        let sentinel = Operand::Const(SENTINEL);
+                        builder.emit(Inst::Store(slots, Operand::Const(1), sentinel));
+                        slots
+                    }
+                };
+                let builder = self.current();
+                let position =
+                    builder.assign_temp(Ty::Int, |temp| Inst::Copy(temp, Operand::Const(0)));
+                self.assign(varname.clone(), Value::Int(Operand::Const(0)))?;
+                self.current().switch_to(head);
+                let value = self.load(slots, Operand::Temp(position));
+                let next = Operand::Temp(position);
+                self.current()
+                    .emit(Inst::Binary(position, BinOp::Add, next, Operand::Const(1)));
+                self.assign(varname, Value::Int(value))?;
+                let sentinel = Operand::Const(SENTINEL);
+                self.current()
+                    .terminate(Terminator::Branch(Cond::Eq, value, sentinel, end, body));
+                Open::Array(head, end)
+            }
+        };
+        self.current().switch_to(body);
+        self.current().open.push(open);
+        Ok(())
+    }
+
+    fn lower_end(&mut self, closer: &str) -> Result<()> {
+        let open = self.current().open.pop();
+        let (head, end) = match (open, closer) {
+            (Some(Open::If(end)), "if") => (None, end),
+            (Some(Open::While(head, end)), "while") => (Some(head), end),
+            (Some(Open::Array(head, end)), "for") => (Some(head), end),
+            (Some(Open::Range(head, end, varname, step)), "for") => {
+                let value = self.lower_int(ExpressionNode::Var(varname.clone()))?;
+                let next = self.binary(BinOp::Add, value, Operand::Temp(step));
+                self.assign(varname, Value::Int(next))?;
+                (Some(head), end)
+            }
+            _ => return Err(new_error(&format!("no {} to close", closer))),
+        };
+        if let Some(head) = head {
+            self.current().terminate(Terminator::Jump(head));
+        }
+        self.current().switch_to(end);
+        Ok(())
+    }
+
+    fn lower_func(&mut self, name: String, args: Vec<String>, inline: Inline) -> Result<()> {
+        if self.builders.len() > 1 {
+            return Err(new_error("functions cant be defined inside functions"));
+        }
+        if self.funcs.contains_key(&name) {
+            return Err(new_error(&format!("function {} is defined twice", name)));
+        }
+        self.funcs.insert(name.clone(), args.len());
+        let mut builder = Builder::new(&name);
+        builder.function.inline = inline;
+        for arg in args.into_iter() {
+            let temp = builder.temp(Ty::Int);
+            builder.function.params.push(temp);
+            builder.vars.insert(arg, Var::Int(temp));
+        }
+        builder.returned =
+            Some(builder.assign_temp(Ty::Int, |temp| Inst::Copy(temp, Operand::Const(0))));
+        self.builders.push(builder);
+        Ok(())
+    }
+
+    fn lower_end_func(&mut self) -> Result<()> {
+        let builder = self.current();
+        let returned = match builder.returned {
+            Some(returned) if builder.open.is_empty() => returned,
+            _ => return Err(new_error("no foo to close")),
+        };
+        let mut builder = self.builders.pop().unwrap();
+        builder.terminate(Terminator::Return(Operand::Temp(returned)));
+        self.functions.push(builder.finish());
+        Ok(())
+    }
+
+    fn lower_return(&mut self, node: ExpressionNode) -> Result<()> {
+        let value = self.lower_int(node)?;
+        if let Some(returned) = self.current().returned {
+            self.current().emit(Inst::Copy(returned, value));
+        }
+        Ok(())
+    }
+
+    pub fn lower(mut self, program: Vec<StatementNode>) -> Result<Program> {
+        for (line_num, line) in program.into_iter().enumerate() {
+            self.line = self.lines.get(line_num).copied().unwrap_or(line_num + 1);
+            match line {
+                StatementNode::Exit(expr_node) => {
+                    let value = self.lower_int(expr_node)?;
+                    self.current().terminate(Terminator::Exit(value));
+                }
+                StatementNode::Assign(name, expr_node) => {
+                    let value = self.lower_expr(expr_node)?;
+                    self.assign(name, value)?
+                }
+                StatementNode::For(var, expr_node) => self.lower_for(var, expr_node)?,
+                StatementNode::EndFor => self.lower_end("for")?,
+                StatementNode::While(expr_node) => self.lower_while(expr_node)?,
+                StatementNode::EndWhile => self.lower_end("while")?,
+                StatementNode::If(expr_node) => self.lower_if(expr_node)?,
+                StatementNode::EndIf => self.lower_end("if")?,
+                StatementNode::AssignIndex(name, index_expr, assign_expr) => {
+                    self.lower_assign_index(name, index_expr, assign_expr)?
+                }
+                StatementNode::AssignOp(name, op, expr_node) => {
+                    self.lower_assign_op(name, None, op, expr_node)?
+                }
+                StatementNode::AssignIndexOp(name, index_expr, op, assign_expr) => {
+                    self.lower_assign_op(name, Some(index_expr), op, assign_expr)?
+                }
+                StatementNode::EndFunc => self.lower_end_func()?,
+                StatementNode::Func(name, args, inline) => self.lower_func(name, args, inline)?,
+                StatementNode::Return(expr) => self.lower_return(expr)?,
+            };
+        }
+        if self.builders.len() > 1 || !self.current().open.is_empty() {
+            return Err(new_error("block is never closed"));
+        }
+        // running off the end exits with 0
+        let mut main = self.builders.pop().unwrap();
+        if main.blocks[main.current].1.is_none() {
+            main.terminate(Terminator::Exit(Operand::Const(0)));
+        }
+        Ok(Program {
+            functions: self.functions,
+            main: main.finish(),
+        })
+    }
+}
@@ -1,44 +1,229 @@
-use std::io::Result;
+use std::fs::write;
+use std::io::{stdin, stdout, Result, Write};
+use std::process;
 
 mod tokenizer;
 use error::new_error;
 use tokenizer::Lexer;
 
 mod local_client;
-use local_client::{read_file, write_assembly_file, write_json};
+use local_client::{
+    output_name, read_file, write_assembly_file, write_executable, write_json, write_output_file,
+};
 
 mod parser;
-use parser::{parse, StatementNode};
+use parser::{parse, source_lines, StatementNode};
 
 mod error;
 
-mod generator;
-use generator::Generator;
-
 mod arg_parser;
-use arg_parser::parse_args;
+use arg_parser::{files, parse_args, program_args};
+
+mod syscall;
+
+mod runtime;
+
+mod bytecode;
+use bytecode::Compiler;
+
+mod vm;
+use vm::Vm;
+
+mod interpreter;
+use interpreter::Interpreter;
+
+mod repl;
+
+mod llvm;
+use llvm::LlvmGenerator;
+
+mod cgen;
+use cgen::CGenerator;
+
+mod wasm;
+use wasm::WasmGenerator;
+
+mod assembler;
+use assembler::Assembler;
+
+mod elf;
+
+mod jit;
+use jit::Jit;
+
+mod driver;
+
+mod ir;
+
+mod lower;
+
+mod loops;
+
+mod fold;
+
+mod inline;
+
+mod optimize;
+use optimize::{build_ir, Options, DEFAULT_LEVEL, MAX_LEVEL};
+
+mod regalloc;
+
+mod tco;
+
+mod x86;
+use x86::compile;
+
+mod peephole;
+
+mod dce;
+
+mod warnings;
+use warnings::warnings;
+
+mod fmt;
+use fmt::format_files;
+
+// a runtime error is printed on its own and exits 1, like the compiled
+// code does
+fn or_exit(result: Result<i64>) -> i64 {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        1
+    })
+}
 
 // loop through args so order soesnt matter
 fn main() -> Result<()> {
     let args = parse_args();
+    if args.contains_key("repl") {
+        let code = repl::run(stdin().lock(), stdout())?;
+        process::exit(code as i32);
+    }
     let filename = args.get("filename").ok_or(new_error("incorrect usage"))?;
+    if args.contains_key("fmt") {
+        let check = args.contains_key("check");
+        let changed = format_files(&files(), check)?;
+        if check && !changed.is_empty() {
+            for file in changed.iter() {
+                eprintln!("{} is not formatted", file);
+            }
+            process::exit(1);
+        }
+        return Ok(());
+    }
 
     let code = read_file(filename);
+    let lines = source_lines(&code);
     // collect the errors into a vec of errors
     let parse_tree: Result<Vec<StatementNode>> = code
-        .into_iter()
+        .iter()
         .filter(|line| !line.trim().is_empty())
-        .map(Lexer::lex)
         .enumerate()
-        .map(|(line_num, line)| parse(line?, line_num + 1))
+        .map(|(line_num, line)| parse(Lexer::lex_line(line.clone(), line_num + 1)?, line_num + 1))
         .collect();
+    if let Ok(program) = &parse_tree {
+        for (at, warning) in warnings(program) {
+            eprintln!("warning: line {}: {}", lines[at], warning);
+        }
+    }
+
+    let level = match args.get("level") {
+        Some(level) => (level.parse().ok())
+            .filter(|level| *level <= MAX_LEVEL)
+            .ok_or(new_error(&format!("no optimisation level -O{}", level)))?,
+        None => DEFAULT_LEVEL,
+    };
+    let options = Options {
+        level,
+        tco: !args.contains_key("no_tco"),
+    };
+
+    if args.contains_key("run") {
+        let program = parse_tree?;
+        let code = match (args.contains_key("interp"), args.contains_key("vm")) {
+            (true, _) => or_exit(Interpreter::with_lines(program, &lines, stdout())?.run()),
+            (_, true) => {
+                let code = Compiler::with_lines(&lines).compile(program)?;
+                or_exit(Vm::new(code, stdout()).run())
+            }
+            _ if args.contains_key("jit") => {
+                let (code, out) = Jit::new(program, &lines, options)?.run()?;
+                stdout().write_all(&out)?;
+                code
+            }
+            _ => {
+                let assembly = compile(program, &lines, options)?;
+                let name = output_name(filename);
+                let keep_temps = args.contains_key("keep_temps");
+                let assembler = args.get("assembler").map(String::as_str);
+                driver::run(assembly, &name, &program_args(), keep_temps, assembler)? as i64
+            }
+        };
+        process::exit(code as i32);
+    }
+
+    if args.contains_key("build") && !args.contains_key("asm_only") {
+        let assembly = compile(parse_tree?, &lines, options)?;
+        let out = args.get("out").cloned().unwrap_or(output_name(filename));
+        let assembler = args.get("assembler").map(String::as_str);
+        return driver::build(assembly, &out, args.contains_key("keep_temps"), assembler);
+    }
+
+    match args.get("emit").map(String::as_str) {
+        Some("tokens") => {
+            let mut dump = String::new();
+            let source = code.into_iter().filter(|line| !line.trim().is_empty());
+            for (line_num, line) in lines.iter().zip(source) {
+                let tokens: Vec<String> = (Lexer::lex_line(line, *line_num)?.iter())
+                    .map(|token| format!("{:?}", token))
+                    .collect();
+                dump += &format!("{}: {}\n", line_num, tokens.join(" "));
+            }
+            return write_output_file(filename, ".tokens", dump);
+        }
+        Some("ast") => return write_json(filename, Ok(parse_tree?)),
+        Some("llvm") => {
+            let ir = LlvmGenerator::with_lines(&lines).generate(parse_tree?)?;
+            return write_output_file(filename, ".ll", ir);
+        }
+        Some("c") => {
+            let source = CGenerator::with_lines(&lines).generate(parse_tree?)?;
+            return write_output_file(filename, ".c", source);
+        }
+        Some("wasm") => {
+            let module = WasmGenerator::with_lines(&lines).generate(parse_tree?)?;
+            write_output_file(filename, ".wat", module.wat)?;
+            return write_output_file(filename, ".wasm", module.wasm);
+        }
+        Some("ir") => {
+            let ir = build_ir(parse_tree?, &lines, options)?;
+            return write_output_file(filename, ".ir", ir.to_string());
+        }
+        Some("asm") => {
+            return write_assembly_file(filename, compile(parse_tree?, &lines, options)?)
+        }
+        Some("obj") => {
+            let assembly = compile(parse_tree?, &lines, options)?;
+            let object = Assembler::new().assemble(&assembly)?;
+            return write_output_file(filename, ".o", elf::object(&object)?);
+        }
+        Some("exe") => {
+            let assembly = compile(parse_tree?, &lines, options)?;
+            let object = Assembler::new().assemble(&assembly)?;
+            return write_executable(filename, elf::link(&object)?);
+        }
+        Some(other) => return Err(new_error(&format!("cant emit {}", other))),
+        None => (),
+    }
 
     match args.get("json") {
         Some(_) => write_json(filename, parse_tree)?,
         None => {
-            let mut generator = Generator::new();
-            let assembly = generator.generate(parse_tree?);
-            write_assembly_file(&filename, assembly?)?;
+            let assembly = compile(parse_tree?, &lines, options);
+            match args.get("out") {
+                Some(out) => write(out, assembly?)?,
+                None => write_assembly_file(&filename, assembly?)?,
+            }
         }
     }
     Ok(())
@@ -0,0 +1,73 @@
+use crate::dce::{remove_dead_stores, remove_unused_functions};
+use crate::fold::fold;
+use crate::inline::inline;
+use crate::ir::Program;
+use crate::loops::optimize_loops;
+use crate::lower::Lowerer;
+use crate::parser::StatementNode;
+use crate::tco::eliminate_tail_calls;
+
+use std::io::Result;
+
+// the native backend does everything it can unless asked otherwise
+pub const MAX_LEVEL: usize = 2;
+pub const DEFAULT_LEVEL: usize = MAX_LEVEL;
+
+// how a native build is optimised. tail calls are turned into jumps at
+// every level unless tco is off, since a deep recursion that only works
+// when optimising would be a surprise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub level: usize,
+    pub tco: bool,
+}
+
+impl Options {
+    pub fn level(level: usize) -> Self {
+        Self { level, tco: true }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::level(DEFAULT_LEVEL)
+    }
+}
+
+// runs the passes for the options over every function. level 0 leaves the
+// ir as it was lowered apart from tail calls, 1 folds constants and removes
+// dead code, which is cheap and only ever makes the program smaller, and 2
+// also inlines and optimises loops
+pub fn optimize(program: &mut Program, options: Options) {
+    if options.tco {
+        program.functions.iter_mut().for_each(eliminate_tail_calls);
+    }
+    if options.level >= 2 {
+        inline(program);
+    }
+    for function in program.functions.iter_mut().chain([&mut program.main]) {
+        if options.level >= 1 {
+            fold(function);
+        }
+        if options.level >= 2 {
+            optimize_loops(function);
+            fold(function);
+        }
+        if options.level >= 1 {
+            remove_dead_stores(function);
+        }
+    }
+    if options.level >= 1 {
+        remove_unused_functions(program);
+    }
+}
+
+// the parse tree lowered, checked and optimised, ready for a backend. lines
+// are where each statement is in the file, for runtime errors to point at
+pub fn build_ir(program: Vec<StatementNode>, lines: &[usize], options: Options) -> Result<Program> {
+    let mut program = Lowerer::with_lines(lines).lower(program)?;
+    program.verify()?;
+    optimize(&mut program, options);
+    program.verify()?;
+    Ok(program)
+}
@@ -8,19 +8,29 @@ use std::vec::IntoIter;
 
 use serde::Serialize;
 
+// what a function asks of the inliner, written before foo
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+pub enum Inline {
+    Auto,
+    Always,
+    Never,
+}
+
 #[derive(Debug, Serialize, PartialEq, Clone)]
 pub enum StatementNode {
     Return(ExpressionNode),
     Exit(ExpressionNode),
     Assign(String, ExpressionNode),
     AssignIndex(String, ExpressionNode, ExpressionNode),
+    AssignOp(String, String, ExpressionNode),
+    AssignIndexOp(String, ExpressionNode, String, ExpressionNode),
     For(String, ExpressionNode),
     EndFor,
     If(ExpressionNode),
     EndIf,
     While(ExpressionNode),
     EndWhile,
-    Func(String, Vec<String>),
+    Func(String, Vec<String>, Inline),
     EndFunc,
 }
 
@@ -31,6 +41,7 @@ pub enum ExpressionNode {
     Index(String, Box<ExpressionNode>),
     Callable(String, Vec<Box<ExpressionNode>>),
     Infix(Box<ExpressionNode>, String, Box<ExpressionNode>),
+    Prefix(String, Box<ExpressionNode>),
     Array(Vec<Box<ExpressionNode>>),
     PreAllocArray(usize),
 }
@@ -40,6 +51,48 @@ pub fn parse(line: Vec<TokenKind>, line_num: usize) -> Result<StatementNode> {
     Parser::parse(iterator, line_num)
 }
 
+// the line in the file each statement came from. blank lines are dropped
+// before parsing, so line_num above counts statements instead
+pub fn source_lines(code: &[String]) -> Vec<usize> {
+    code.iter()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+// a bare expression on its own line, used by the repl to echo values
+pub fn parse_expr(line: Vec<TokenKind>, line_num: usize) -> Result<ExpressionNode> {
+    let mut parser = Parser {
+        iterator: line.into_iter().peekable(),
+        line: line_num,
+    };
+    let current_token = parser
+        .iterator
+        .next()
+        .ok_or_else(|| syntax_error("no tokens found", line_num))?;
+    let expr = parser.parse_expression(current_token, 1)?;
+    match parser.iterator.next() {
+        Some(_) => Err(syntax_error("unexpected token", line_num)),
+        None => Ok(expr),
+    }
+}
+
+// how tightly an infix operator binds, higher first. an operator binds
+// the same on both sides so a - b - c is a - (b - c)
+pub fn precedence(op: &str) -> Option<u8> {
+    match op {
+        "|" => Some(1),
+        "^" => Some(2),
+        "&" => Some(3),
+        "==" | "!=" => Some(4),
+        "<<" | ">>" => Some(5),
+        "+" | "-" => Some(6),
+        "*" | "/" | "%" => Some(7),
+        _ => None,
+    }
+}
+
 struct Parser {
     iterator: Peekable<IntoIter<TokenKind>>,
     line: usize,
@@ -73,7 +126,9 @@ impl Parser {
             TokenKind::EndWhile => Ok(StatementNode::EndWhile),
             TokenKind::If => self.parse_if(),
             TokenKind::EndIf => Ok(StatementNode::EndIf),
-            TokenKind::Func => self.parse_func_dec(),
+            TokenKind::Func => self.parse_func_dec(Inline::Auto),
+            TokenKind::Inline => self.parse_annotated_func(Inline::Always),
+            TokenKind::NoInline => self.parse_annotated_func(Inline::Never),
             TokenKind::EndFunc => Ok(StatementNode::EndFunc),
             _ => Err(syntax_error("not a valid line start", self.line)),
         }
@@ -122,7 +177,14 @@ impl Parser {
         Ok(StatementNode::While(self.parse_expression(exp_start, 1)?))
     }
 
-    fn parse_func_dec(&mut self) -> Result<StatementNode> {
+    fn parse_annotated_func(&mut self, inline: Inline) -> Result<StatementNode> {
+        match self.iterator.next() {
+            Some(TokenKind::Func) => self.parse_func_dec(inline),
+            _ => Err(syntax_error("expected foo", self.line)),
+        }
+    }
+
+    fn parse_func_dec(&mut self, inline: Inline) -> Result<StatementNode> {
         if let Some(TokenKind::Callable(name)) = self.iterator.next() {
             let mut args: Vec<String> = Vec::new();
             loop {
@@ -133,7 +195,7 @@ impl Parser {
                 {
                     TokenKind::VarName(vname) => args.push(vname),
                     TokenKind::Comma => continue,
-                    TokenKind::CloseParen => return Ok(StatementNode::Func(name, args)),
+                    TokenKind::CloseParen => return Ok(StatementNode::Func(name, args, inline)),
                     _ => return Err(syntax_error("unexpected token", self.line)),
                 };
             }
@@ -147,35 +209,64 @@ impl Parser {
             .next()
             .ok_or_else(|| syntax_error("expected =", self.line))?;
         match current_token {
-            TokenKind::Assign => {
-                let current_token = self
-                    .iterator
-                    .next()
-                    .ok_or_else(|| syntax_error("expected expression", self.line))?;
-                Ok(StatementNode::Assign(
-                    name,
-                    self.parse_expression(current_token, 1)?,
-                ))
-            }
+            TokenKind::Assign => Ok(StatementNode::Assign(name, self.parse_assigned_value()?)),
+            TokenKind::AssignOp(op) => Ok(StatementNode::AssignOp(
+                name,
+                op,
+                self.parse_assigned_value()?,
+            )),
+            TokenKind::Increment(op) => Ok(StatementNode::AssignOp(
+                name,
+                op,
+                ExpressionNode::Value("1".to_string()),
+            )),
             TokenKind::OpenSquare => {
                 let current_token = self
                     .iterator
                     .next()
                     .ok_or_else(|| syntax_error("expected expression", self.line))?;
                 let index_expr = self.parse_expression(current_token, 1)?;
-                self.iterator.next();
-                self.iterator.next();
-                let current_token = self
+                match self.iterator.next() {
+                    Some(TokenKind::CloseSquare) => Ok(()),
+                    _ => Err(syntax_error("expected ]", self.line)),
+                }?;
+                match self
                     .iterator
                     .next()
-                    .ok_or_else(|| syntax_error("expected = and expression", self.line))?;
-                let assign_expr = self.parse_expression(current_token, 1)?;
-                Ok(StatementNode::AssignIndex(name, index_expr, assign_expr))
+                    .ok_or_else(|| syntax_error("expected = and expression", self.line))?
+                {
+                    TokenKind::Assign => Ok(StatementNode::AssignIndex(
+                        name,
+                        index_expr,
+                        self.parse_assigned_value()?,
+                    )),
+                    TokenKind::AssignOp(op) => Ok(StatementNode::AssignIndexOp(
+                        name,
+                        index_expr,
+                        op,
+                        self.parse_assigned_value()?,
+                    )),
+                    TokenKind::Increment(op) => Ok(StatementNode::AssignIndexOp(
+                        name,
+                        index_expr,
+                        op,
+                        ExpressionNode::Value("1".to_string()),
+                    )),
+                    _ => Err(syntax_error("expected = and expression", self.line)),
+                }
             }
             _ => Err(syntax_error("Invalid Token", self.line)),
         }
     }
 
+    fn parse_assigned_value(&mut self) -> Result<ExpressionNode> {
+        let current_token = self
+            .iterator
+            .next()
+            .ok_or_else(|| syntax_error("expected expression", self.line))?;
+        self.parse_expression(current_token, 1)
+    }
+
     // how to not repeat myself here
     fn parse_return(&mut self) -> Result<StatementNode> {
         let err_msg = "expected expression";
@@ -235,9 +326,21 @@ impl Parser {
             TokenKind::Int(value) => Ok(ExpressionNode::Value(value)),
             TokenKind::VarName(name) => self.parse_var(name),
             TokenKind::Callable(name) => self.parse_callable(name),
+            TokenKind::Operator(op) if op == "~" => self.parse_prefix(op),
             _ => Err(syntax_error("invalid expression", self.line)),
         }
     }
+    fn parse_prefix(&mut self, op: String) -> Result<ExpressionNode> {
+        let next = self
+            .iterator
+            .next()
+            .ok_or(syntax_error("expected expression", self.line))?;
+        Ok(ExpressionNode::Prefix(
+            op,
+            Box::new(self.parse_expression_token(next)?),
+        ))
+    }
+
     fn parse_open_paren(&mut self) -> Result<ExpressionNode> {
         let expr = match self.iterator.next() {
             Some(token) => self.parse_expression(token, 1),
@@ -327,15 +430,8 @@ impl Parser {
     }
 
     fn get_precedance(&mut self, infix: String) -> Result<u8> {
-        match infix.as_str() {
-            "==" | "!=" => Ok(1),
-            "+" | "-" => Ok(2),
-            "*" | "/" | "%" => Ok(3),
-            _ => Err(syntax_error(
-                format!("unknown operator {}", infix).as_str(),
-                self.line,
-            )),
-        }
+        precedence(&infix)
+            .ok_or_else(|| syntax_error(format!("unknown operator {}", infix).as_str(), self.line))
     }
 
     fn make_infix(lh: ExpressionNode, rh: ExpressionNode, infix: String) -> ExpressionNode {
@@ -0,0 +1,512 @@
+// a peephole pass over nasm text. it only looks at one basic block at a
+// time, anything it doesnt understand ends the block, and it keeps going
+// over the instructions until none of the rewrites apply any more
+
+const QWORDS: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+const DWORDS: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+const WORDS: [&str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w",
+    "r14w", "r15w",
+];
+const BYTES: [&str; 16] = [
+    "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b",
+    "r13b", "r14b", "r15b",
+];
+const HIGH_BYTES: [&str; 4] = ["ah", "ch", "dh", "bh"];
+
+const RAX: usize = 0;
+const RDX: usize = 2;
+const RSP: usize = 4;
+const RBP: usize = 5;
+// the flags get the bit after the registers
+const FLAGS: u32 = 1 << 16;
+
+fn bit(family: usize) -> u32 {
+    1 << family
+}
+
+// which of the sixteen registers a name is part of, and its size in bytes
+fn register(name: &str) -> Option<(usize, usize)> {
+    let find = |names: &[&str], size| {
+        names
+            .iter()
+            .position(|reg| *reg == name)
+            .map(|family| (family, size))
+    };
+    find(&QWORDS, 8)
+        .or_else(|| find(&DWORDS, 4))
+        .or_else(|| find(&WORDS, 2))
+        .or_else(|| find(&BYTES, 1))
+        .or_else(|| find(&HIGH_BYTES, 1))
+}
+
+fn qword(arg: &str) -> Option<usize> {
+    match register(arg) {
+        Some((family, 8)) => Some(family),
+        _ => None,
+    }
+}
+
+// every register an operand names, including the ones in an address
+fn mentioned(arg: &str) -> u32 {
+    arg.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter_map(register)
+        .fold(0, |mask, (family, _)| mask | bit(family))
+}
+
+fn is_memory(arg: &str) -> bool {
+    arg.contains('[')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Label(String, String),
+    Inst(String, String, Vec<String>),
+    // directives, data and blank lines, kept as they are
+    Other(String),
+}
+
+fn parse_line(line: &str) -> Line {
+    let trimmed = line.trim();
+    if trimmed.ends_with(':') && !trimmed.contains(char::is_whitespace) {
+        return Line::Label(line.to_string(), trimmed.trim_end_matches(':').to_string());
+    }
+    let (op, rest) = trimmed
+        .split_once(char::is_whitespace)
+        .unwrap_or((trimmed, ""));
+    if op.is_empty() || op.contains(':') || ["section", "global", "extern"].contains(&op) {
+        return Line::Other(line.to_string());
+    }
+    let indent = line[..line.len() - line.trim_start().len()].to_string();
+    let args = match rest.trim() {
+        "" => Vec::new(),
+        rest => rest.split(',').map(|arg| arg.trim().to_string()).collect(),
+    };
+    Line::Inst(indent, op.to_string(), args)
+}
+
+impl Line {
+    fn render(&self) -> String {
+        match self {
+            Line::Label(text, _) | Line::Other(text) => text.clone(),
+            Line::Inst(indent, op, args) if args.is_empty() => format!("{}{}", indent, op),
+            Line::Inst(indent, op, args) => format!("{}{} {}", indent, op, args.join(", ")),
+        }
+    }
+}
+
+// what an instruction does to the registers and flags. kills are writes
+// that dont depend on what was there before. a barrier is anything that
+// leaves the block or isnt understood
+#[derive(Debug, Default)]
+struct Effects {
+    reads: u32,
+    writes: u32,
+    kills: u32,
+    barrier: bool,
+}
+
+impl Effects {
+    fn dest(&mut self, arg: &str, reads_old: bool) {
+        match register(arg) {
+            // 32 bit writes clear the top half so they kill the whole register
+            Some((family, size)) => {
+                self.writes |= bit(family);
+                match reads_old || size < 4 {
+                    true => self.reads |= bit(family),
+                    false => self.kills |= bit(family),
+                }
+            }
+            None => self.reads |= mentioned(arg),
+        }
+    }
+
+    fn kill_flags(&mut self) {
+        self.writes |= FLAGS;
+        self.kills |= FLAGS;
+    }
+
+    fn change_flags(&mut self) {
+        self.reads |= FLAGS;
+        self.writes |= FLAGS;
+    }
+}
+
+fn effects(op: &str, args: &[String]) -> Effects {
+    let mut effects = Effects::default();
+    let sources = args
+        .iter()
+        .skip(1)
+        .fold(0, |mask, arg| mask | mentioned(arg));
+    effects.reads |= sources;
+    match (op, args) {
+        ("mov" | "movzx" | "movsx" | "movsxd" | "lea", [dest, _]) => effects.dest(dest, false),
+        ("xor" | "sub", [dest, source]) if dest == source && register(dest).is_some() => {
+            effects.reads &= !mentioned(dest);
+            effects.dest(dest, false);
+            effects.kill_flags();
+        }
+        ("add" | "sub" | "and" | "or" | "xor" | "imul", [dest, _]) => {
+            effects.dest(dest, true);
+            effects.kill_flags();
+        }
+        ("adc" | "sbb", [dest, _]) => {
+            effects.dest(dest, true);
+            effects.change_flags();
+        }
+        ("cmp" | "test", [lh, _]) => {
+            effects.reads |= mentioned(lh);
+            effects.kill_flags();
+        }
+        ("imul" | "idiv" | "mul" | "div", [source]) => {
+            effects.reads |= mentioned(source) | bit(RAX) | bit(RDX);
+            effects.writes |= bit(RAX) | bit(RDX);
+            effects.change_flags();
+        }
+        ("inc" | "dec" | "neg", [dest]) => {
+            effects.dest(dest, true);
+            effects.change_flags();
+        }
+        ("not", [dest]) => effects.dest(dest, true),
+        ("shl" | "sal" | "shr" | "sar", [dest, _]) => {
+            effects.dest(dest, true);
+            effects.change_flags();
+        }
+        ("cqo", []) => {
+            effects.reads |= bit(RAX);
+            effects.dest("rdx", false);
+        }
+        ("push", [source]) => {
+            effects.reads |= mentioned(source) | bit(RSP);
+            effects.writes |= bit(RSP);
+        }
+        ("pop", [dest]) => {
+            effects.reads |= bit(RSP);
+            effects.writes |= bit(RSP);
+            effects.dest(dest, false);
+        }
+        (op, [dest]) if op.starts_with("set") => {
+            effects.reads |= FLAGS;
+            effects.dest(dest, true);
+        }
+        (op, [dest, _]) if op.starts_with("cmov") => {
+            effects.reads |= FLAGS;
+            effects.dest(dest, true);
+        }
+        _ => effects.barrier = true,
+    }
+    effects
+}
+
+// the instruction straight after i, if nothing comes between them
+fn next_inst(lines: &[Line], i: usize) -> Option<usize> {
+    match lines.get(i + 1) {
+        Some(Line::Inst(..)) => Some(i + 1),
+        _ => None,
+    }
+}
+
+// whether the registers and flags in mask are all overwritten after line i
+// before anything reads them. the end of the block counts as a read
+fn dead_after(lines: &[Line], i: usize, mask: u32) -> bool {
+    let mut live = mask;
+    for line in lines[i + 1..].iter() {
+        let Line::Inst(_, op, args) = line else {
+            return false;
+        };
+        let effects = effects(op, args);
+        if effects.barrier || effects.reads & live != 0 {
+            return false;
+        }
+        live &= !effects.kills;
+        if live == 0 {
+            return true;
+        }
+    }
+    false
+}
+
+// the same address once a push under it is gone. [rsp] is the pushed
+// value itself so it cant be moved
+fn below_push(arg: &str) -> Option<String> {
+    let start = arg.find('[')?;
+    let inner = arg[start..].strip_prefix('[')?.strip_suffix(']')?;
+    let (base, offset) = inner.split_once('+')?;
+    let offset: i64 = offset.trim().parse().ok()?;
+    if base.trim() != "rsp" || offset < 8 {
+        return None;
+    }
+    Some(match offset - 8 {
+        0 => format!("{}[rsp]", &arg[..start]),
+        offset => format!("{}[rsp + {}]", &arg[..start], offset),
+    })
+}
+
+// a push that is popped again in the same block, with nothing in between
+// moving the stack, becomes a mov to wherever it was popped, or nothing
+// when that is where it came from. the mov goes where the pop was, or
+// where the push was if what was pushed changes in between and the
+// register it is popped into is left alone. addresses off rsp in between
+// are moved down to make up for the missing push
+fn push_pop(lines: &mut Vec<Line>, i: usize) -> bool {
+    let Line::Inst(push_indent, op, args) = &lines[i] else {
+        return false;
+    };
+    let source = match (op.as_str(), args.as_slice()) {
+        ("push", [source]) => source.clone(),
+        _ => return false,
+    };
+    let push_indent = push_indent.clone();
+    let pushed = match qword(&source) {
+        Some(RSP) => return false,
+        Some(family) => bit(family),
+        None if is_memory(&source) || register(&source).is_some() => return false,
+        None => 0,
+    };
+    let mut moved = Vec::new();
+    // the registers used in between
+    let mut touched = 0;
+    for j in i + 1..lines.len() {
+        let Line::Inst(indent, op, args) = &lines[j] else {
+            return false;
+        };
+        if op == "pop" {
+            let pop_indent = indent.clone();
+            let (dest, family) = match args.as_slice() {
+                [dest] => match qword(dest) {
+                    Some(family) if family != RSP => (dest.clone(), family),
+                    _ => return false,
+                },
+                _ => return false,
+            };
+            let at_pop = touched & pushed == 0;
+            if !at_pop && touched & bit(family) != 0 {
+                return false;
+            }
+            let mov = |indent: String| {
+                Line::Inst(
+                    indent,
+                    "mov".to_string(),
+                    vec![dest.clone(), source.clone()],
+                )
+            };
+            for (k, n, arg) in moved {
+                if let Line::Inst(_, _, args) = &mut lines[k] {
+                    args[n] = arg;
+                }
+            }
+            match (at_pop, dest == source) {
+                (true, true) => {
+                    lines.remove(j);
+                    lines.remove(i);
+                }
+                (true, false) => {
+                    lines[j] = mov(pop_indent);
+                    lines.remove(i);
+                }
+                (false, _) => {
+                    lines.remove(j);
+                    lines[i] = mov(push_indent);
+                }
+            }
+            return true;
+        }
+        let effects = effects(op, args);
+        if effects.barrier || op == "push" {
+            return false;
+        }
+        // only writes to what was pushed matter for a mov at the pop
+        touched |= effects.reads & !pushed | effects.writes;
+        for (n, arg) in args.iter().enumerate() {
+            if mentioned(arg) & bit(RSP) != 0 {
+                match below_push(arg) {
+                    Some(arg) => moved.push((j, n, arg)),
+                    None => return false,
+                }
+            }
+        }
+    }
+    false
+}
+
+// mov a, x then mov b, a with a not needed afterwards becomes mov b, x
+fn forward_copy(lines: &mut Vec<Line>, i: usize) -> bool {
+    let Some(j) = next_inst(lines, i) else {
+        return false;
+    };
+    let (Line::Inst(_, op, first), Line::Inst(indent, next_op, second)) = (&lines[i], &lines[j])
+    else {
+        return false;
+    };
+    let (temp, source, dest) = match (
+        op.as_str(),
+        first.as_slice(),
+        next_op.as_str(),
+        second.as_slice(),
+    ) {
+        ("mov", [temp, source], "mov", [dest, copied]) if copied == temp && dest != temp => {
+            (temp, source, dest)
+        }
+        _ => return false,
+    };
+    let Some(family) = qword(temp).filter(|family| *family != RSP && *family != RBP) else {
+        return false;
+    };
+    let fits = match qword(dest) {
+        Some(family) => family != RSP,
+        None => is_memory(dest) && qword(source).is_some() && mentioned(dest) & bit(family) == 0,
+    };
+    if !fits || !dead_after(lines, j, bit(family)) {
+        return false;
+    }
+    lines[j] = Line::Inst(
+        indent.clone(),
+        "mov".to_string(),
+        vec![dest.clone(), source.clone()],
+    );
+    lines.remove(i);
+    true
+}
+
+// mov a, a and the second of mov a, b; mov b, a do nothing
+fn redundant_move(lines: &mut Vec<Line>, i: usize) -> bool {
+    let Line::Inst(_, op, args) = &lines[i] else {
+        return false;
+    };
+    if op != "mov" {
+        return false;
+    }
+    if let [dest, source] = args.as_slice() {
+        if dest == source && register(dest).is_some() {
+            lines.remove(i);
+            return true;
+        }
+        let Some(j) = next_inst(lines, i) else {
+            return false;
+        };
+        let Line::Inst(_, next_op, next) = &lines[j] else {
+            return false;
+        };
+        let swapped = next_op == "mov" && next.len() == 2 && next[0] == *source && next[1] == *dest;
+        let sizes = match (qword(dest), qword(source)) {
+            (Some(_), Some(_)) => true,
+            (Some(family), None) => is_memory(source) && mentioned(source) & bit(family) == 0,
+            (None, Some(_)) => is_memory(dest),
+            (None, None) => false,
+        };
+        if swapped && sizes {
+            lines.remove(j);
+            return true;
+        }
+    }
+    false
+}
+
+// a mov or zeroing into a register that is overwritten before it is read
+fn dead_write(lines: &mut Vec<Line>, i: usize) -> bool {
+    let Line::Inst(_, op, args) = &lines[i] else {
+        return false;
+    };
+    let (dest, flags) = match (op.as_str(), args.as_slice()) {
+        ("mov" | "movzx" | "lea", [dest, _]) => (dest, 0),
+        ("xor", [dest, source]) if dest == source => (dest, FLAGS),
+        _ => return false,
+    };
+    let family = match register(dest) {
+        Some((family, size)) if size >= 4 && family != RSP && family != RBP => family,
+        _ => return false,
+    };
+    if !dead_after(lines, i, bit(family) | flags) {
+        return false;
+    }
+    lines.remove(i);
+    true
+}
+
+fn invert(condition: &str) -> Option<&'static str> {
+    const PAIRS: [(&str, &str); 10] = [
+        ("e", "ne"),
+        ("z", "nz"),
+        ("l", "ge"),
+        ("g", "le"),
+        ("b", "ae"),
+        ("a", "be"),
+        ("s", "ns"),
+        ("o", "no"),
+        ("c", "nc"),
+        ("p", "np"),
+    ];
+    PAIRS.iter().find_map(|(a, b)| match condition {
+        _ if condition == *a => Some(*b),
+        _ if condition == *b => Some(*a),
+        _ => None,
+    })
+}
+
+// whether label comes before the next instruction after line i
+fn lands_after(lines: &[Line], i: usize, label: &str) -> bool {
+    lines[i + 1..]
+        .iter()
+        .map_while(|line| match line {
+            Line::Label(_, name) => Some(name),
+            _ => None,
+        })
+        .any(|name| name == label)
+}
+
+// jumps to the very next instruction go, and a conditional jump over an
+// unconditional one becomes the opposite jump
+fn jump(lines: &mut Vec<Line>, i: usize) -> bool {
+    let Line::Inst(indent, op, args) = &lines[i] else {
+        return false;
+    };
+    let Some(condition) = op.strip_prefix('j') else {
+        return false;
+    };
+    let [target] = args.as_slice() else {
+        return false;
+    };
+    if lands_after(lines, i, target) {
+        lines.remove(i);
+        return true;
+    }
+    let Some(j) = next_inst(lines, i) else {
+        return false;
+    };
+    let (Line::Inst(_, next_op, next), Some(inverse)) = (&lines[j], invert(condition)) else {
+        return false;
+    };
+    if next_op != "jmp" || next.len() != 1 || !lands_after(lines, j, target) {
+        return false;
+    }
+    lines[i] = Line::Inst(indent.clone(), format!("j{}", inverse), next.clone());
+    lines.remove(j);
+    true
+}
+
+pub fn peephole(assembly: &str) -> String {
+    let mut lines: Vec<Line> = assembly.lines().map(parse_line).collect();
+    let rules = [push_pop, forward_copy, redundant_move, dead_write, jump];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut i = 0;
+        while i < lines.len() {
+            match rules.iter().any(|rule| rule(&mut lines, i)) {
+                true => changed = true,
+                false => i += 1,
+            }
+        }
+    }
+    let mut text: String = lines.iter().map(|line| line.render() + "\n").collect();
+    if !assembly.ends_with('\n') {
+        text.pop();
+    }
+    text
+}
@@ -0,0 +1,128 @@
+use crate::ir::{Function, Operand, Temp};
+
+use std::collections::HashSet;
+
+// where a temp lives for the whole function, one of the backend's
+// registers or a slot in the frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    Reg(usize),
+    Slot(usize),
+}
+
+pub fn temps(operands: Vec<Operand>) -> impl Iterator<Item = Temp> {
+    operands.into_iter().filter_map(|operand| match operand {
+        Operand::Temp(temp) => Some(temp),
+        Operand::Const(_) => None,
+    })
+}
+
+// the temps live going into and coming out of each block
+pub fn liveness(function: &Function) -> Vec<(HashSet<Temp>, HashSet<Temp>)> {
+    let mut live = vec![(HashSet::new(), HashSet::new()); function.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, block) in function.blocks.iter().enumerate().rev() {
+            let live_out: HashSet<Temp> = block
+                .term
+                .successors()
+                .iter()
+                .flat_map(|target| live[*target].0.iter().copied())
+                .collect();
+            let mut live_in = live_out.clone();
+            live_in.extend(temps(block.term.operands()));
+            for inst in block.insts.iter().rev() {
+                if let Some(dest) = inst.dest() {
+                    live_in.remove(&dest);
+                }
+                live_in.extend(temps(inst.operands()));
+            }
+            if live_in != live[id].0 || live_out != live[id].1 {
+                live[id] = (live_in, live_out);
+                changed = true;
+            }
+        }
+    }
+    live
+}
+
+// the first and last place each temp is live, counting every instruction
+// and terminator in block order. a temp that is live around a loop covers
+// all of it
+pub fn intervals(function: &Function) -> Vec<Option<(usize, usize)>> {
+    let mut intervals: Vec<Option<(usize, usize)>> = vec![None; function.types.len()];
+    let mut extend = |temp: Temp, at: usize| {
+        intervals[temp.0] = match intervals[temp.0] {
+            Some((start, end)) => Some((start.min(at), end.max(at))),
+            None => Some((at, at)),
+        }
+    };
+    for param in function.params.iter() {
+        extend(*param, 0);
+    }
+    let mut at = 0;
+    for (block, (live_in, live_out)) in function.blocks.iter().zip(liveness(function)) {
+        live_in.iter().for_each(|temp| extend(*temp, at));
+        for inst in block.insts.iter() {
+            temps(inst.operands())
+                .chain(inst.dest())
+                .for_each(|temp| extend(temp, at));
+            at += 1;
+        }
+        temps(block.term.operands()).for_each(|temp| extend(temp, at));
+        live_out.iter().for_each(|temp| extend(*temp, at));
+        at += 1;
+    }
+    intervals
+}
+
+// linear scan over the intervals with this many registers. when they run
+// out, whichever temp stays live the longest goes to the stack since its
+// register would be tied up for longest
+pub fn allocate(function: &Function, registers: usize) -> Vec<Location> {
+    let intervals = intervals(function);
+    let mut order: Vec<usize> = (0..intervals.len()).collect();
+    order.sort_by_key(|temp| intervals[*temp]);
+    let end = |temp: usize| intervals[temp].map_or(0, |(_, end)| end);
+
+    let mut locations = vec![Location::Slot(0); intervals.len()];
+    let mut slots = 0;
+    let mut spill = |locations: &mut Vec<Location>, temp: usize| {
+        locations[temp] = Location::Slot(slots);
+        slots += 1;
+    };
+    // temps holding a register, with the register
+    let mut active: Vec<(usize, usize)> = Vec::new();
+    let mut free: Vec<usize> = (0..registers).rev().collect();
+    for temp in order {
+        // temps that are never touched still need somewhere to be
+        let Some((start, _)) = intervals[temp] else {
+            spill(&mut locations, temp);
+            continue;
+        };
+        active.retain(|(other, register)| match end(*other) < start {
+            true => {
+                free.push(*register);
+                false
+            }
+            false => true,
+        });
+        if let Some(register) = free.pop() {
+            locations[temp] = Location::Reg(register);
+            active.push((temp, register));
+            continue;
+        }
+        let furthest = (0..active.len()).max_by_key(|i| end(active[*i].0));
+        match furthest {
+            Some(i) if end(active[i].0) > end(temp) => {
+                let (other, register) = active.remove(i);
+                spill(&mut locations, other);
+                locations[temp] = Location::Reg(register);
+                active.push((temp, register));
+            }
+            _ => spill(&mut locations, temp),
+        }
+    }
+    locations
+}
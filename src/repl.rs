@@ -0,0 +1,195 @@
+use crate::interpreter::Interpreter;
+use crate::optimize::Options;
+use crate::parser::{parse, parse_expr, ExpressionNode, StatementNode};
+use crate::tokenizer::{Lexer, TokenKind};
+use crate::x86::compile;
+
+use std::io::{BufRead, Error, Result, Write};
+
+const HELP: &str = "\
+:ast   show the parse tree of the last input
+:asm   show the assembly generated for the last input
+:help  show this message
+:quit  leave the repl";
+
+#[derive(Debug, Clone)]
+enum Input {
+    Statements(Vec<StatementNode>),
+    Expression(ExpressionNode),
+}
+
+// reads entries line by line and runs them on one Interpreter so variables
+// and functions carry over. lines opening a block are held until it closes
+pub struct Repl<W: Write> {
+    interpreter: Interpreter<W>,
+    // statements that ran cleanly, compiled again for :asm
+    history: Vec<StatementNode>,
+    last: Option<(Input, usize)>,
+    pending: Vec<Vec<TokenKind>>,
+    depth: usize,
+    line: usize,
+}
+
+// runs the repl until exit, :quit or the end of input and gives back the
+// exit code
+pub fn run<R: BufRead, W: Write + Send>(input: R, out: W) -> Result<i64> {
+    let mut repl = Repl::new(out)?;
+    repl.prompt()?;
+    for line in input.lines() {
+        if let Some(code) = repl.feed(&line?)? {
+            return Ok(code);
+        }
+        repl.prompt()?;
+    }
+    Ok(0)
+}
+
+impl<W: Write + Send> Repl<W> {
+    pub fn new(out: W) -> Result<Self> {
+        Ok(Self {
+            interpreter: Interpreter::new(Vec::new(), out)?,
+            history: Vec::new(),
+            last: None,
+            pending: Vec::new(),
+            depth: 0,
+            line: 0,
+        })
+    }
+
+    fn prompt(&mut self) -> Result<()> {
+        let prompt = match self.depth {
+            0 => ">>> ",
+            _ => "... ",
+        };
+        write!(self.interpreter.out(), "{}", prompt)?;
+        self.interpreter.out().flush()
+    }
+
+    // takes one line of input, giving back the exit code once the repl ends
+    pub fn feed(&mut self, line: &str) -> Result<Option<i64>> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Ok(None);
+        }
+        if self.pending.is_empty() && trimmed.starts_with(':') {
+            return self.command(trimmed);
+        }
+        let tokens = match Lexer::lex(line.to_string()) {
+            Ok(tokens) => tokens,
+            Err(e) => return self.report(e),
+        };
+        match tokens.first() {
+            Some(TokenKind::If | TokenKind::While | TokenKind::For | TokenKind::Func) => {
+                self.depth += 1
+            }
+            Some(
+                TokenKind::EndIf | TokenKind::EndWhile | TokenKind::EndFor | TokenKind::EndFunc,
+            ) => self.depth = self.depth.saturating_sub(1),
+            _ => (),
+        }
+        self.pending.push(tokens);
+        match self.depth {
+            0 => self.submit(),
+            _ => Ok(None),
+        }
+    }
+
+    fn submit(&mut self) -> Result<Option<i64>> {
+        let lines = std::mem::take(&mut self.pending);
+        let start = self.line;
+        self.line += lines.len();
+        let input = match Self::parse_input(lines, start) {
+            Ok(input) => input,
+            Err(e) => return self.report(e),
+        };
+        self.last = Some((input.clone(), self.history.len()));
+        match input {
+            Input::Statements(statements) => match self.interpreter.extend(statements.clone()) {
+                Ok(Some(code)) => return Ok(Some(code)),
+                Ok(None) => self.history.extend(statements),
+                Err(e) => return self.report(e),
+            },
+            Input::Expression(expr) => match self.interpreter.evaluate(&expr) {
+                // print already shows its value
+                Ok(_) if matches!(&expr, ExpressionNode::Callable(name, _) if name == "print") => {}
+                Ok(value) => writeln!(self.interpreter.out(), "{}", value)?,
+                Err(e) => match Interpreter::<W>::exit_code(&e) {
+                    Some(code) => return Ok(Some(code)),
+                    None => return self.report(e),
+                },
+            },
+        }
+        Ok(None)
+    }
+
+    // a single line that isnt a statement is tried as an expression
+    fn parse_input(lines: Vec<Vec<TokenKind>>, start: usize) -> Result<Input> {
+        if let [tokens] = &lines[..] {
+            return match parse(tokens.clone(), start + 1) {
+                Ok(statement) => Ok(Input::Statements(vec![statement])),
+                Err(e) => parse_expr(tokens.clone(), start + 1)
+                    .map(Input::Expression)
+                    .map_err(|_| e),
+            };
+        }
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, tokens)| parse(tokens, start + i + 1))
+            .collect::<Result<Vec<StatementNode>>>()
+            .map(Input::Statements)
+    }
+
+    fn command(&mut self, command: &str) -> Result<Option<i64>> {
+        let shown = match (command, &self.last) {
+            (":quit" | ":q", _) => return Ok(Some(0)),
+            (":help", _) => HELP.to_string(),
+            (":ast" | ":asm", None) => "nothing entered yet".to_string(),
+            (":ast", Some((Input::Statements(statements), _))) => {
+                serde_json::to_string_pretty(statements)?
+            }
+            (":ast", Some((Input::Expression(expr), _))) => serde_json::to_string_pretty(expr)?,
+            (":asm", Some((input, start))) => match self.assembly(input.clone(), *start) {
+                Ok(assembly) => assembly,
+                Err(e) => return self.report(e),
+            },
+            _ => format!("unknown command {}, try :help", command),
+        };
+        writeln!(self.interpreter.out(), "{}", shown)?;
+        Ok(None)
+    }
+
+    // compiles the session up to the last input with and without it at -O0
+    // and keeps the lines that differ. registers can shift around the new
+    // code so a few neighbouring moves may come along with it
+    fn assembly(&self, input: Input, start: usize) -> Result<String> {
+        let before = self.history[..start].to_vec();
+        let mut after = before.clone();
+        match input {
+            Input::Statements(statements) => after.extend(statements),
+            // a lone expression has nowhere to go so it is stored in _
+            Input::Expression(expr) => after.push(StatementNode::Assign("_".to_string(), expr)),
+        }
+        let before = compile(before, &[], Options::level(0))?;
+        let after = compile(after, &[], Options::level(0))?;
+        let (before, after): (Vec<&str>, Vec<&str>) =
+            (before.lines().collect(), after.lines().collect());
+        let start = (before.iter().zip(after.iter()))
+            .take_while(|(a, b)| a == b)
+            .count();
+        let end = (before[start..]
+            .iter()
+            .rev()
+            .zip(after[start..].iter().rev()))
+        .take_while(|(a, b)| a == b)
+        .count();
+        Ok(after[start..after.len() - end].join("\n"))
+    }
+
+    fn report(&mut self, e: Error) -> Result<Option<i64>> {
+        self.pending.clear();
+        self.depth = 0;
+        writeln!(self.interpreter.out(), "error: {}", e)?;
+        Ok(None)
+    }
+}
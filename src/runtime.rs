@@ -0,0 +1,48 @@
+// the pieces of the file builtins that the interpreter and the vm share, so
+// both treat arrays the way the generated code does
+use crate::syscall;
+
+// marks the end of an array, the same value `end` lexes to
+pub const SENTINEL: i64 = 0x7F;
+// the most a file builtin moves in one go, the same size as the generator's iobuf
+pub const IOBUF_SIZE: usize = 4096;
+
+// what the generated code writes to stderr either side of the line number
+// when it divides by zero, before exiting with 1
+pub const DIV_ZERO: (&str, &str) = ("runtime error: line ", ": division by zero\n");
+
+// text as the numbers of a db line, which both assemblers take
+pub fn db(text: &str) -> String {
+    let bytes: Vec<String> = text.bytes().map(|byte| byte.to_string()).collect();
+    bytes.join(", ")
+}
+
+// the bytes up to the sentinel, cut short the same way the iobuf copy is
+pub fn path(slots: &[i64]) -> Vec<u8> {
+    slots
+        .iter()
+        .take_while(|slot| **slot != SENTINEL)
+        .take(IOBUF_SIZE - 1)
+        .map(|slot| *slot as u8)
+        .collect()
+}
+
+// an unsigned clamp like the generated code so negative counts are capped
+pub fn clamp(count: i64, length: usize) -> usize {
+    (count as u64).min(length.min(IOBUF_SIZE) as u64) as usize
+}
+
+// reads up to count bytes into the first slots of an array
+pub fn read(fd: i64, slots: &mut [i64], count: usize) -> i64 {
+    let mut bytes = vec![0; count];
+    let got = syscall::read(fd, &mut bytes);
+    for (slot, byte) in slots.iter_mut().zip(bytes.iter().take(got.max(0) as usize)) {
+        *slot = *byte as i64;
+    }
+    got
+}
+
+// only the low byte of each slot is written, like the generated code
+pub fn bytes(slots: &[i64], count: usize) -> Vec<u8> {
+    slots[..count].iter().map(|slot| *slot as u8).collect()
+}
@@ -0,0 +1,102 @@
+// raw linux syscalls so the file builtins return exactly what the kernel
+// does, including -errno, whichever way the program is run
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn syscall3(number: i64, arg_1: i64, arg_2: i64, arg_3: i64) -> i64 {
+    let out: i64;
+    // safety: every caller hands over pointers to buffers it owns for the
+    // whole call and sized to match the length argument
+    unsafe {
+        std::arch::asm!(
+            "syscall",
+            inlateout("rax") number => out,
+            in("rdi") arg_1,
+            in("rsi") arg_2,
+            in("rdx") arg_3,
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack),
+        );
+    }
+    out
+}
+
+// mmap is the only one that needs all six
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn syscall6(number: i64, args: [i64; 6]) -> i64 {
+    let out: i64;
+    // safety: only used to map fresh anonymous memory
+    unsafe {
+        std::arch::asm!(
+            "syscall",
+            inlateout("rax") number => out,
+            in("rdi") args[0],
+            in("rsi") args[1],
+            in("rdx") args[2],
+            in("r10") args[3],
+            in("r8") args[4],
+            in("r9") args[5],
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack),
+        );
+    }
+    out
+}
+
+// -ENOSYS everywhere else
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn syscall3(_number: i64, _arg_1: i64, _arg_2: i64, _arg_3: i64) -> i64 {
+    -38
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn syscall6(_number: i64, _args: [i64; 6]) -> i64 {
+    -38
+}
+
+fn with_nul(path: &[u8]) -> Vec<u8> {
+    let mut out = path.to_vec();
+    out.push(0);
+    out
+}
+
+pub fn read(fd: i64, buffer: &mut [u8]) -> i64 {
+    syscall3(0, fd, buffer.as_mut_ptr() as i64, buffer.len() as i64)
+}
+
+pub fn write(fd: i64, buffer: &[u8]) -> i64 {
+    syscall3(1, fd, buffer.as_ptr() as i64, buffer.len() as i64)
+}
+
+pub fn open(path: &[u8], flags: i64, mode: i64) -> i64 {
+    let path = with_nul(path);
+    syscall3(2, path.as_ptr() as i64, flags, mode)
+}
+
+pub fn close(fd: i64) -> i64 {
+    syscall3(3, fd, 0, 0)
+}
+
+pub fn unlink(path: &[u8]) -> i64 {
+    let path = with_nul(path);
+    syscall3(87, path.as_ptr() as i64, 0, 0)
+}
+
+// passes on a syscall that jitted code made itself, its pointers point into
+// the program's own memory
+pub fn forward(number: i64, arg_1: i64, arg_2: i64, arg_3: i64) -> i64 {
+    syscall3(number, arg_1, arg_2, arg_3)
+}
+
+// anonymous private memory, the address or -errno
+pub fn mmap(len: usize, prot: i64, flags: i64) -> i64 {
+    syscall6(9, [0, len as i64, prot, flags | 0x22, -1, 0])
+}
+
+pub fn mprotect(address: i64, len: usize, prot: i64) -> i64 {
+    syscall3(10, address, len as i64, prot)
+}
+
+pub fn munmap(address: i64, len: usize) -> i64 {
+    syscall3(11, address, len as i64, 0)
+}
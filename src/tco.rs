@@ -0,0 +1,84 @@
+use crate::ir::{Function, Inst, Operand, Temp, Terminator};
+
+// whether the block's value goes straight back to the caller: it returns
+// returned itself, or falls through empty blocks to one that does
+fn returns(function: &Function, mut block: usize, returned: Temp) -> bool {
+    let mut seen = vec![false; function.blocks.len()];
+    loop {
+        match function.blocks[block].term {
+            Terminator::Return(Operand::Temp(temp)) => return temp == returned,
+            Terminator::Jump(target)
+                if !seen[target] && function.blocks[target].insts.is_empty() =>
+            {
+                seen[target] = true;
+                block = target;
+            }
+            _ => return false,
+        }
+    }
+}
+
+// the block's call to the function itself, if the call is the last thing
+// the block does and its result is what gets returned
+fn tail_call(function: &Function, block: usize, returned: Temp) -> bool {
+    let insts = &function.blocks[block].insts;
+    let [.., Inst::Call(result, name, _), Inst::Copy(dest, Operand::Temp(value))] =
+        insts.as_slice()
+    else {
+        return false;
+    };
+    *name == function.name
+        && dest == &returned
+        && value == result
+        && returns(function, block, returned)
+}
+
+// turns return f(..) at the end of f into a jump back to the top with the
+// params set to the arguments, so the recursion runs in one frame. return
+// only sets the value so the call is in tail position when nothing runs
+// between it and oof
+pub fn eliminate_tail_calls(function: &mut Function) {
+    // the value every ret in the function hands back, there is only one
+    let returned = function.blocks.iter().find_map(|block| match block.term {
+        Terminator::Return(Operand::Temp(temp)) => Some(temp),
+        _ => None,
+    });
+    // an array sized at runtime takes stack that only the ret gives back
+    let grows_stack = function
+        .blocks
+        .iter()
+        .flat_map(|block| block.insts.iter())
+        .any(|inst| matches!(inst, Inst::Alloc(_, Operand::Temp(_))));
+    let Some(returned) = returned else {
+        return;
+    };
+    if grows_stack {
+        return;
+    }
+    for block in 0..function.blocks.len() {
+        if !tail_call(function, block, returned) {
+            continue;
+        }
+        let insts = &mut function.blocks[block].insts;
+        insts.pop();
+        let Some(Inst::Call(_, _, args)) = insts.pop() else {
+            unreachable!("tail_call checked for a call");
+        };
+        // the arguments can read the params, so they are all worked out
+        // before any param is overwritten
+        let mut moves = Vec::new();
+        for (param, arg) in function.params.clone().into_iter().zip(args) {
+            match arg {
+                Operand::Temp(temp) if temp == param => continue,
+                Operand::Temp(_) => {
+                    let staged = function.temp(function.types[param.0]);
+                    function.blocks[block].insts.push(Inst::Copy(staged, arg));
+                    moves.push(Inst::Copy(param, Operand::Temp(staged)));
+                }
+                Operand::Const(_) => moves.push(Inst::Copy(param, arg)),
+            }
+        }
+        function.blocks[block].insts.extend(moves);
+        function.blocks[block].term = Terminator::Jump(0);
+    }
+}
@@ -0,0 +1,191 @@
+use super::cgen_tests::run_c;
+use super::elf_tests::{build, run_elf};
+use super::llvm_tests::run_ir;
+use super::wasm_tests::run_wasm;
+use super::{parse_file, parse_src, SCRIPTS};
+use crate::bytecode::Compiler;
+use crate::cgen::CGenerator;
+use crate::interpreter::Interpreter;
+use crate::jit::Jit;
+use crate::llvm::LlvmGenerator;
+use crate::optimize::Options;
+use crate::parser::StatementNode;
+use crate::vm::Vm;
+use crate::wasm::WasmGenerator;
+use std::io::Result;
+
+// runs a parse tree and gives back the exit code and what it printed. the
+// name keeps the files of backends that write them apart
+type Backend = fn(&str, Vec<StatementNode>) -> Result<(i64, String)>;
+
+const BACKENDS: [(&str, Backend); 7] = [
+    ("interpreter", interpret),
+    ("vm", vm),
+    ("llvm", llvm),
+    ("c", c),
+    ("wasm", wasm),
+    ("elf", elf),
+    ("jit", jit),
+];
+
+fn interpret(_: &str, program: Vec<StatementNode>) -> Result<(i64, String)> {
+    let mut out = Vec::new();
+    let code = Interpreter::new(program, &mut out)?.run()?;
+    Ok((code, String::from_utf8(out).unwrap()))
+}
+
+fn vm(_: &str, program: Vec<StatementNode>) -> Result<(i64, String)> {
+    let mut out = Vec::new();
+    let code = Vm::new(Compiler::new().compile(program)?, &mut out).run()?;
+    Ok((code, String::from_utf8(out).unwrap()))
+}
+
+fn llvm(name: &str, program: Vec<StatementNode>) -> Result<(i64, String)> {
+    let (code, out) = run_ir(name, LlvmGenerator::new().generate(program)?);
+    Ok((code as i64, out))
+}
+
+fn c(name: &str, program: Vec<StatementNode>) -> Result<(i64, String)> {
+    let (code, out) = run_c(name, CGenerator::new().generate(program)?);
+    Ok((code as i64, out))
+}
+
+fn wasm(_: &str, program: Vec<StatementNode>) -> Result<(i64, String)> {
+    Ok(run_wasm(&WasmGenerator::new().generate(program)?.wasm))
+}
+
+// the native code pads what it prints with nul bytes
+fn native(code: i64, stdout: Vec<u8>) -> (i64, String) {
+    (code, String::from_utf8(stdout).unwrap().replace('\0', ""))
+}
+
+fn elf(name: &str, program: Vec<StatementNode>) -> Result<(i64, String)> {
+    let (code, stdout) = run_elf(name, build(program)?);
+    Ok(native(code as i64, stdout))
+}
+
+fn jit(_: &str, program: Vec<StatementNode>) -> Result<(i64, String)> {
+    let (code, stdout) = Jit::new(program, &[], Options::default())?.run()?;
+    Ok(native(code, stdout))
+}
+
+// every backend exits with the code and prints the output
+fn check(name: &str, src: &str, expected: (i64, &str)) {
+    for (backend, run) in BACKENDS {
+        let got = run(name, parse_src(src).unwrap()).unwrap();
+        assert_eq!((expected.0, expected.1.to_string()), got, "{}", backend);
+    }
+}
+
+#[test]
+fn test_scripts() {
+    for (addr, expected) in SCRIPTS {
+        let name = addr.trim_start_matches("test_scripts/").replace(".zb", "");
+        for (backend, run) in BACKENDS {
+            let (code, _) = run(&name, parse_file(addr).unwrap()).unwrap();
+            assert_eq!(expected as i64, code, "{} on {}", addr, backend);
+        }
+    }
+}
+
+#[test]
+fn test_print() {
+    for (backend, run) in BACKENDS {
+        let got = run("print", parse_file("test_scripts/print.zb").unwrap()).unwrap();
+        let expected = (0, "42\n1\n201\n321\n10\n11\n0\n".to_string());
+        assert_eq!(expected, got, "{}", backend);
+    }
+}
+
+#[test]
+fn test_range_values() {
+    let src = "r = range(2, 12, 3)
+for x in range(5)
+  r = range(x)
+rof
+foo last(n)
+  q = range(n)
+  return q[n - 1]
+oof
+_ = print(r[1])
+_ = print(last(9) * 100)
+exit 2";
+    check("range_values", src, (2, "1\n800\n"));
+}
+
+#[test]
+fn test_exit_in_func() {
+    let src = "foo stop()\n  exit 7\noof\n_ = print(stop())\nexit 1";
+    check("exit_in_func", src, (7, ""));
+}
+
+// user functions named like the helpers each backend brings along
+#[test]
+fn test_runtime_names() {
+    let src = "foo clamp(n)
+  return n + 1
+oof
+foo add(a, b)
+  return a - b
+oof
+foo alloc(n)
+  return n * 3
+oof
+foo main(n)
+  return alloc(n) * 2
+oof
+exit clamp(add(main(4), 9))";
+    check("names", src, (16, ""));
+}
+
+// the failed open after the unlink gives -2. wasm has no files to open
+#[test]
+fn test_file_io() {
+    for (backend, run) in BACKENDS.iter().filter(|(backend, _)| *backend != "wasm") {
+        let path = format!("/tmp/zb_{}", backend);
+        let path: Vec<String> = path.chars().map(|c| format!("'{}'", c)).collect();
+        let src = format!(
+            "path = [{}, end]
+data = ['o', 'k', end]
+fd = open(path, 577, 420)
+_ = write(fd, data, 2)
+_ = close(fd)
+got = [size 2]
+fd = open(path, 0, 0)
+n = read(fd, got, 100)
+_ = close(fd)
+_ = unlink(path)
+_ = write(1, got, 2)
+exit n + open(path, 0, 0)",
+            path.join(", ")
+        );
+        let got = run("file_io", parse_src(&src).unwrap()).unwrap();
+        assert_eq!((0, "ok".to_string()), got, "{}", backend);
+    }
+}
+
+#[test]
+fn test_errors() {
+    for src in [
+        "while 1\nx = 1",
+        "if 1\nrof",
+        "fi",
+        "x = nope(1)",
+        "x = 1\nexit x[0]",
+    ] {
+        for (backend, run) in BACKENDS {
+            let program = parse_src(src).unwrap();
+            assert!(run("errors", program).is_err(), "{:?} on {}", src, backend);
+        }
+    }
+}
+
+// a number assigned to an array goes in its first slot, and an array used
+// as a number is its first slot
+#[test]
+fn test_array_assignment() {
+    let src = "a = [1, 2, 3]\na = 7\nexit a[0] * 10 + a[1]";
+    check("number_to_array", src, (72, ""));
+    let src = "a = [4, 5, 6]\nb = a\nb += 1\nexit a[0] * 10 + b";
+    check("array_to_number", src, (45, ""));
+}
@@ -0,0 +1,62 @@
+use super::parse_src;
+use crate::cgen::CGenerator;
+use crate::parser::source_lines;
+use std::fs::write;
+use std::process::Command;
+
+// builds the c with the system compiler, warnings are errors
+fn build_c(name: &str, source: String) -> String {
+    let path = format!("/tmp/zeblang_c_{}", name);
+    write(format!("{}.c", path), source).unwrap();
+    let build = Command::new("cc")
+        .args(["-std=c99", "-pedantic", "-Wall", "-Wextra", "-Werror", "-o"])
+        .arg(&path)
+        .arg(format!("{}.c", path))
+        .output()
+        .expect("failed");
+    assert!(
+        build.status.success(),
+        "{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+    path
+}
+
+pub(super) fn run_c(name: &str, source: String) -> (i32, String) {
+    let output = Command::new(build_c(name, source))
+        .output()
+        .expect("failed");
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+// a number has no slots to index, even the first
+#[test]
+fn test_c_index_number() {
+    for src in ["x = 1\nexit x[2]", "x = 1\nx[0] = 2", "x = 1\nx[1] += 2"] {
+        let err = CGenerator::new()
+            .generate(parse_src(src).unwrap())
+            .unwrap_err();
+        assert_eq!("x is not an array", err.to_string());
+    }
+}
+
+// a zero divisor stops the program with the line it was on
+#[test]
+fn test_c_division_by_zero() {
+    let src = "x = 4\n\ny = x - 4\n\n\nexit x / y\nexit 2";
+    let code: Vec<String> = src.lines().map(|line| line.to_string()).collect();
+    let source = CGenerator::with_lines(&source_lines(&code))
+        .generate(parse_src(src).unwrap())
+        .unwrap();
+    let output = Command::new(build_c("div_zero", source))
+        .output()
+        .expect("failed");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "runtime error: line 6: division by zero\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
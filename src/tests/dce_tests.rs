@@ -0,0 +1,147 @@
+use super::parse_src;
+use crate::ir::{Inst, Program};
+use crate::jit::Jit;
+use crate::optimize::{build_ir, Options};
+use crate::warnings::warnings;
+
+fn optimized(src: &str) -> Program {
+    build_ir(parse_src(src).unwrap(), &[], Options::level(1)).unwrap()
+}
+
+fn jit(src: &str) -> i64 {
+    Jit::new(parse_src(src).unwrap(), &[], Options::default())
+        .unwrap()
+        .run()
+        .unwrap()
+        .0
+}
+
+fn warned(src: &str) -> Vec<(usize, String)> {
+    warnings(&parse_src(src).unwrap())
+}
+
+fn insts(program: &Program) -> usize {
+    program
+        .functions
+        .iter()
+        .chain([&program.main])
+        .flat_map(|function| function.blocks.iter())
+        .map(|block| block.insts.len())
+        .sum()
+}
+
+#[test]
+fn test_dead_stores_removed() {
+    let src = "x = 1\ny = 2\nnoinline foo f(a)\nb = a * 3\nc = b + 1\nreturn a\noof\nexit f(x)";
+    let program = optimized(src);
+    // the copy of a for the return and the call are all that's left
+    assert_eq!(2, insts(&program));
+    assert_eq!(1, jit(src));
+}
+
+#[test]
+fn test_dead_store_keeps_effects() {
+    // the print and the division by zero can't be dropped even though
+    // nothing reads them, the division still stops the program
+    let src = "x = print(5)\nz = 0\ny = 1 / z\nexit 0";
+    let program = optimized(src);
+    let kept = &program.main.blocks[0].insts;
+    assert!(kept.iter().any(|inst| matches!(inst, Inst::Builtin(..))));
+    assert!(program.to_string().contains("div_zero 3"));
+    assert_eq!(1, jit(src));
+}
+
+#[test]
+fn test_dead_loop_variable() {
+    let src = "i = 0\nn = 0\nwhile i != 10\nn += i\ni += 1\nelihw\nexit i";
+    let program = optimized(src);
+    let text = program.to_string();
+    assert_eq!(1, text.matches("add").count());
+    assert_eq!(10, jit(src));
+}
+
+#[test]
+fn test_unused_functions_removed() {
+    let src = "noinline foo b()\nreturn 1\noof\nnoinline foo a()\nreturn b()\noof\nfoo c()\nreturn 2\noof\nfoo d()\nreturn c()\noof\nexit a()";
+    let program = optimized(src);
+    let mut names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+    names.sort();
+    assert_eq!(vec!["a", "b"], names);
+    assert_eq!(1, jit(src));
+}
+
+#[test]
+fn test_warn_after_exit() {
+    let src = "x = 1\nexit x\nx = 2\nexit x";
+    assert_eq!(
+        vec![(2, "this never runs, it comes after an exit".to_string())],
+        warned(src)
+    );
+}
+
+#[test]
+fn test_warn_after_exit_in_block() {
+    // only the rest of the if is dead, the code after fi still runs
+    let src = "x = 1\nif x == 1\nexit 2\nx = 3\nfi\nexit x";
+    assert_eq!(
+        vec![(3, "this never runs, it comes after an exit".to_string())],
+        warned(src)
+    );
+}
+
+#[test]
+fn test_warn_return_replaced() {
+    // the body after a return still runs, only a second return is a mistake
+    let src = "foo f(a)\nreturn a\na += 1\nreturn a\noof\nexit f(1)";
+    assert_eq!(
+        vec![(
+            3,
+            "this return replaces the value of the one before it".to_string()
+        )],
+        warned(src)
+    );
+    // a return in a block picks between the values instead
+    let src = "foo f(a)\nreturn 0\nif a\nreturn a\nreturn 2\nfi\nreturn a\noof\nexit f(1)";
+    assert_eq!(
+        vec![
+            (
+                4,
+                "this return replaces the value of the one before it".to_string()
+            ),
+            (
+                6,
+                "this return replaces the value of the one before it".to_string()
+            ),
+        ],
+        warned(src)
+    );
+}
+
+#[test]
+fn test_warn_unused_variables() {
+    let src =
+        "x = 1\ny = 2\n_z = 3\narr = [1, 2]\narr[0] = 5\nfoo f(a)\nb = a\nreturn a\noof\nexit f(x)";
+    assert_eq!(
+        vec![
+            (1, "y is set but never read".to_string()),
+            (3, "arr is set but never read".to_string()),
+            (6, "b is set but never read".to_string()),
+        ],
+        warned(src)
+    );
+}
+
+#[test]
+fn test_warn_unused_function() {
+    let src = "foo f()\nreturn 1\noof\nfoo g()\nreturn f()\noof\nexit 0";
+    assert_eq!(
+        vec![(3, "function g is never called".to_string())],
+        warned(src)
+    );
+}
+
+#[test]
+fn test_no_warnings() {
+    let src = "n = 0\nfor i in range(0, 5, 1)\nn += i\nrof\nexit n";
+    assert!(warned(src).is_empty());
+}
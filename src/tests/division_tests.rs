@@ -0,0 +1,114 @@
+use super::parse_src;
+use crate::assembler::Assembler;
+use crate::elf::link;
+use crate::interpreter::Interpreter;
+use crate::jit::Jit;
+use crate::optimize::Options;
+use crate::x86::compile;
+use std::fs::{set_permissions, write, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+fn jit(src: &str, level: usize) -> (i64, Vec<u8>) {
+    Jit::new(parse_src(src).unwrap(), &[], Options::level(level))
+        .unwrap()
+        .run()
+        .unwrap()
+}
+
+fn interpret(src: &str) -> (i64, Vec<u8>) {
+    let mut out: Vec<u8> = Vec::new();
+    let code = Interpreter::new(parse_src(src).unwrap(), &mut out)
+        .unwrap()
+        .run()
+        .unwrap();
+    (code, out)
+}
+
+// the -O0 assembly linked and run as its own process
+fn run_generated(name: &str, src: &str) -> Output {
+    let assembly = compile(parse_src(src).unwrap(), &[], Options::level(0)).unwrap();
+    let executable = link(&Assembler::new().assemble(&assembly).unwrap()).unwrap();
+    let path = format!("/tmp/zeblang_division_{}", name);
+    write(&path, executable).unwrap();
+    set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+    Command::new(&path).output().expect("failed")
+}
+
+// the divisor comes from a variable so it isnt folded away
+const SIGNS: &str = "a = 7
+b = 2
+n = 0 - 1
+m = 0x7FFFFFFFFFFFFFFF
+m = ~m
+_ = print((0 - a) / b)
+_ = print((0 - a) % b)
+_ = print(a / (0 - b))
+_ = print(a % (0 - b))
+_ = print((0 - a) / (0 - b))
+_ = print(m / n)
+_ = print(m % n)
+_ = print(m / 1)
+q = 100
+q /= b - 9
+exit q + 20";
+
+#[test]
+fn test_truncating_division() {
+    let expected = interpret(SIGNS);
+    let out = String::from_utf8(expected.1.clone()).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "-3",
+            "-1",
+            "-3",
+            "1",
+            "3",
+            "-9223372036854775808",
+            "0",
+            "-9223372036854775808"
+        ]
+    );
+    assert_eq!(6, expected.0);
+    for level in 0..=2 {
+        assert_eq!(expected, jit(SIGNS, level), "-O{}", level);
+    }
+}
+
+#[test]
+fn test_division_by_zero_exits() {
+    let src = "x = 5
+y = x - 5
+_ = print(1)
+_ = print(x % y)
+exit 3";
+    for level in 0..=2 {
+        let (code, out) = jit(src, level);
+        assert_eq!((1, b"1\n".to_vec()), (code, out), "-O{}", level);
+    }
+    // a divisor that is known to be zero still waits until it runs
+    assert_eq!(1, jit("exit 1 / 0", 2).0);
+    assert_eq!(4, jit("x = 0\nif x\n  exit 1 / x\nfi\nexit 4", 2).0);
+}
+
+#[test]
+fn test_generated_division() {
+    // stale rdx from the multiply would break the quotient without cqo
+    let src = "a = 0 - 3000000000
+b = a * a
+c = (0 - 9) / 2
+d = (0 - 9) % 2
+exit (a / 1000000000 + 10) * 10 + c + d";
+    let output = run_generated("signs", src);
+    assert_eq!(Some(70 - 4 - 1), output.status.code());
+
+    let src = "x = 5\ny = 0\nexit x / y";
+    let output = run_generated("zero", src);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "runtime error: line 3: division by zero\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
@@ -0,0 +1,68 @@
+use super::parse_file;
+use crate::driver::{build, run, tool};
+use crate::optimize::Options;
+use crate::x86::compile;
+use std::fs::remove_file;
+use std::path::Path;
+use std::process::Command;
+
+fn assembly(addr: &str) -> String {
+    compile(parse_file(addr).unwrap(), &[], Options::level(0)).unwrap()
+}
+
+#[test]
+fn test_build() {
+    let out = "/tmp/zeblang_driver_funcs";
+    build(assembly("test_scripts/funcs.zb"), out, false, None).unwrap();
+    let status = Command::new(out).status().unwrap();
+    assert_eq!(Some(18), status.code());
+    assert!(!Path::new(&format!("{}.asm", out)).exists());
+    assert!(!Path::new(&format!("{}.o", out)).exists());
+    remove_file(out).unwrap();
+}
+
+#[test]
+fn test_build_keep_temps() {
+    let out = "/tmp/zeblang_driver_while";
+    build(assembly("test_scripts/while.zb"), out, true, None).unwrap();
+    for file in [
+        out.to_string(),
+        format!("{}.asm", out),
+        format!("{}.o", out),
+    ] {
+        assert!(Path::new(&file).exists(), "{}", file);
+        remove_file(file).unwrap();
+    }
+}
+
+// the built in assembler and linker can be asked for by name
+#[test]
+fn test_build_builtin() {
+    let out = "/tmp/zeblang_driver_builtin";
+    build(assembly("test_scripts/if.zb"), out, false, Some("builtin")).unwrap();
+    let status = Command::new(out).status().unwrap();
+    assert_eq!(Some(3), status.code());
+    remove_file(out).unwrap();
+    let e = build(assembly("test_scripts/if.zb"), out, false, Some("gas")).unwrap_err();
+    assert_eq!("no assembler gas, use nasm or builtin", e.to_string());
+}
+
+#[test]
+fn test_run() {
+    let args = ["a".to_string(), "b".to_string()];
+    for (addr, expected) in [
+        ("test_scripts/range.zb", 143),
+        ("test_scripts/if.zb", 3),
+        ("test_scripts/compound.zb", 54),
+    ] {
+        let code = run(assembly(addr), addr, &args, false, None).unwrap();
+        assert_eq!(expected, code, "{}", addr);
+    }
+}
+
+#[test]
+fn test_missing_tool() {
+    let e = tool("zeblang-no-such-tool", &[]).unwrap_err();
+    assert!(e.to_string().contains("zeblang-no-such-tool was not found"));
+    assert!(tool("false", &[]).is_err());
+}
@@ -0,0 +1,84 @@
+use crate::assembler::Assembler;
+use crate::elf::link;
+use crate::optimize::Options;
+use crate::parser::StatementNode;
+use crate::x86::compile;
+use std::fs::{set_permissions, write, Permissions};
+use std::io::Result;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+// the -O0 assembly, assembled and linked without nasm or ld
+pub(super) fn build(program: Vec<StatementNode>) -> Result<Vec<u8>> {
+    let assembly = compile(program, &[], Options::level(0))?;
+    link(&Assembler::new().assemble(&assembly)?)
+}
+
+pub(super) fn run_elf(name: &str, executable: Vec<u8>) -> (i32, Vec<u8>) {
+    let path = format!("/tmp/zeblang_elf_{}", name);
+    write(&path, executable).unwrap();
+    set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new(&path).output().expect("failed");
+    (output.status.code().unwrap(), output.stdout)
+}
+
+// bytes checked against gnu as, relocated fields are still zero
+#[test]
+fn test_encodings() {
+    for (line, expected) in [
+        ("push rax", vec![0x50]),
+        ("pop r15", vec![0x41, 0x5F]),
+        ("push 0x7F", vec![0x6A, 0x7F]),
+        ("mov rax, [rsp + 8]", vec![0x48, 0x8B, 0x44, 0x24, 0x08]),
+        ("mov rbp, [rbp]", vec![0x48, 0x8B, 0x6D, 0x00]),
+        ("mov [rbp + 16], rax", vec![0x48, 0x89, 0x45, 0x10]),
+        ("mov ah, bl", vec![0x88, 0xDC]),
+        ("add ah, '0'", vec![0x80, 0xC4, 0x30]),
+        ("idiv rbx", vec![0x48, 0xF7, 0xFB]),
+        ("cqo", vec![0x48, 0x99]),
+        ("imul rax, rcx", vec![0x48, 0x0F, 0xAF, 0xC1]),
+        ("sete al", vec![0x0F, 0x94, 0xC0]),
+        ("mov rax, [rbp - 8]", vec![0x48, 0x8B, 0x45, 0xF8]),
+        ("inc r8", vec![0x49, 0xFF, 0xC0]),
+        ("sal rax, cl", vec![0x48, 0xD3, 0xE0]),
+        ("cmova rdx, rax", vec![0x48, 0x0F, 0x47, 0xD0]),
+        ("mov rsi, msg", vec![0x48, 0xC7, 0xC6, 0, 0, 0, 0]),
+        ("mov [msg], eax", vec![0x89, 0x04, 0x25, 0, 0, 0, 0]),
+        ("mov [iobuf + rcx], al", vec![0x88, 0x81, 0, 0, 0, 0]),
+        (
+            "movzx rbx, byte [iobuf + rcx]",
+            vec![0x48, 0x0F, 0xB6, 0x99, 0, 0, 0, 0],
+        ),
+        (
+            "mov rax, [rsi + rcx * 8 + 16]",
+            vec![0x48, 0x8B, 0x44, 0xCE, 0x10],
+        ),
+        (
+            "mov rax, 81985529216486895",
+            vec![0x48, 0xB8, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01],
+        ),
+    ] {
+        let src = format!(
+            "section .data\nmsg: db 0\nsection .bss\niobuf: resb 8\nsection .text\n{}\n",
+            line
+        );
+        assert_eq!(
+            expected,
+            Assembler::new().assemble(&src).unwrap().text,
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_assembler_errors() {
+    let assemble = |src: &str| Assembler::new().assemble(src);
+    assert!(assemble("section .text\nfrobnicate rax\n").is_err());
+    assert!(assemble("section .text\nmov [rax], 1\n").is_err());
+    assert!(assemble("section .text\nmov al, rbx\n").is_err());
+    assert!(assemble("section .text\nx:\nx:\n").is_err());
+    let object = assemble("section .text\n_start:\njmp nowhere\n").unwrap();
+    assert!(link(&object).is_err());
+    assert!(link(&assemble("section .text\nsyscall\n").unwrap()).is_err());
+}
@@ -0,0 +1,132 @@
+use super::parse_src;
+use crate::fmt::format;
+use crate::local_client::read_file;
+use std::fs::read_dir;
+
+fn lines(src: &str) -> Vec<String> {
+    src.lines().map(|line| line.to_string()).collect()
+}
+
+// formatting again changes nothing and the tree is the same as before
+fn check(src: &str) -> String {
+    let formatted = format(&lines(src)).unwrap();
+    assert_eq!(formatted, format(&lines(&formatted)).unwrap());
+    assert_eq!(parse_src(src).unwrap(), parse_src(&formatted).unwrap());
+    formatted
+}
+
+#[test]
+fn test_indent_and_spacing() {
+    let src = "
+foo   f(a,b)
+    s=0
+    for i in range(a,b)
+  if i%2==0
+            s+=i*(a+b)
+      fi
+    rof
+
+
+    return s
+oof
+while x!=3
+x+=1
+elihw
+exit f(1,x)   ";
+    let expected = "foo f(a, b)
+  s = 0
+  for i in range(a, b)
+    if i % 2 == 0
+      s += i * (a + b)
+    fi
+  rof
+
+  return s
+oof
+while x != 3
+  x += 1
+elihw
+exit f(1, x)
+";
+    assert_eq!(expected, check(src));
+}
+
+#[test]
+fn test_brackets() {
+    // ops of the same precedence group to the right, so the first needs
+    // its brackets and the second doesnt
+    let src = "a = (1 - 2) - 3
+b = 1 - (2 - 3)
+c = ((1 + 2)) * ~(3 | 4) << 1
+d = x[(i + 1) * 2] & ~y";
+    let expected = "a = (1 - 2) - 3
+b = 1 - 2 - 3
+c = (1 + 2) * ~(3 | 4) << 1
+d = x[(i + 1) * 2] & ~y
+";
+    assert_eq!(expected, check(src));
+}
+
+#[test]
+fn test_literals_kept() {
+    let src = "x = [0x1F,'a', '\\n',1_000, 0b101, end]
+x[0]++
+i--
+xs = [size 4]
+noinline foo g()
+oof
+inline foo h(n)
+return n
+oof";
+    let expected = "x = [0x1F, 'a', '\\n', 1_000, 0b101, end]
+x[0]++
+i--
+xs = [size 4]
+noinline foo g()
+oof
+inline foo h(n)
+  return n
+oof
+";
+    assert_eq!(expected, check(src));
+}
+
+#[test]
+fn test_pairs() {
+    let pairs = [
+        ("array_3 = [1+1, 4 * 2, 0]", "array_3 = [1 + 1, 4 * 2, 0]\n"),
+        ("x = y*2+1", "x = y * 2 + 1\n"),
+        ("exit 0 ", "exit 0\n"),
+        ("y = x  ", "y = x\n"),
+        (
+            "if y != x \n  x = 3\n  y = y - 19\nfi ",
+            "if y != x\n  x = 3\n  y = y - 19\nfi\n",
+        ),
+        (
+            "foo blah(alpha, beta)\n  return alpha + beta\noof \nfoo sum(a)\n  sum = 0 \noof",
+            "foo blah(alpha, beta)\n  return alpha + beta\noof\nfoo sum(a)\n  sum = 0\noof\n",
+        ),
+        (
+            "while i \n  i = i - 1\nelihw",
+            "while i\n  i = i - 1\nelihw\n",
+        ),
+    ];
+    for (src, expected) in pairs {
+        assert_eq!(expected, check(src), "{:?}", src);
+    }
+}
+
+#[test]
+fn test_format_scripts() {
+    for entry in read_dir("test_scripts").unwrap() {
+        let path = entry.unwrap().path();
+        let path = path.to_str().unwrap();
+        let src = read_file(path).join("\n");
+        match parse_src(&src) {
+            Ok(_) => {
+                check(&src);
+            }
+            Err(_) => assert!(format(&lines(&src)).is_err(), "{}", path),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::parse_src;
+use crate::ir::Program;
+use crate::jit::Jit;
+use crate::optimize::{build_ir, Options};
+
+fn folded(src: &str) -> Program {
+    build_ir(parse_src(src).unwrap(), &[], Options::level(1)).unwrap()
+}
+
+fn jit(src: &str) -> i64 {
+    Jit::new(parse_src(src).unwrap(), &[], Options::default())
+        .unwrap()
+        .run()
+        .unwrap()
+        .0
+}
+
+#[test]
+fn test_fold_expression() {
+    assert_eq!(
+        "func _start() {\nb0:\n    exit 7\n}\n",
+        folded("x = 1 + 2 * 3\nexit x").to_string()
+    );
+}
+
+// division truncates towards zero and the remainder takes the sign of the
+// left hand side, the same as idiv
+#[test]
+fn test_fold_division() {
+    let src = "x = (0 - 7) / 2\ny = (0 - 7) % 2\nexit x * 10 + y";
+    assert_eq!("exit -31", folded(src).main.blocks[0].term.to_string());
+    assert_eq!(-31, jit(src));
+}
+
+// dividing by zero is left for the check to report at runtime, and
+// i64::MIN / -1 wraps
+#[test]
+fn test_no_fold_division_by_zero() {
+    for src in ["x = 0\nexit 1 / x", "x = 0\nexit 1 % x"] {
+        let text = folded(src).to_string();
+        assert!(text.contains("div_zero 2"), "{}", text);
+        assert_eq!(1, jit(src));
+    }
+    let src = "x = 1 << 63\nexit x / (0 - 1)";
+    let term = folded(src).main.blocks[0].term.to_string();
+    assert_eq!(format!("exit {}", i64::MIN), term);
+}
+
+#[test]
+fn test_fold_branches() {
+    let src = "x = 2
+if x == 3
+  x = 4
+fi
+while x != 2
+  x = 9
+elihw
+exit x";
+    assert_eq!(
+        "func _start() {\nb0:\n    exit 2\n}\n",
+        folded(src).to_string()
+    );
+}
+
+// a value is only known after a join if every way in agrees on it
+#[test]
+fn test_fold_joins() {
+    let agree = folded(
+        "noinline foo f(a)\n  x = 5\n  if a == 1\n    x = 5\n  fi\n  return x\noof\nexit f(1)",
+    );
+    assert!(agree.to_string().contains("ret 5"));
+    let differ = folded(
+        "noinline foo f(a)\n  x = 5\n  if a == 1\n    x = 6\n  fi\n  return x\noof\nexit f(1)",
+    );
+    assert!(!differ.to_string().contains("ret 5"));
+    assert_eq!(
+        6,
+        jit("foo f(a)\n  x = 5\n  if a == 1\n    x = 6\n  fi\n  return x\noof\nexit f(1)")
+    );
+}
+
+#[test]
+fn test_loop_counter_not_folded() {
+    let src = "i = 0\nwhile i != 4\n  i += 1\nelihw\nexit i";
+    assert!(folded(src).to_string().contains("br ne"));
+    assert_eq!(4, jit(src));
+}
+
+#[test]
+fn test_level_zero_unchanged() {
+    let src = "x = 1 + 2 * 3\nexit x";
+    let program = build_ir(parse_src(src).unwrap(), &[], Options::level(0)).unwrap();
+    assert!(program.to_string().contains("mul 2, 3"));
+}
@@ -0,0 +1,130 @@
+use super::parse_src;
+use crate::interpreter::Interpreter;
+use crate::ir::{Inst, Program};
+use crate::jit::Jit;
+use crate::optimize::{build_ir, Options};
+
+fn optimized(src: &str) -> Program {
+    build_ir(parse_src(src).unwrap(), &[], Options::default()).unwrap()
+}
+
+fn jit(src: &str) -> (i64, Vec<u8>) {
+    Jit::new(parse_src(src).unwrap(), &[], Options::default())
+        .unwrap()
+        .run()
+        .unwrap()
+}
+
+// the same program with every function marked noinline
+fn noinline(src: &str) -> String {
+    src.lines()
+        .map(|line| match line.trim_start().starts_with("foo ") {
+            true => format!("noinline {}", line.trim_start()),
+            false => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn calls(program: &Program) -> Vec<String> {
+    program
+        .functions
+        .iter()
+        .chain([&program.main])
+        .flat_map(|function| function.blocks.iter())
+        .flat_map(|block| block.insts.iter())
+        .filter_map(|inst| match inst {
+            Inst::Call(_, name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// inlined, not inlined and interpreted all agree on the exit code and
+// what's printed
+fn check(src: &str) {
+    let inlined = jit(src);
+    assert_eq!(inlined, jit(&noinline(src)));
+    let mut out: Vec<u8> = Vec::new();
+    let code = Interpreter::new(parse_src(src).unwrap(), &mut out)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(inlined, (code, out));
+}
+
+#[test]
+fn test_inline_small_function() {
+    let src =
+        "foo square(x)\nreturn x * x\noof\nn = 0\nfor i in [1, 2, 3]\nn += square(i)\nrof\nexit n";
+    let program = optimized(src);
+    assert!(calls(&program).is_empty());
+    assert!(program.functions.is_empty());
+    assert!(!calls(&optimized(&noinline(src))).is_empty());
+    check(src);
+}
+
+#[test]
+fn test_inline_nested() {
+    let src = "foo add(a, b)\nreturn a + b\noof\nfoo twice(a)\nreturn add(a, a)\noof\nexit twice(add(1, 2)) + twice(4)";
+    assert!(calls(&optimized(src)).is_empty());
+    assert_eq!(14, jit(src).0);
+    check(src);
+}
+
+#[test]
+fn test_inline_keeps_effects() {
+    // prints happen in the same order and the value set by return is only
+    // handed back at oof, with the body running on after it
+    let src = "foo f(a)\nreturn print(a)\na = print(a + 1)\nreturn a + 1\noof\nx = f(1) + f(10)\n_ = print(x)\nexit x";
+    assert!(calls(&optimized(src)).is_empty());
+    check(src);
+}
+
+#[test]
+fn test_inline_arrays() {
+    // each inlined copy gets its own array, filled afresh every time round
+    let src = "foo pick(i)\narr = [1, 2, 3]\narr[i] += 10\nreturn arr[i] + arr[0]\noof\nn = 0\nfor i in [0, 1, 2, 1]\nn += pick(i)\nrof\nexit n + pick(2)";
+    assert!(calls(&optimized(src)).is_empty());
+    assert_eq!(76, jit(src).0);
+    check(src);
+}
+
+#[test]
+fn test_inline_exit() {
+    let src = "foo stop(a)\nif a == 3\nexit a\nfi\nreturn a\noof\nfor i in [1, 2, 3, 4]\n_ = stop(i)\nrof\nexit 0";
+    assert!(calls(&optimized(src)).is_empty());
+    assert_eq!(3, jit(src).0);
+    check(src);
+}
+
+#[test]
+fn test_inline_threshold() {
+    let body = "n = a\n".repeat(10) + "n += 1\n".repeat(10).as_str();
+    let src = format!("foo big(a)\n{}return n\noof\nexit big(1) + big(2)", body);
+    assert_eq!(vec!["big", "big"], calls(&optimized(&src)));
+    let forced = format!("inline {}", src);
+    assert!(calls(&optimized(&forced)).is_empty());
+    assert_eq!(jit(&src), jit(&forced));
+}
+
+#[test]
+fn test_noinline() {
+    let src = "noinline foo one()\nreturn 1\noof\nexit one()";
+    assert_eq!(vec!["one"], calls(&optimized(src)));
+    assert_eq!(1, jit(src).0);
+}
+
+#[test]
+fn test_no_inline_recursion() {
+    let src = "inline foo fib(n)\nr = n\nif n != 0\nif n != 1\nr = fib(n - 1) + fib(n - 2)\nfi\nfi\nreturn r\noof\nfoo go(n)\nreturn fib(n)\noof\nexit go(10)";
+    assert_eq!(vec!["fib", "fib", "fib"], calls(&optimized(src)));
+    assert_eq!(55, jit(src).0);
+    check(src);
+}
+
+#[test]
+fn test_inline_needs_foo() {
+    assert!(parse_src("inline x = 1").is_err());
+    assert!(parse_src("noinline exit 1").is_err());
+}
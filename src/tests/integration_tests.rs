@@ -44,6 +44,12 @@ fn test_for() {
     assert_eq!(8, out);
 }
 
+#[test]
+fn test_range() {
+    let out = run_zeblang_file("test_scripts/range.zb");
+    assert_eq!(143, out);
+}
+
 #[test]
 fn test_if() {
     let out = run_zeblang_file("test_scripts/if.zb");
@@ -74,18 +80,81 @@ fn test_precedance() {
     assert_eq!(13, out);
 }
 
+#[test]
+fn test_bit_and() {
+    let out = run_zeblang_file("test_scripts/bit_and.zb");
+    assert_eq!(10, out);
+}
+
+#[test]
+fn test_bit_or() {
+    let out = run_zeblang_file("test_scripts/bit_or.zb");
+    assert_eq!(29, out);
+}
+
+#[test]
+fn test_bit_xor() {
+    let out = run_zeblang_file("test_scripts/bit_xor.zb");
+    assert_eq!(11, out);
+}
+
+#[test]
+fn test_bit_not() {
+    let out = run_zeblang_file("test_scripts/bit_not.zb");
+    assert_eq!(49, out);
+}
+
+#[test]
+fn test_shift_left() {
+    let out = run_zeblang_file("test_scripts/shift_left.zb");
+    assert_eq!(56, out);
+}
+
+#[test]
+fn test_shift_right() {
+    let out = run_zeblang_file("test_scripts/shift_right.zb");
+    assert_eq!(15, out);
+}
+
+#[test]
+fn test_literals() {
+    let out = run_zeblang_file("test_scripts/literals.zb");
+    assert_eq!(66, out);
+}
+
+#[test]
+fn test_compound_assign() {
+    let out = run_zeblang_file("test_scripts/compound.zb");
+    assert_eq!(54, out);
+}
+
 #[test]
 fn test_variables() {
     let out = run_zeblang_file("test_scripts/variables.zb");
     assert_eq!(3, out);
 }
 
+#[test]
+fn test_file_io() {
+    let out = run_zeblang_file("test_scripts/file_io.zb");
+    assert_eq!(46, out);
+}
+
 #[test]
 fn test_json_syntax_error() {
     let out = run_zeblang_file_json("test_scripts/syntax_error.zb");
     assert_eq!("\"2: expected operator\"".to_string(), out);
 }
 
+#[test]
+fn test_json_overflow_error() {
+    let out = run_zeblang_file_json("test_scripts/overflow.zb");
+    assert_eq!(
+        "\"1: integer literal 99999999999999999999 does not fit in i64\"".to_string(),
+        out
+    );
+}
+
 #[test]
 fn test_json() {
     let out = run_zeblang_file_json("test_scripts/json.zb");
@@ -112,3 +181,160 @@ fn test_print() {
         .collect::<Vec<_>>()[2..9];
     assert_eq!(out_slice, ["0", "11", "10", "321", "201", "1", "42"])
 }
+
+// zeblang with the args on a copy of the script in /tmp, so what it writes
+// doesn't land in test_scripts
+fn zeblang_tmp(addr: &str, name: &str, args: &[&str]) -> (bool, String) {
+    let path = format!("/tmp/zeblang_{}.zb", name);
+    std::fs::copy(addr, &path).unwrap();
+    let output = Command::new("target/debug/zeblang")
+        .arg(&path)
+        .args(args)
+        .output()
+        .expect("failed");
+    (output.status.success(), path.replace(".zb", ""))
+}
+
+#[test]
+fn test_emit() {
+    let (ok, out) = zeblang_tmp("test_scripts/if.zb", "emit", &["--emit", "tokens"]);
+    let tokens = read_to_string(format!("{}.tokens", out)).unwrap();
+    assert!(ok && tokens.starts_with("1: "), "{}", tokens);
+    for (stage, extension) in [("ast", "json"), ("ir", "ir"), ("asm", "asm")] {
+        let (ok, out) = zeblang_tmp("test_scripts/if.zb", "emit", &["--emit", stage]);
+        assert!(ok, "{}", stage);
+        assert!(!read_to_string(format!("{}.{}", out, extension))
+            .unwrap()
+            .is_empty());
+    }
+    // an object ld can link
+    let (ok, out) = zeblang_tmp("test_scripts/funcs.zb", "emit", &["--emit", "obj"]);
+    assert!(ok);
+    let linked = Command::new("ld")
+        .arg(format!("{}.o", out))
+        .arg("-o")
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(linked.success());
+    assert_eq!(Some(18), Command::new(&out).status().unwrap().code());
+    assert!(!zeblang_tmp("test_scripts/if.zb", "emit", &["--emit", "bogus"]).0);
+}
+
+#[test]
+fn test_optimisation_levels() {
+    for level in ["-O0", "-O1", "-O2"] {
+        let (ok, out) = zeblang_tmp("test_scripts/range.zb", "levels", &[level, "--emit", "exe"]);
+        assert!(ok, "{}", level);
+        assert_eq!(Some(143), Command::new(&out).status().unwrap().code());
+    }
+    assert!(!zeblang_tmp("test_scripts/range.zb", "levels", &["-O3", "--emit", "exe"]).0);
+    assert!(
+        !zeblang_tmp(
+            "test_scripts/range.zb",
+            "levels",
+            &["-Ofast", "--emit", "ir"]
+        )
+        .0
+    );
+}
+
+// outputs go next to the source even when its path starts with a dot
+#[test]
+fn test_relative_paths() {
+    let dir = "/tmp/zeblang_relative";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::copy("test_scripts/if.zb", format!("{}/if.zb", dir)).unwrap();
+    let zeblang = std::fs::canonicalize("target/debug/zeblang").unwrap();
+    for args in [
+        &["./if.zb", "--emit", "exe"][..],
+        &["../zeblang_relative/if.zb", "-j"],
+    ] {
+        let status = Command::new(&zeblang)
+            .current_dir(dir)
+            .args(args)
+            .status()
+            .expect("failed");
+        assert!(status.success());
+    }
+    let status = Command::new(format!("{}/if", dir)).status().unwrap();
+    assert_eq!(Some(3), status.code());
+    assert!(std::path::Path::new(&format!("{}/if.json", dir)).exists());
+}
+
+// without nasm and ld on the path build says so and uses its own assembler
+// and linker, unless nasm was asked for
+#[test]
+fn test_build_without_nasm() {
+    let path = "/tmp/zeblang_no_nasm.zb";
+    std::fs::copy("test_scripts/range.zb", path).unwrap();
+    let output = Command::new("target/debug/zeblang")
+        .env("PATH", "/nonexistent")
+        .args(["build", path, "-o", "/tmp/zeblang_no_nasm"])
+        .output()
+        .expect("failed");
+    assert!(output.status.success());
+    assert_eq!(
+        "nasm was not found, building with the built in assembler and linker\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let status = Command::new("/tmp/zeblang_no_nasm").status().unwrap();
+    assert_eq!(Some(143), status.code());
+
+    let output = Command::new("target/debug/zeblang")
+        .env("PATH", "/nonexistent")
+        .args([
+            "build",
+            path,
+            "-o",
+            "/tmp/zeblang_no_nasm",
+            "--assembler",
+            "nasm",
+        ])
+        .output()
+        .expect("failed");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("nasm was not found, install it or use --assembler builtin"));
+}
+
+#[test]
+fn test_division_by_zero() {
+    let path = "/tmp/zeblang_div_zero.zb";
+    // the line is the one in the file, blank lines and all
+    std::fs::write(path, "x = 4\n\ny = x - 4\n\n\nexit x / y\n").unwrap();
+    for args in [
+        &["run", "--jit", path][..],
+        &["run", path],
+        &["run", "--interp", path],
+        &["run", "--vm", path],
+    ] {
+        let output = Command::new("target/debug/zeblang")
+            .args(args)
+            .output()
+            .expect("failed");
+        assert_eq!(Some(1), output.status.code());
+        assert_eq!(
+            "runtime error: line 6: division by zero\n",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn test_fmt() {
+    let path = "/tmp/zeblang_fmt.zb";
+    std::fs::write(path, "if 1==1\n    exit 2\nfi\n").unwrap();
+    let fmt = |check: bool| {
+        let mut command = Command::new("target/debug/zeblang");
+        command.arg("fmt");
+        if check {
+            command.arg("--check");
+        }
+        command.arg(path).status().expect("failed").code()
+    };
+    assert_eq!(Some(1), fmt(true));
+    assert_eq!(Some(0), fmt(false));
+    assert_eq!("if 1 == 1\n  exit 2\nfi\n", read_to_string(path).unwrap());
+    assert_eq!(Some(0), fmt(true));
+}
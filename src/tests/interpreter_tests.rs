@@ -0,0 +1,67 @@
+use super::parse_src;
+use crate::interpreter::Interpreter;
+use crate::parser::{source_lines, StatementNode};
+use std::io::Result;
+use std::process::Command;
+
+fn interpret(program: Vec<StatementNode>) -> (i64, String) {
+    let mut out: Vec<u8> = Vec::new();
+    let code = Interpreter::new(program, &mut out).unwrap().run().unwrap();
+    (code, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn test_interpret_unclosed_block() {
+    let program = parse_src("x = 1\nwhile x").unwrap();
+    let err = Interpreter::new(program, Vec::new()).err().unwrap();
+    assert_eq!("2: block is never closed", err.to_string());
+}
+
+#[test]
+fn test_interpret_library() -> Result<()> {
+    assert_eq!(5, zeblang::interpret("x = 2\nx += 3\nexit x".to_string())?);
+    Ok(())
+}
+
+#[test]
+fn test_run_interp() {
+    let output = Command::new("target/debug/zeblang")
+        .arg("run")
+        .arg("--interp")
+        .arg("test_scripts/while.zb")
+        .output()
+        .expect("failed");
+    assert_eq!(Some(12), output.status.code());
+}
+
+const DEPTH: &str = "foo depth(n)
+  r = 0
+  if n
+    r = depth(n - 1) + 1
+  fi
+  return r
+oof
+
+";
+
+#[test]
+fn test_interpret_deep_recursion() {
+    let program = parse_src(&format!("{}exit depth(9000) - 8990", DEPTH)).unwrap();
+    assert_eq!(10, interpret(program).0);
+}
+
+#[test]
+fn test_interpret_call_stack_too_deep() {
+    let src = format!("{}exit depth(100000)", DEPTH);
+    let code: Vec<String> = src.lines().map(|line| line.to_string()).collect();
+    let program = parse_src(&src).unwrap();
+    let err = Interpreter::with_lines(program, &source_lines(&code), Vec::new())
+        .unwrap()
+        .run()
+        .err()
+        .unwrap();
+    assert_eq!(
+        "runtime error: line 4: call stack too deep",
+        err.to_string()
+    );
+}
@@ -0,0 +1,122 @@
+use super::{parse_file, parse_src, script_paths};
+use crate::assembler::Assembler;
+use crate::elf::link;
+use crate::ir::Program;
+use crate::jit::Jit;
+use crate::lower::Lowerer;
+use crate::optimize::Options;
+use crate::x86::compile;
+use std::io::Result;
+
+fn lower_src(src: &str) -> Result<Program> {
+    Lowerer::new().lower(parse_src(src)?)
+}
+
+fn run_src(src: &str) -> (i64, String) {
+    let program = parse_src(src).unwrap();
+    let (code, stdout) = Jit::new(program, &[], Options::default())
+        .unwrap()
+        .run()
+        .unwrap();
+    (code, String::from_utf8(stdout).unwrap())
+}
+
+#[test]
+fn test_ir_text() {
+    let src = "foo twice(x)
+  return x * 2
+oof
+n = 0
+while n != 3
+  n += 1
+elihw
+exit twice(n)";
+    let expected = "func twice(%0: int) {
+b0:
+    %1: int = 0
+    %2: int = mul %0, 2
+    %1 = %2
+    ret %1
+}
+
+func _start() {
+b0:
+    %0: int = 0
+    jmp b1
+b1:
+    br ne %0, 3, b2, b3
+b2:
+    %0 = add %0, 1
+    jmp b1
+b3:
+    %1: int = call twice(%0)
+    exit %1
+}
+";
+    assert_eq!(expected, lower_src(src).unwrap().to_string());
+}
+
+#[test]
+fn test_ir_verifies() {
+    for addr in script_paths() {
+        let program = Lowerer::new().lower(parse_file(addr).unwrap());
+        program.unwrap().verify().unwrap();
+    }
+}
+
+// the ir's x86 through the in-tree assembler and linker
+#[test]
+fn test_ir_assembles() {
+    for addr in script_paths() {
+        let assembly = compile(parse_file(addr).unwrap(), &[], Options::default()).unwrap();
+        link(&Assembler::new().assemble(&assembly).unwrap()).unwrap();
+    }
+}
+
+// functions are real calls, so they can recurse
+#[test]
+fn test_ir_recursion() {
+    let src = "foo fib(n)
+  r = n
+  if n != 0
+    if n != 1
+      r = fib(n - 1) + fib(n - 2)
+    fi
+  fi
+  return r
+oof
+exit fib(10)";
+    assert_eq!(55, run_src(src).0);
+}
+
+// print handles any i64 and hands back what it printed
+#[test]
+fn test_ir_print() {
+    let (code, out) = run_src(
+        "x = print(0 - 9223372036854775807)\n_ = print(1234567)\nexit x + 9223372036854775807",
+    );
+    assert_eq!(0, code);
+    assert_eq!("-9223372036854775807\n1234567\n", out);
+}
+
+#[test]
+fn test_ir_division() {
+    let (code, out) = run_src("_ = print((0 - 7) / 2)\n_ = print((0 - 7) % 2)\nexit 0");
+    assert_eq!(0, code);
+    assert_eq!("-3\n-1\n", out);
+}
+
+#[test]
+fn test_ir_errors() {
+    for src in [
+        "exit nope(1)",
+        "foo f(a)\n  return a\noof\nexit f(1, 2)",
+        "x = 1\nif x == 1\n  x = 2",
+        "x = 1\nfi",
+        "exit [1, 2] + 1",
+        "foo f()\n  return 1\noof\nfoo f()\n  return 2\noof",
+        "foo f()\n  foo g()\n  oof\noof",
+    ] {
+        assert!(lower_src(src).is_err(), "{}", src);
+    }
+}
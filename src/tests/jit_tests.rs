@@ -0,0 +1,26 @@
+use super::parse_src;
+use crate::jit::Jit;
+use crate::optimize::Options;
+
+fn jit_src(src: &str) -> Jit {
+    Jit::new(parse_src(src).unwrap(), &[], Options::default()).unwrap()
+}
+
+// every run starts from the program's initial data
+#[test]
+fn test_jit_rerun() {
+    let mut jit = jit_src("arr = [1, 2]\narr[0] += 5\n_ = print(arr[0])\nexit arr[0] + arr[1]");
+    for _ in 0..3 {
+        let (code, stdout) = jit.run().unwrap();
+        assert_eq!(8, code);
+        assert_eq!(
+            "6",
+            String::from_utf8(stdout).unwrap().replace('\0', "").trim()
+        );
+    }
+}
+
+#[test]
+fn test_jit_no_exit() {
+    assert_eq!((0, Vec::new()), jit_src("x = 4").run().unwrap());
+}
@@ -0,0 +1,62 @@
+use super::parse_src;
+use crate::llvm::LlvmGenerator;
+use crate::parser::source_lines;
+use std::fs::write;
+use std::io::Result;
+use std::process::Command;
+
+fn generate_src(src: &str) -> Result<String> {
+    LlvmGenerator::new().generate(parse_src(src)?)
+}
+
+// runs the ir with lli and hands back the exit code and stdout
+pub(super) fn run_ir(name: &str, ir: String) -> (i32, String) {
+    let path = format!("/tmp/zeblang_llvm_{}.ll", name);
+    write(&path, ir).unwrap();
+    let output = Command::new("lli").arg(&path).output().expect("failed");
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn test_llvm_min_by_minus_one() {
+    let src = "m = 0x7FFFFFFFFFFFFFFF
+m = ~m
+n = 0 - 1
+_ = print(m / n)
+_ = print(m % n)
+exit 3";
+    assert_eq!(
+        (3, "-9223372036854775808\n0\n".to_string()),
+        run_ir("min_div", generate_src(src).unwrap())
+    );
+}
+
+// a number has no slots to index, even the first
+#[test]
+fn test_llvm_index_number() {
+    for src in ["x = 1\nexit x[2]", "x = 1\nx[0] = 2", "x = 1\nx[1] += 2"] {
+        let err = generate_src(src).unwrap_err();
+        assert_eq!("x is not an array", err.to_string());
+    }
+}
+
+// a zero divisor stops the program with the line it was on
+#[test]
+fn test_llvm_division_by_zero() {
+    let src = "x = 4\n\ny = x - 4\n\n\nx %= y\nexit 2";
+    let code: Vec<String> = src.lines().map(|line| line.to_string()).collect();
+    let ir = LlvmGenerator::with_lines(&source_lines(&code))
+        .generate(parse_src(src).unwrap())
+        .unwrap();
+    let path = "/tmp/zeblang_llvm_div_zero.ll";
+    write(path, ir).unwrap();
+    let output = Command::new("lli").arg(path).output().expect("failed");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "runtime error: line 6: division by zero\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,188 @@
+use super::parse_src;
+use crate::bytecode::BinOp;
+use crate::interpreter::Interpreter;
+use crate::ir::{Function, Inst};
+use crate::jit::Jit;
+use crate::optimize::{build_ir, Options};
+
+fn function_at(src: &str, name: &str, options: Options) -> Function {
+    let program = build_ir(parse_src(src).unwrap(), &[], options).unwrap();
+    program
+        .functions
+        .into_iter()
+        .find(|function| function.name == name)
+        .unwrap()
+}
+
+fn function(src: &str, name: &str) -> Function {
+    function_at(src, name, Options::default())
+}
+
+// the ops in blocks that can be run more than once, that is blocks that
+// can get back to themselves
+fn looped(function: &Function) -> Vec<BinOp> {
+    let cycles = |id: usize| {
+        let mut seen = vec![false; function.blocks.len()];
+        let mut pending = function.blocks[id].term.successors();
+        while let Some(next) = pending.pop() {
+            if next == id {
+                return true;
+            }
+            if !seen[next] {
+                seen[next] = true;
+                pending.extend(function.blocks[next].term.successors());
+            }
+        }
+        false
+    };
+    (0..function.blocks.len())
+        .filter(|id| cycles(*id))
+        .flat_map(|id| function.blocks[id].insts.iter())
+        .filter_map(|inst| match inst {
+            Inst::Binary(_, op, ..) => Some(*op),
+            _ => None,
+        })
+        .collect()
+}
+
+// optimised, unoptimised and interpreted all agree
+fn check(src: &str) {
+    let run = |options| {
+        Jit::new(parse_src(src).unwrap(), &[], options)
+            .unwrap()
+            .run()
+            .unwrap()
+    };
+    let optimised = run(Options::default());
+    assert_eq!(optimised, run(Options::level(0)));
+    let mut out: Vec<u8> = Vec::new();
+    let code = Interpreter::new(parse_src(src).unwrap(), &mut out)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(optimised, (code, out));
+}
+
+#[test]
+fn test_strength_reduction() {
+    let src = "noinline foo f(n)
+  i = 0
+  s = 0
+  while i != n
+    s += i * 8 + (i << 2)
+    i += 1
+  elihw
+  return s
+oof
+_ = print(f(10))
+exit f(3)";
+    let ops = looped(&function(src, "f"));
+    assert!(!ops.contains(&BinOp::Mul) && !ops.contains(&BinOp::Shl));
+    check(src);
+}
+
+#[test]
+fn test_strength_reduction_counting_down() {
+    let src = "noinline foo f(n)
+  xs = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+  i = n
+  while i != 0
+    i -= 1
+    xs[i * 2] = i
+  elihw
+  return xs[0] + xs[2] + xs[4] + xs[10]
+oof
+exit f(6)";
+    assert!(!looped(&function(src, "f")).contains(&BinOp::Mul));
+    check(src);
+}
+
+#[test]
+fn test_hoist_invariant() {
+    let src = "noinline foo f(n, k)
+  i = 0
+  s = 0
+  while i != n
+    s += k * k + i
+    i += 1
+  elihw
+  return s
+oof
+exit f(10, 3)";
+    assert!(!looped(&function(src, "f")).contains(&BinOp::Mul));
+    check(src);
+}
+
+#[test]
+fn test_hoist_out_of_nested_loops() {
+    let src = "noinline foo f(n, k)
+  s = 0
+  for i in range(n)
+    for j in range(n)
+      s += (k - 1) * (k + 1) + i
+    rof
+  rof
+  return s
+oof
+_ = print(f(4, 5))
+exit f(3, 2)";
+    let ops = looped(&function(src, "f"));
+    assert!(!ops.contains(&BinOp::Mul));
+    check(src);
+}
+
+#[test]
+fn test_no_hoist() {
+    // the division would fault if it ran when k is 0. k * 2 can still go,
+    // but not x, which is set twice in the loop so changes round it
+    let src = "noinline foo f(n, k)
+  i = 0
+  s = 0
+  while i != n
+    if k != 0
+      s += 100 / k
+    fi
+    x = k * 2
+    x += i
+    s += x
+    i += 1
+  elihw
+  return s
+oof
+_ = print(f(5, 0))
+exit f(5, 4)";
+    let ops = looped(&function(src, "f"));
+    assert!(ops.contains(&BinOp::Div));
+    assert!(!ops.contains(&BinOp::Mul));
+    check(src);
+}
+
+#[test]
+fn test_tail_call_loop() {
+    // after tco the loop goes back to the top of the function
+    let src = "noinline foo f(n, k, acc)
+  return acc
+  if n != 0
+    return f(n - 1, k, acc + k * k)
+  fi
+oof
+exit f(10, 2, 0)";
+    check(src);
+}
+
+#[test]
+fn test_levels() {
+    // loops are only optimised at -O2, and nothing is folded at -O0
+    let src = "noinline foo f(n, k)
+  s = 0
+  for i in range(n)
+    s += k * (2 + 3)
+  rof
+  return s
+oof
+exit f(4, 2)";
+    let at = |level| function_at(src, "f", Options::level(level));
+    assert!(at(2).to_string() != at(1).to_string());
+    assert!(!at(1).to_string().contains("2, 3") && at(0).to_string().contains("2, 3"));
+    check(src);
+}
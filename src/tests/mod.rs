@@ -1,3 +1,72 @@
+mod backend_tests;
+mod cgen_tests;
+mod dce_tests;
+mod division_tests;
+mod driver_tests;
+mod elf_tests;
+mod fmt_tests;
+mod fold_tests;
+mod inline_tests;
 #[cfg(test)]
 mod integration_tests;
+mod interpreter_tests;
+mod ir_tests;
+mod jit_tests;
+mod llvm_tests;
+mod loops_tests;
+mod peephole_tests;
+mod regalloc_tests;
+mod repl_tests;
+mod tco_tests;
 mod tokenizer_tests;
+mod vm_tests;
+mod wasm_tests;
+
+use crate::local_client::read_file;
+use crate::parser::{parse, StatementNode};
+use crate::tokenizer::Lexer;
+use std::io::Result;
+
+// parses source the way main does, skipping blank lines
+pub fn parse_src(src: &str) -> Result<Vec<StatementNode>> {
+    src.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Lexer::lex(line.to_string()))
+        .enumerate()
+        .map(|(line_num, line)| parse(line?, line_num + 1))
+        .collect()
+}
+
+pub fn parse_file(addr: &str) -> Result<Vec<StatementNode>> {
+    parse_src(&read_file(addr).join("\n"))
+}
+
+// the scripts every backend runs and the exit codes the integration tests
+// expect from them
+pub const SCRIPTS: [(&str, i32); 17] = [
+    ("test_scripts/funcs.zb", 18),
+    ("test_scripts/for.zb", 8),
+    ("test_scripts/range.zb", 143),
+    ("test_scripts/if.zb", 3),
+    ("test_scripts/arrays.zb", 10),
+    ("test_scripts/mut_arrays.zb", 8),
+    ("test_scripts/while.zb", 12),
+    ("test_scripts/precedance.zb", 13),
+    ("test_scripts/bit_and.zb", 10),
+    ("test_scripts/bit_or.zb", 29),
+    ("test_scripts/bit_xor.zb", 11),
+    ("test_scripts/bit_not.zb", 49),
+    ("test_scripts/shift_left.zb", 56),
+    ("test_scripts/shift_right.zb", 15),
+    ("test_scripts/literals.zb", 66),
+    ("test_scripts/compound.zb", 54),
+    ("test_scripts/variables.zb", 3),
+];
+
+// the scripts above along with print.zb, for checks that dont need the code
+pub fn script_paths() -> impl Iterator<Item = &'static str> {
+    SCRIPTS
+        .iter()
+        .map(|(addr, _)| *addr)
+        .chain(["test_scripts/print.zb"])
+}
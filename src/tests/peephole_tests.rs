@@ -0,0 +1,190 @@
+use super::{parse_file, script_paths};
+use crate::assembler::Assembler;
+use crate::elf::link;
+use crate::optimize::Options;
+use crate::peephole::peephole;
+use crate::x86::compile;
+use std::fs::{set_permissions, write, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+fn run(name: &str, assembly: &str) -> (i32, Vec<u8>) {
+    let path = format!("/tmp/zeblang_peephole_{}", name);
+    write(
+        &path,
+        link(&Assembler::new().assemble(assembly).unwrap()).unwrap(),
+    )
+    .unwrap();
+    set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new(&path).output().expect("failed");
+    (output.status.code().unwrap(), output.stdout)
+}
+
+fn instructions(assembly: &str) -> usize {
+    assembly
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.ends_with(':'))
+        .filter(|line| {
+            !line.contains(':') && !line.starts_with("section") && !line.starts_with("global")
+        })
+        .count()
+}
+
+// the -O0 output with and without the pass does the same thing and is
+// never longer
+#[test]
+fn test_peephole_scripts() {
+    for addr in script_paths() {
+        let name = addr.trim_start_matches("test_scripts/").replace(".zb", "");
+        let assembly = compile(parse_file(addr).unwrap(), &[], Options::level(0)).unwrap();
+        let optimised = peephole(&assembly);
+        assert_eq!(
+            run(&name, &assembly),
+            run(&format!("{}_opt", name), &optimised),
+            "{}",
+            addr
+        );
+        assert!(
+            instructions(&optimised) <= instructions(&assembly),
+            "{}",
+            addr
+        );
+    }
+}
+
+// the rewrites the pass makes in the -O0 output of the scripts. the ir
+// backend doesnt push or jump to the next block, so the moves are what is
+// left to tidy
+#[test]
+fn test_peephole_compiled() {
+    let compiled = |addr| compile(parse_file(addr).unwrap(), &[], Options::level(0)).unwrap();
+    // a spilled variable read straight back after it is stored
+    let assembly = compiled("test_scripts/compound.zb");
+    let reload = "mov [rbp - 8], rax\n    mov rax, [rbp - 8]\n";
+    assert!(assembly.contains(reload), "{}", assembly);
+    assert!(!peephole(&assembly).contains(reload));
+    // a copy that is only there to be stored
+    let assembly = compiled("test_scripts/range.zb");
+    assert!(assembly.contains("add r15, [rbp - 8]\n    mov rax, r15\n    mov [rbp - 16], rax\n"));
+    assert!(peephole(&assembly).contains("add r15, [rbp - 8]\n    mov [rbp - 16], r15\n"));
+    // a constant overwritten before anything reads it
+    let assembly = compiled("test_scripts/arrays.zb");
+    assert!(assembly.contains("mov r12, rbx\n    mov rbx, 3\n    lea rbx, [rbp - 72]\n"));
+    assert!(peephole(&assembly).contains("mov r12, rbx\n    lea rbx, [rbp - 72]\n"));
+}
+
+fn text(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("    {}\n", line)).collect()
+}
+
+#[test]
+fn test_push_pop() {
+    assert_eq!(
+        text(&["mov rax, 5", "mov rbx, rax", "add rax, rbx", "syscall"]),
+        peephole(&text(&[
+            "mov rax, 5",
+            "push rax",
+            "pop rbx",
+            "add rax, rbx",
+            "syscall"
+        ]))
+    );
+    assert_eq!(
+        text(&["mov rax, 5", "syscall"]),
+        peephole(&text(&["mov rax, 5", "push rax", "pop rax", "syscall"]))
+    );
+    // what was pushed changes, so it is copied where the push was
+    assert_eq!(
+        text(&["mov rdi, rax", "mov rax, 60", "syscall"]),
+        peephole(&text(&["push rax", "mov rax, 60", "pop rdi", "syscall"]))
+    );
+    // rsp offsets in between move down by the missing push
+    assert_eq!(
+        text(&[
+            "mov rax, 1",
+            "mov rcx, [rsp + 8]",
+            "mov rbx, rax",
+            "syscall"
+        ]),
+        peephole(&text(&[
+            "mov rax, 1",
+            "push rax",
+            "mov rcx, [rsp + 16]",
+            "pop rbx",
+            "syscall"
+        ]))
+    );
+}
+
+#[test]
+fn test_push_pop_kept() {
+    for lines in [
+        // the pushed value is read back through rsp
+        ["push rax", "mov rcx, [rsp]", "pop rbx", "syscall"],
+        // rax changes before the pop and rbx is still needed until then
+        ["push rax", "mov rax, rbx", "pop rbx", "syscall"],
+        // the pop is in another block
+        ["push rax", "syscall", "next:", "pop rbx"],
+    ] {
+        let src: String = lines
+            .iter()
+            .map(|line| match line.ends_with(':') {
+                true => format!("{}\n", line),
+                false => format!("    {}\n", line),
+            })
+            .collect();
+        assert_eq!(src, peephole(&src));
+    }
+}
+
+#[test]
+fn test_moves() {
+    assert_eq!(
+        text(&["mov rbx, [rsp + 8]", "mov rax, 2", "syscall"]),
+        peephole(&text(&[
+            "mov rax, [rsp + 8]",
+            "mov rbx, rax",
+            "mov rax, 2",
+            "syscall"
+        ]))
+    );
+    assert_eq!(
+        text(&["mov [rsp], rax", "syscall"]),
+        peephole(&text(&[
+            "mov [rsp], rax",
+            "mov rax, [rsp]",
+            "mov rbx, rbx",
+            "syscall"
+        ]))
+    );
+}
+
+// a zeroing is only dropped when the register is written again before it
+// is read, the one before idiv is needed
+#[test]
+fn test_dead_zeroing() {
+    assert_eq!(
+        text(&["mov rax, 1", "cmp rax, rbx", "syscall"]),
+        peephole(&text(&[
+            "xor rax, rax",
+            "mov rax, 1",
+            "cmp rax, rbx",
+            "syscall"
+        ]))
+    );
+    let src = text(&["xor rdx, rdx", "idiv rbx", "syscall"]);
+    assert_eq!(src, peephole(&src));
+}
+
+#[test]
+fn test_jumps() {
+    assert_eq!(
+        "    cmp rax, rbx\nnext:\n    syscall\n",
+        peephole("    cmp rax, rbx\n    je next\nnext:\n    syscall\n")
+    );
+    assert_eq!(
+        "    cmp rax, rbx\n    jne out\nskip:\n    syscall\n",
+        peephole("    cmp rax, rbx\n    je skip\n    jmp out\nskip:\n    syscall\n")
+    );
+}
@@ -0,0 +1,107 @@
+use super::{parse_file, parse_src};
+use crate::ir::Function;
+use crate::jit::Jit;
+use crate::lower::Lowerer;
+use crate::optimize::Options;
+use crate::regalloc::{allocate, intervals, Location};
+use crate::x86::compile;
+
+fn main_of(src: &str) -> Function {
+    Lowerer::new().lower(parse_src(src).unwrap()).unwrap().main
+}
+
+// no two temps that are live at once share a register
+fn check(function: &Function, registers: usize) {
+    let intervals = intervals(function);
+    let locations = allocate(function, registers);
+    for (a, location) in locations.iter().enumerate() {
+        for b in a + 1..locations.len() {
+            if let (Location::Reg(_), Some((start, end)), Some((other_start, other_end))) =
+                (location, intervals[a], intervals[b])
+            {
+                let overlap = start <= other_end && other_start <= end;
+                assert!(!overlap || locations[b] != *location, "%{} and %{}", a, b);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_loop_intervals() {
+    let function = main_of("x = 5\ni = 0\nwhile i != 3\n  i += 1\nelihw\nexit x");
+    let intervals = intervals(&function);
+    let (_, loop_end) = intervals[1].unwrap();
+    // x is used after the loop so it stays live all the way through it
+    assert!(intervals[0].unwrap().1 > loop_end);
+}
+
+#[test]
+fn test_allocations_dont_overlap() {
+    for addr in [
+        "test_scripts/funcs.zb",
+        "test_scripts/for.zb",
+        "test_scripts/range.zb",
+        "test_scripts/mut_arrays.zb",
+        "test_scripts/precedance.zb",
+        "test_scripts/compound.zb",
+        "benchmarks/is_127_prime.zb",
+    ] {
+        let program = Lowerer::new().lower(parse_file(addr).unwrap()).unwrap();
+        for function in program.functions.iter().chain([&program.main]) {
+            for registers in 0..6 {
+                check(function, registers);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_spills() {
+    let function = main_of("a = 1\nb = 2\nc = 3\nexit a + b + c");
+    let spilled = |registers| {
+        allocate(&function, registers)
+            .iter()
+            .filter(|location| matches!(location, Location::Slot(_)))
+            .count()
+    };
+    assert_eq!(0, spilled(5));
+    assert!(spilled(1) > 0);
+}
+
+// the prime loop runs without touching memory
+#[test]
+fn test_prime_loop_in_registers() {
+    let assembly = compile(
+        parse_file("benchmarks/is_127_prime.zb").unwrap(),
+        &[],
+        Options::default(),
+    )
+    .unwrap();
+    let start = assembly.split("zb_print:").next().unwrap();
+    assert!(!start.contains("[rbp"));
+    assert!(!start.contains("push"));
+}
+
+// more live values than registers, kept across calls that use the
+// registers themselves
+#[test]
+fn test_spilled_program() {
+    let src = "foo mix(a, b)
+  c = a * 3
+  d = b * 5
+  e = c + d
+  f = c - d
+  return e * f
+oof
+v1 = 1
+v2 = 2
+v3 = 3
+v4 = 4
+v5 = 5
+v6 = 6
+v7 = 7
+v8 = mix(v1, v2)
+exit v1 + v2 + v3 + v4 + v5 + v6 + v7 + v8";
+    let mut jit = Jit::new(parse_src(src).unwrap(), &[], Options::default()).unwrap();
+    assert_eq!(28 + (3 + 10) * (3 - 10), jit.run().unwrap().0);
+}
@@ -0,0 +1,57 @@
+use crate::repl;
+
+fn session(input: &str) -> (i64, String) {
+    let mut out: Vec<u8> = Vec::new();
+    let code = repl::run(input.as_bytes(), &mut out).unwrap();
+    (code, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn test_repl_keeps_state() {
+    let (code, out) = session("x = 4\nfoo double(n)\nreturn n * 2\noof\ndouble(x) + 1\nx\n");
+    assert_eq!(0, code);
+    assert_eq!(">>> >>> ... ... >>> 9\n>>> 4\n>>> ", out);
+}
+
+#[test]
+fn test_repl_runs_blocks_when_closed() {
+    let (_, out) = session("i = 0\nwhile i != 3\n_ = print(i)\ni++\nelihw\n");
+    assert_eq!(">>> >>> ... ... ... 0\n1\n2\n>>> ", out);
+}
+
+#[test]
+fn test_repl_exit() {
+    assert_eq!(7, session("x = 7\nexit x\nexit 1\n").0);
+    assert_eq!(3, session("foo f()\nexit 3\noof\nf()\n").0);
+    assert_eq!(0, session(":quit\nexit 1\n").0);
+}
+
+#[test]
+fn test_repl_recovers_from_errors() {
+    let (code, out) = session("y\nfi\nx = 1 / 0\nx = 2\nx\n");
+    assert_eq!(0, code);
+    assert!(
+        out.contains("error: variable y not found in this scope"),
+        "{}",
+        out
+    );
+    assert!(out.contains("no if to close"), "{}", out);
+    assert!(
+        out.contains("runtime error: line 1: division by zero"),
+        "{}",
+        out
+    );
+    assert!(out.ends_with(">>> 2\n>>> "), "{}", out);
+}
+
+#[test]
+fn test_repl_ast_and_asm() {
+    let (_, out) = session("x = 5\n:ast\n:asm\ny = x\n:asm\n");
+    assert!(out.contains("\"Assign\""), "{}", out);
+    assert!(out.contains(", 5"), "{}", out);
+    // the second :asm only shows the code for y = x
+    let last = out.rsplit(">>> ").nth(1).unwrap();
+    assert!(!last.contains(", 5"), "{}", last);
+    assert!(!last.contains("section"), "{}", last);
+    assert!(last.contains("mov "), "{}", last);
+}
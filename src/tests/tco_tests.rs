@@ -0,0 +1,105 @@
+use super::parse_src;
+use crate::interpreter::Interpreter;
+use crate::ir::{Inst, Program};
+use crate::jit::Jit;
+use crate::optimize::{build_ir, Options};
+
+fn no_tco() -> Options {
+    Options {
+        tco: false,
+        ..Options::default()
+    }
+}
+
+fn calls(src: &str, options: Options) -> usize {
+    let program: Program = build_ir(parse_src(src).unwrap(), &[], options).unwrap();
+    program
+        .functions
+        .iter()
+        .flat_map(|function| function.blocks.iter())
+        .flat_map(|block| block.insts.iter())
+        .filter(|inst| matches!(inst, Inst::Call(..)))
+        .count()
+}
+
+fn jit(src: &str, options: Options) -> i64 {
+    Jit::new(parse_src(src).unwrap(), &[], options)
+        .unwrap()
+        .run()
+        .unwrap()
+        .0
+}
+
+fn interpret(src: &str) -> i64 {
+    Interpreter::new(parse_src(src).unwrap(), Vec::new())
+        .unwrap()
+        .run()
+        .unwrap()
+}
+
+const COUNT: &str = "noinline foo count(n, acc)
+  return acc
+  if n != 0
+    return count(n - 1, acc + 1)
+  fi
+oof";
+
+#[test]
+fn test_tail_call_becomes_jump() {
+    let src = format!("{}\nexit count(3, 0)", COUNT);
+    assert_eq!(0, calls(&src, Options::default()));
+    assert_eq!(1, calls(&src, no_tco()));
+}
+
+// the jit's stack is 8MB, ten million frames wouldn't come close to fitting
+#[test]
+fn test_tail_call_constant_stack() {
+    let src = format!("{}\nexit count(10000000, 0) - 9999990", COUNT);
+    assert_eq!(10, jit(&src, Options::default()));
+}
+
+#[test]
+fn test_tail_call_at_level_0() {
+    let src = format!("{}\nexit count(1000000, 0) - 999990", COUNT);
+    assert_eq!(10, jit(&src, Options::level(0)));
+}
+
+#[test]
+fn test_tail_call_swaps_args() {
+    // both arguments read both params, so neither can be set before the
+    // other is worked out
+    let src = "noinline foo swap(n, a, b)
+  return a * 10 + b
+  if n != 0
+    return swap(n - 1, b, a)
+  fi
+oof
+exit swap(3, 1, 2) + swap(4, 1, 2)";
+    assert_eq!(0, calls(src, Options::default()));
+    assert_eq!(interpret(src), jit(src, Options::default()));
+    assert_eq!(33, jit(src, Options::default()));
+}
+
+#[test]
+fn test_not_tail_calls() {
+    // the result is added to, and a statement runs after the return
+    let src = "noinline foo sum(n)
+  r = n
+  if n != 0
+    r = n + sum(n - 1)
+  fi
+  return r
+oof
+noinline foo last(n)
+  r = 0
+  if n != 0
+    return last(n - 1)
+    r = 1
+  fi
+  return r + n
+oof
+exit sum(10) + last(5)";
+    assert_eq!(2, calls(src, Options::default()));
+    assert_eq!(interpret(src), jit(src, Options::default()));
+    assert_eq!(61, jit(src, Options::default()));
+}
@@ -53,3 +53,79 @@ fn test_func_lex() -> Result<()> {
     assert_eq!(&target, &out);
     Ok(())
 }
+
+#[test]
+fn test_bitwise_lex() -> Result<()> {
+    let out = Lexer::lex("a&b|~c^1<<2>>3".to_string())?;
+    let target = vec![
+        TokenKind::VarName("a".to_string()),
+        TokenKind::Operator("&".to_string()),
+        TokenKind::VarName("b".to_string()),
+        TokenKind::Operator("|".to_string()),
+        TokenKind::Operator("~".to_string()),
+        TokenKind::VarName("c".to_string()),
+        TokenKind::Operator("^".to_string()),
+        TokenKind::Int("1".to_string()),
+        TokenKind::Operator("<<".to_string()),
+        TokenKind::Int("2".to_string()),
+        TokenKind::Operator(">>".to_string()),
+        TokenKind::Int("3".to_string()),
+    ];
+    assert_eq!(&target, &out);
+    Ok(())
+}
+
+#[test]
+fn test_literal_lex() -> Result<()> {
+    let out = Lexer::lex("0x7f 0B101 0o17 1_000_000 'a' '\\n' 0x_ff".to_string())?;
+    let target = vec![
+        TokenKind::Int("127".to_string()),
+        TokenKind::Int("5".to_string()),
+        TokenKind::Int("15".to_string()),
+        TokenKind::Int("1000000".to_string()),
+        TokenKind::Int("97".to_string()),
+        TokenKind::Int("10".to_string()),
+        TokenKind::Int("255".to_string()),
+    ];
+    assert_eq!(&target, &out);
+    Ok(())
+}
+
+#[test]
+fn test_bad_literals() {
+    for bad in [
+        "9223372036854775808",
+        "0x8000000000000000",
+        "0b2",
+        "0x",
+        "''",
+        "'ab'",
+    ] {
+        assert!(
+            Lexer::lex(bad.to_string()).is_err(),
+            "{} should not lex",
+            bad
+        );
+    }
+    assert!(Lexer::lex("9223372036854775807".to_string()).is_ok());
+    let e = Lexer::lex_line("x = 0b2".to_string(), 4).unwrap_err();
+    assert_eq!("4: invalid integer literal 0b2", e.to_string());
+}
+
+#[test]
+fn test_compound_lex() -> Result<()> {
+    let out = Lexer::lex("+= -= *= /= %= ++ -- + ->".to_string())?;
+    let target = vec![
+        TokenKind::AssignOp("+".to_string()),
+        TokenKind::AssignOp("-".to_string()),
+        TokenKind::AssignOp("*".to_string()),
+        TokenKind::AssignOp("/".to_string()),
+        TokenKind::AssignOp("%".to_string()),
+        TokenKind::Increment("+".to_string()),
+        TokenKind::Increment("-".to_string()),
+        TokenKind::Operator("+".to_string()),
+        TokenKind::Range,
+    ];
+    assert_eq!(&target, &out);
+    Ok(())
+}
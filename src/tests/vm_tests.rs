@@ -0,0 +1,72 @@
+use super::parse_src;
+use crate::bytecode::{Compiler, Op};
+use crate::parser::StatementNode;
+use crate::vm::Vm;
+use std::io::Result;
+use std::mem::size_of;
+
+fn compile_src(src: &str) -> Result<Vec<Op>> {
+    Compiler::new().compile(parse_src(src)?)
+}
+
+fn run(program: Vec<StatementNode>) -> (i64, String) {
+    let code = Compiler::new().compile(program).unwrap();
+    let mut out: Vec<u8> = Vec::new();
+    let exit_code = Vm::new(code, &mut out).run().unwrap();
+    (exit_code, String::from_utf8(out).unwrap())
+}
+
+fn run_src(src: &str) -> (i64, String) {
+    run(parse_src(src).unwrap())
+}
+
+#[test]
+fn test_vm_calls() {
+    let (code, out) = run_src(
+        "foo fib(n)
+  out = n
+  if n != 0
+    if n != 1
+      out = fib(n - 1) + fib(n - 2)
+    fi
+  fi
+  return out
+oof
+foo stop()
+  exit fib(12)
+oof
+_ = print(stop())",
+    );
+    assert_eq!(144, code);
+    assert_eq!("", out);
+}
+
+#[test]
+fn test_vm_compile_errors() {
+    let errors = [
+        ("x = y", "variable y not found in this scope"),
+        ("x = 1\nfi", "2: no if to close"),
+        ("while 1", "block is never closed"),
+        ("x = nope(1)", "function nope undefined"),
+        ("x = 1\nexit x[0]", "x is not an array"),
+        (
+            "x = 1 + [1, 2]",
+            "arrays can only be assigned or looped over",
+        ),
+    ];
+    for (src, message) in errors {
+        assert_eq!(message, compile_src(src).unwrap_err().to_string());
+    }
+}
+
+#[test]
+fn test_vm_division_by_zero() {
+    let code = compile_src("x = 0\nexit 1 / x").unwrap();
+    let err = Vm::new(code, Vec::new()).run().unwrap_err();
+    assert_eq!("runtime error: line 2: division by zero", err.to_string());
+}
+
+#[test]
+fn test_op_size() {
+    assert!(size_of::<Op>() <= 16);
+}
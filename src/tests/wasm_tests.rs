@@ -0,0 +1,600 @@
+use super::parse_src;
+use crate::parser::source_lines;
+use crate::runtime::DIV_ZERO;
+use crate::wasm::{WasmGenerator, WasmModule};
+use std::io::Result;
+
+fn generate_src(src: &str) -> Result<WasmModule> {
+    WasmGenerator::new().generate(parse_src(src)?)
+}
+
+// a small decoder for the subset of wasm the backend emits. it checks the
+// module is well formed as it goes and keeps enough to run it after
+
+#[derive(Debug, Clone, Copy)]
+struct Op {
+    code: u8,
+    // the immediate, the offset for loads and stores
+    imm: i64,
+}
+
+#[derive(Debug)]
+struct Code {
+    locals: Vec<u8>,
+    ops: Vec<Op>,
+    // index of the matching end for each block, loop and if
+    ends: Vec<usize>,
+}
+
+#[derive(Debug, Default)]
+struct Module {
+    types: Vec<(Vec<u8>, Vec<u8>)>,
+    imports: Vec<(String, u32)>,
+    funcs: Vec<u32>,
+    globals: Vec<i64>,
+    exports: Vec<(String, u8, u32)>,
+    codes: Vec<Code>,
+}
+
+impl Module {
+    fn signature(&self, func: u32) -> &(Vec<u8>, Vec<u8>) {
+        let imports = self.imports.len() as u32;
+        match func < imports {
+            true => &self.types[self.imports[func as usize].1 as usize],
+            false => &self.types[self.funcs[(func - imports) as usize] as usize],
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+type Decoded<T> = std::result::Result<T, String>;
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Decoded<u8> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| format!("unexpected end at {}", self.pos))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn u32(&mut self) -> Decoded<u32> {
+        let mut value: u64 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(value).map_err(|_| "u32 too big".to_string());
+            }
+        }
+        Err("u32 leb too long".to_string())
+    }
+
+    fn s64(&mut self, bits: u32) -> Decoded<i64> {
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+            if shift >= bits + 7 {
+                return Err("signed leb too long".to_string());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Decoded<String> {
+        let len = self.u32()? as usize;
+        let end = self.pos + len;
+        let name = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| "name runs off the end".to_string())?;
+        self.pos = end;
+        String::from_utf8(name.to_vec()).map_err(|_| "name isnt utf8".to_string())
+    }
+
+    fn val_type(&mut self) -> Decoded<u8> {
+        match self.byte()? {
+            ty @ (0x7E | 0x7F) => Ok(ty),
+            ty => Err(format!("unknown value type {:#x}", ty)),
+        }
+    }
+
+    fn val_types(&mut self) -> Decoded<Vec<u8>> {
+        (0..self.u32()?).map(|_| self.val_type()).collect()
+    }
+
+    fn op(&mut self) -> Decoded<Op> {
+        let code = self.byte()?;
+        let imm = match code {
+            0x02..=0x04 => match self.byte()? {
+                0x40 => 0,
+                ty => return Err(format!("unexpected block type {:#x}", ty)),
+            },
+            0x0C | 0x0D | 0x10 | 0x20..=0x24 => self.u32()? as i64,
+            0x29 | 0x37 => {
+                if self.u32()? != 3 {
+                    return Err("i64 accesses should be 8 byte aligned".to_string());
+                }
+                self.u32()? as i64
+            }
+            0x3F | 0x40 => match self.byte()? {
+                0 => 0,
+                _ => return Err("only memory 0 exists".to_string()),
+            },
+            0x41 => self.s64(32)?,
+            0x42 => self.s64(64)?,
+            0x00
+            | 0x0B
+            | 0x1A
+            | 0x45
+            | 0x47
+            | 0x4D
+            | 0x50..=0x57
+            | 0x6A..=0x76
+            | 0x7C..=0x87
+            | 0xA7
+            | 0xAD => 0,
+            code => return Err(format!("unknown opcode {:#x}", code)),
+        };
+        Ok(Op { code, imm })
+    }
+}
+
+fn decode_code(reader: &mut Reader, module: &Module, func: usize) -> Decoded<Code> {
+    let size = reader.u32()? as usize;
+    let end = reader.pos + size;
+    let mut locals = Vec::new();
+    for _ in 0..reader.u32()? {
+        let count = reader.u32()?;
+        let ty = reader.val_type()?;
+        locals.extend(std::iter::repeat_n(ty, count as usize));
+    }
+    let (params, _) = &module.types[module.funcs[func] as usize];
+    let local_count = (params.len() + locals.len()) as i64;
+    let func_count = (module.imports.len() + module.funcs.len()) as i64;
+    let mut ops = Vec::new();
+    let mut open = Vec::new();
+    let mut ends = Vec::new();
+    while reader.pos < end {
+        let op = reader.op()?;
+        let index = ops.len();
+        ends.push(0);
+        match op.code {
+            0x02..=0x04 => open.push(index),
+            0x0B => match open.pop() {
+                Some(start) => ends[start] = index,
+                None if reader.pos == end => (),
+                None => return Err("end with nothing open".to_string()),
+            },
+            0x0C | 0x0D if op.imm > open.len() as i64 => {
+                return Err(format!("branch depth {} too deep", op.imm))
+            }
+            0x10 if op.imm >= func_count => return Err(format!("no function {}", op.imm)),
+            0x20..=0x22 if op.imm >= local_count => return Err(format!("no local {}", op.imm)),
+            0x23 | 0x24 if op.imm >= module.globals.len() as i64 => {
+                return Err(format!("no global {}", op.imm))
+            }
+            _ => (),
+        }
+        ops.push(op);
+    }
+    match (reader.pos == end, open.is_empty(), ops.last()) {
+        (true, true, Some(Op { code: 0x0B, .. })) => Ok(Code { locals, ops, ends }),
+        _ => Err(format!("function {} body is malformed", func)),
+    }
+}
+
+fn decode(bytes: &[u8]) -> Decoded<Module> {
+    if bytes.get(..8) != Some(b"\0asm\x01\0\0\0") {
+        return Err("bad magic or version".to_string());
+    }
+    let mut reader = Reader { bytes, pos: 8 };
+    let mut module = Module::default();
+    let mut last_id = 0;
+    while reader.pos < bytes.len() {
+        let id = reader.byte()?;
+        if id <= last_id {
+            return Err(format!("section {} out of order", id));
+        }
+        last_id = id;
+        let size = reader.u32()? as usize;
+        let end = reader.pos + size;
+        if end > bytes.len() {
+            return Err(format!("section {} runs off the end", id));
+        }
+        match id {
+            1 => {
+                for _ in 0..reader.u32()? {
+                    if reader.byte()? != 0x60 {
+                        return Err("expected a function type".to_string());
+                    }
+                    module
+                        .types
+                        .push((reader.val_types()?, reader.val_types()?));
+                }
+            }
+            2 => {
+                for _ in 0..reader.u32()? {
+                    let name = format!("{}.{}", reader.name()?, reader.name()?);
+                    if reader.byte()? != 0x00 {
+                        return Err("only functions are imported".to_string());
+                    }
+                    module.imports.push((name, reader.u32()?));
+                }
+            }
+            3 => {
+                for _ in 0..reader.u32()? {
+                    module.funcs.push(reader.u32()?);
+                }
+            }
+            5 => {
+                if (reader.u32()?, reader.byte()?) != (1, 0x00) {
+                    return Err("expected one memory with no maximum".to_string());
+                }
+                reader.u32()?;
+            }
+            6 => {
+                for _ in 0..reader.u32()? {
+                    reader.val_type()?;
+                    reader.byte()?;
+                    let init = reader.op()?;
+                    if reader.op()?.code != 0x0B {
+                        return Err("global init should be one constant".to_string());
+                    }
+                    module.globals.push(init.imm);
+                }
+            }
+            7 => {
+                for _ in 0..reader.u32()? {
+                    module
+                        .exports
+                        .push((reader.name()?, reader.byte()?, reader.u32()?));
+                }
+            }
+            10 => {
+                if reader.u32()? as usize != module.funcs.len() {
+                    return Err("function and code counts differ".to_string());
+                }
+                for func in 0..module.funcs.len() {
+                    let code = decode_code(&mut reader, &module, func)?;
+                    module.codes.push(code);
+                }
+            }
+            id => return Err(format!("unexpected section {}", id)),
+        }
+        if reader.pos != end {
+            return Err(format!("section {} has the wrong size", id));
+        }
+    }
+    if module.types.iter().any(|(_, results)| results.len() > 1)
+        || module
+            .funcs
+            .iter()
+            .any(|ty| *ty as usize >= module.types.len())
+        || module
+            .imports
+            .iter()
+            .any(|(_, ty)| *ty as usize >= module.types.len())
+    {
+        return Err("bad function types".to_string());
+    }
+    Ok(module)
+}
+
+// runs main with the imports hooked up, like a host would. a zero divisor
+// traps with the message the other backends print
+
+enum Stop {
+    Exit(i64),
+    Trap(String),
+}
+
+struct Machine<'a> {
+    module: &'a Module,
+    memory: Vec<u8>,
+    globals: Vec<i64>,
+    out: String,
+}
+
+// i32 values sit on the stack sign extended
+fn i32_of(value: i64) -> i64 {
+    value as i32 as i64
+}
+
+impl<'a> Machine<'a> {
+    fn address(&self, base: i64, offset: i64) -> std::result::Result<usize, Stop> {
+        let address = base as u32 as usize + offset as usize;
+        match address + 8 <= self.memory.len() {
+            true => Ok(address),
+            false => Err(Stop::Trap(format!("out of bounds access at {}", address))),
+        }
+    }
+
+    fn call(&mut self, func: u32, args: Vec<i64>) -> std::result::Result<Option<i64>, Stop> {
+        let module = self.module;
+        match module
+            .imports
+            .get(func as usize)
+            .map(|(name, _)| name.as_str())
+        {
+            Some("env.print") => {
+                self.out += &format!("{}\n", args[0]);
+                return Ok(None);
+            }
+            Some("env.exit") => return Err(Stop::Exit(args[0])),
+            Some("env.div_zero") => {
+                let message = format!("{}{}{}", DIV_ZERO.0, args[0], DIV_ZERO.1);
+                return Err(Stop::Trap(message.trim_end().to_string()));
+            }
+            Some(name) => return Err(Stop::Trap(format!("unknown import {}", name))),
+            None => (),
+        }
+        let code = &module.codes[func as usize - module.imports.len()];
+        let results = module.signature(func).1.len();
+        let mut locals = args;
+        locals.extend(code.locals.iter().map(|_| 0));
+        let mut stack: Vec<i64> = Vec::new();
+        // where a branch to each open label continues and whether it loops
+        let mut labels: Vec<(usize, bool)> = Vec::new();
+        let mut pc = 0;
+        macro_rules! pop {
+            () => {
+                stack
+                    .pop()
+                    .ok_or_else(|| Stop::Trap("stack underflow".to_string()))?
+            };
+        }
+        macro_rules! binary {
+            ($f:expr) => {{
+                let rh = pop!();
+                let lh = pop!();
+                stack.push($f(lh, rh));
+            }};
+        }
+        loop {
+            let op = code.ops[pc];
+            pc += 1;
+            match op.code {
+                0x00 => return Err(Stop::Trap("unreachable".to_string())),
+                0x02 => labels.push((code.ends[pc - 1] + 1, false)),
+                0x03 => labels.push((pc, true)),
+                0x04 => match pop!() {
+                    0 => pc = code.ends[pc - 1] + 1,
+                    _ => labels.push((code.ends[pc - 1] + 1, false)),
+                },
+                0x0B => {
+                    if labels.pop().is_none() {
+                        break;
+                    }
+                }
+                0x0C | 0x0D => {
+                    if op.code == 0x0D && pop!() == 0 {
+                        continue;
+                    }
+                    let depth = op.imm as usize;
+                    let (target, looping) = labels[labels.len() - 1 - depth];
+                    labels.truncate(labels.len() - depth - !looping as usize);
+                    pc = target;
+                }
+                0x10 => {
+                    let params = module.signature(op.imm as u32).0.len();
+                    let args = stack.split_off(stack.len() - params);
+                    if let Some(value) = self.call(op.imm as u32, args)? {
+                        stack.push(value);
+                    }
+                }
+                0x1A => {
+                    pop!();
+                }
+                0x20 => stack.push(locals[op.imm as usize]),
+                0x21 => locals[op.imm as usize] = pop!(),
+                0x22 => locals[op.imm as usize] = *stack.last().unwrap(),
+                0x23 => stack.push(self.globals[op.imm as usize]),
+                0x24 => self.globals[op.imm as usize] = pop!(),
+                0x29 => {
+                    let address = self.address(pop!(), op.imm)?;
+                    let bytes = self.memory[address..address + 8].try_into().unwrap();
+                    stack.push(i64::from_le_bytes(bytes));
+                }
+                0x37 => {
+                    let value = pop!();
+                    let address = self.address(pop!(), op.imm)?;
+                    self.memory[address..address + 8].copy_from_slice(&value.to_le_bytes());
+                }
+                0x3F => stack.push((self.memory.len() / 65536) as i64),
+                0x40 => {
+                    let pages = self.memory.len() / 65536;
+                    let grow = pop!() as u32 as usize;
+                    match pages + grow <= 1024 {
+                        true => {
+                            self.memory.resize((pages + grow) * 65536, 0);
+                            stack.push(pages as i64);
+                        }
+                        false => stack.push(-1),
+                    }
+                }
+                0x41 | 0x42 => stack.push(op.imm),
+                0x45 => {
+                    let value = pop!();
+                    stack.push((i32_of(value) == 0) as i64);
+                }
+                0x47 => binary!(|lh, rh| (i32_of(lh) != i32_of(rh)) as i64),
+                0x4D => binary!(|lh: i64, rh: i64| ((lh as u32) <= (rh as u32)) as i64),
+                0x50 => {
+                    let value = pop!();
+                    stack.push((value == 0) as i64);
+                }
+                0x51 => binary!(|lh, rh| (lh == rh) as i64),
+                0x52 => binary!(|lh, rh| (lh != rh) as i64),
+                0x53 => binary!(|lh, rh| (lh < rh) as i64),
+                0x55 => binary!(|lh, rh| (lh > rh) as i64),
+                0x57 => binary!(|lh, rh| (lh <= rh) as i64),
+                0x6A => binary!(|lh: i64, rh: i64| i32_of(lh.wrapping_add(rh))),
+                0x6B => binary!(|lh: i64, rh: i64| i32_of(lh.wrapping_sub(rh))),
+                0x71 => binary!(|lh, rh| lh & rh),
+                0x72 => binary!(|lh, rh| lh | rh),
+                0x74 => binary!(|lh: i64, rh: i64| i32_of(((lh as u32) << (rh & 31)) as i64)),
+                0x76 => binary!(|lh: i64, rh: i64| i32_of(((lh as u32) >> (rh & 31)) as i64)),
+                0x7C => binary!(|lh: i64, rh| lh.wrapping_add(rh)),
+                0x7D => binary!(|lh: i64, rh| lh.wrapping_sub(rh)),
+                0x7E => binary!(|lh: i64, rh| lh.wrapping_mul(rh)),
+                0x7F | 0x81 => {
+                    let rh = pop!();
+                    let lh = pop!();
+                    if rh == 0 {
+                        return Err(Stop::Trap("integer divide by zero".to_string()));
+                    }
+                    stack.push(match op.code {
+                        0x7F => lh.wrapping_div(rh),
+                        _ => lh.wrapping_rem(rh),
+                    });
+                }
+                0x83 => binary!(|lh, rh| lh & rh),
+                0x84 => binary!(|lh, rh| lh | rh),
+                0x85 => binary!(|lh, rh| lh ^ rh),
+                0x86 => binary!(|lh: i64, rh| lh.wrapping_shl(rh as u32)),
+                0x87 => binary!(|lh: i64, rh| lh.wrapping_shr(rh as u32)),
+                0xA7 => {
+                    let value = pop!();
+                    stack.push(i32_of(value));
+                }
+                0xAD => {
+                    let value = pop!();
+                    stack.push(value as u32 as i64);
+                }
+                code => return Err(Stop::Trap(format!("cant run opcode {:#x}", code))),
+            }
+        }
+        match (results, stack.len()) {
+            (0, 0) => Ok(None),
+            (1, 1) => Ok(stack.pop()),
+            _ => Err(Stop::Trap(
+                "wrong number of values left on the stack".to_string(),
+            )),
+        }
+    }
+}
+
+// decodes the module and runs main, giving the exit code and what printed
+// or the message it trapped with
+fn execute(wasm: &[u8]) -> std::result::Result<(i64, String), String> {
+    let module = decode(wasm).unwrap();
+    let main = module
+        .exports
+        .iter()
+        .find(|(name, _, _)| name == "main")
+        .map(|(_, kind, index)| (*kind, *index));
+    let (0x00, main) = main.expect("main isnt exported") else {
+        panic!("main isnt a function")
+    };
+    assert!(module
+        .exports
+        .iter()
+        .any(|export| export == &("memory".to_string(), 0x02, 0)));
+    let mut machine = Machine {
+        module: &module,
+        memory: vec![0; 65536],
+        globals: module.globals.clone(),
+        out: String::new(),
+    };
+    let code = match machine.call(main, Vec::new()) {
+        Ok(Some(code)) => code,
+        Ok(None) => panic!("main returned nothing"),
+        Err(Stop::Exit(code)) => code,
+        Err(Stop::Trap(e)) => return Err(e),
+    };
+    Ok((code, machine.out))
+}
+
+pub(super) fn run_wasm(wasm: &[u8]) -> (i64, String) {
+    execute(wasm).unwrap_or_else(|e| panic!("trapped: {}", e))
+}
+
+// 20000 slots is more than the first page so the memory has to grow
+#[test]
+fn test_wasm_memory_grows() {
+    let src = "big = range(20000)
+total = 0
+i = 0
+while i != 20000
+  total += big[i]
+  i++
+elihw
+exit (total + big[19999]) % 256";
+    let expected = ((0..20000).sum::<i64>() + 19999) % 256;
+    assert_eq!(expected, run_wasm(&generate_src(src).unwrap().wasm).0);
+}
+
+#[test]
+fn test_wasm_text_matches_binary() {
+    let src = "foo double(n)
+  return n * 2
+oof
+x = [1, 2, 3]
+exit double(x[2])";
+    let module = generate_src(src).unwrap();
+    assert!(module
+        .wat
+        .contains("(import \"env\" \"print\" (func $print (param i64)))"));
+    assert!(module
+        .wat
+        .contains("(func $main (export \"main\") (result i32)"));
+    assert!(module
+        .wat
+        .contains("(func $f_double (param i64) (result i64) (local i32 i64)"));
+    // one line per instruction, the binary also has each function's end
+    let decoded = decode(&module.wasm).unwrap();
+    let functions: Vec<&str> = module.wat.split("  (func ").skip(1).collect();
+    assert_eq!(decoded.codes.len(), functions.len());
+    for (code, text) in decoded.codes.iter().zip(functions) {
+        let lines = text.lines().skip(1).filter(|line| line.starts_with("    "));
+        assert_eq!(code.ops.len() - 1, lines.count());
+    }
+}
+
+#[test]
+fn test_wasm_decoder_rejects_bad_modules() {
+    let wasm = generate_src("exit 3").unwrap().wasm;
+    assert!(decode(&wasm).is_ok());
+    assert!(decode(&wasm[..wasm.len() - 1]).is_err());
+    assert!(decode(&wasm[4..]).is_err());
+    let mut corrupt = wasm.clone();
+    *corrupt.last_mut().unwrap() = 0xFF;
+    assert!(decode(&corrupt).is_err());
+}
+
+// a number has no slots to index, even the first
+#[test]
+fn test_wasm_index_number() {
+    for src in ["x = 1\nexit x[2]", "x = 1\nx[0] = 2", "x = 1\nx[1] += 2"] {
+        let err = generate_src(src).unwrap_err();
+        assert_eq!("x is not an array", err.to_string());
+    }
+}
+
+// a zero divisor calls out to the host with the line it was on
+#[test]
+fn test_wasm_division_by_zero() {
+    let src = "x = 4\n\ny = x - 4\n\n\nexit x / y";
+    let code: Vec<String> = src.lines().map(|line| line.to_string()).collect();
+    let module = WasmGenerator::with_lines(&source_lines(&code))
+        .generate(parse_src(src).unwrap())
+        .unwrap();
+    assert_eq!(
+        Err("runtime error: line 6: division by zero".to_string()),
+        execute(&module.wasm)
+    );
+}
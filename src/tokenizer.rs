@@ -1,13 +1,17 @@
-use std::io::Result;
+use std::io::{Error, Result};
 use std::iter::Peekable;
+use std::num::IntErrorKind;
 use std::vec::IntoIter;
 
-use crate::error::new_error;
+use crate::error::{new_error, syntax_error};
+use crate::runtime::SENTINEL;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TokenKind {
     Func,
     EndFunc,
+    Inline,
+    NoInline,
     Return,
     Size,
     If,
@@ -26,6 +30,8 @@ pub enum TokenKind {
     OpenParen,
     CloseParen,
     Range,
+    AssignOp(String),
+    Increment(String),
     VarName(String),
     Int(String),
     Operator(String),
@@ -38,15 +44,35 @@ pub struct Lexer {
 
 impl Lexer {
     pub fn lex(code: String) -> Result<Vec<TokenKind>> {
-        Self {
-            chars: code.into_bytes().into_iter().peekable(),
-        }
-        .lex_code()
+        let tokens = Self::lex_spelled(code)?;
+        Ok(tokens.into_iter().map(|(token, _)| token).collect())
+    }
+
+    // lexes a line of a file, with errors that say which line like the
+    // parser's do
+    pub fn lex_line(code: String, line_num: usize) -> Result<Vec<TokenKind>> {
+        Self::lex(code).map_err(|e| syntax_error(&e.to_string(), line_num))
+    }
+
+    // each token with the text it was lexed from, so the formatter can
+    // write literals the way they were written
+    pub fn lex_spelled(code: String) -> Result<Vec<(TokenKind, String)>> {
+        let mut lexer = Self {
+            chars: code.clone().into_bytes().into_iter().peekable(),
+        };
+        let tokens = lexer.lex_code()?;
+        Ok(tokens
+            .into_iter()
+            .map(|(token, start, end)| (token, code[start..end].to_string()))
+            .collect())
     }
 
-    fn lex_code(&mut self) -> Result<Vec<TokenKind>> {
-        let mut tokens: Vec<TokenKind> = Vec::new();
+    // the tokens and where each starts and ends
+    fn lex_code(&mut self) -> Result<Vec<(TokenKind, usize, usize)>> {
+        let len = self.chars.len();
+        let mut tokens = Vec::new();
         loop {
+            let start = len - self.chars.len();
             let byte = match self.chars.next() {
                 Some(byte) => byte,
                 None => break,
@@ -59,14 +85,16 @@ impl Lexer {
                 b';' => Ok(TokenKind::EndLine),
                 b'(' => Ok(TokenKind::OpenParen),
                 b')' => Ok(TokenKind::CloseParen),
-                b'=' | b'!' | b'+' | b'-' | b'/' | b'*' | b'%' => Ok(self.lex_op(byte)),
-                b'0'..=b'9' => Ok(self.lex_int(byte)),
+                b'=' | b'!' | b'+' | b'-' | b'/' | b'*' | b'%' | b'&' | b'|' | b'^' | b'~'
+                | b'<' | b'>' => Ok(self.lex_op(byte)),
+                b'0'..=b'9' => self.lex_int(byte),
+                b'\'' => self.lex_char(),
                 b'a'..=b'z' | b'A'..=b'Z' | b'_' => Ok(self.lex_word(byte)),
                 bad_token => Err(new_error(
                     format!("bad token {}", bad_token as char).as_str(),
                 )),
             }?;
-            tokens.push(token)
+            tokens.push((token, start, len - self.chars.len()))
         }
         Ok(tokens)
     }
@@ -76,6 +104,8 @@ impl Lexer {
             b'=' => self.lex_equals(byte),
             b'-' => self.lex_dash(byte),
             b'!' => self.lex_bang(byte),
+            b'<' | b'>' => self.lex_shift(byte),
+            b'+' | b'*' | b'/' | b'%' => self.lex_arith(byte),
             _ => TokenKind::Operator(String::from(byte as char)),
         }
     }
@@ -89,9 +119,32 @@ impl Lexer {
         match next {
             b'>' => {
                 self.chars.next();
-                return TokenKind::Range;
+                TokenKind::Range
             }
-            _ => return TokenKind::Operator(op),
+            b'=' => {
+                self.chars.next();
+                TokenKind::AssignOp(op)
+            }
+            b'-' => {
+                self.chars.next();
+                TokenKind::Increment(op)
+            }
+            _ => TokenKind::Operator(op),
+        }
+    }
+
+    fn lex_arith(&mut self, byte: u8) -> TokenKind {
+        let op = String::from(byte as char);
+        match self.chars.peek() {
+            Some(b'=') => {
+                self.chars.next();
+                TokenKind::AssignOp(op)
+            }
+            Some(b'+') if byte == b'+' => {
+                self.chars.next();
+                TokenKind::Increment(op)
+            }
+            _ => TokenKind::Operator(op),
         }
     }
 
@@ -121,7 +174,47 @@ impl Lexer {
         TokenKind::Operator(op)
     }
 
-    fn lex_int(&mut self, byte: u8) -> TokenKind {
+    fn lex_shift(&mut self, byte: u8) -> TokenKind {
+        let mut op = String::from(byte as char);
+        if self.chars.peek() == Some(&byte) {
+            op.push(self.chars.next().unwrap() as char);
+        }
+        TokenKind::Operator(op)
+    }
+
+    fn lex_int(&mut self, byte: u8) -> Result<TokenKind> {
+        let radix = match (byte, self.chars.peek()) {
+            (b'0', Some(b'x' | b'X')) => 16,
+            (b'0', Some(b'b' | b'B')) => 2,
+            (b'0', Some(b'o' | b'O')) => 8,
+            _ => return self.lex_decimal(byte),
+        };
+        let prefix = format!("0{}", self.chars.next().unwrap() as char);
+        let mut digits = String::new();
+        while let Some(next) = self.chars.peek() {
+            match next {
+                b'_' => {
+                    self.chars.next();
+                }
+                b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' => {
+                    digits.push(self.chars.next().unwrap() as char)
+                }
+                _ => break,
+            }
+        }
+        // normalise to decimal so nothing after the lexer needs to know about radixes
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) => Ok(TokenKind::Int(value.to_string())),
+            Err(e) if e.kind() == &IntErrorKind::PosOverflow => Err(Self::overflow_error(
+                format!("{}{}", prefix, digits).as_str(),
+            )),
+            Err(_) => Err(new_error(
+                format!("invalid integer literal {}{}", prefix, digits).as_str(),
+            )),
+        }
+    }
+
+    fn lex_decimal(&mut self, byte: u8) -> Result<TokenKind> {
         let mut int = String::from(byte as char);
         loop {
             let next = match self.chars.peek() {
@@ -130,10 +223,41 @@ impl Lexer {
             };
             match next {
                 b'0'..=b'9' => int.push(self.chars.next().unwrap() as char),
+                b'_' => {
+                    self.chars.next();
+                }
                 _ => break,
             }
         }
-        TokenKind::Int(int)
+        match int.parse::<i64>() {
+            Ok(_) => Ok(TokenKind::Int(int)),
+            Err(_) => Err(Self::overflow_error(&int)),
+        }
+    }
+
+    fn overflow_error(literal: &str) -> Error {
+        new_error(format!("integer literal {} does not fit in i64", literal).as_str())
+    }
+
+    fn lex_char(&mut self) -> Result<TokenKind> {
+        let value = match self.chars.next() {
+            Some(b'\\') => match self.chars.next() {
+                Some(b'n') => b'\n',
+                Some(b't') => b'\t',
+                Some(b'r') => b'\r',
+                Some(b'0') => 0,
+                Some(b'\\') => b'\\',
+                Some(b'\'') => b'\'',
+                _ => return Err(new_error("unknown escape in character literal")),
+            },
+            Some(b'\'') | None => return Err(new_error("empty character literal")),
+            Some(byte) if byte.is_ascii() => byte,
+            Some(_) => return Err(new_error("character literals must be ascii")),
+        };
+        match self.chars.next() {
+            Some(b'\'') => Ok(TokenKind::Int(value.to_string())),
+            _ => Err(new_error("expected ' to close character literal")),
+        }
     }
 
     fn lex_word(&mut self, byte: u8) -> TokenKind {
@@ -160,6 +284,8 @@ impl Lexer {
         match word {
             "foo" => TokenKind::Func,
             "oof" => TokenKind::EndFunc,
+            "inline" => TokenKind::Inline,
+            "noinline" => TokenKind::NoInline,
             "return" => TokenKind::Return,
             "size" => TokenKind::Size,
             "if" => TokenKind::If,
@@ -170,7 +296,7 @@ impl Lexer {
             "elihw" => TokenKind::EndWhile,
             "in" => TokenKind::In,
             "exit" => TokenKind::Exit,
-            "end" => TokenKind::Int("0x7F".to_string()),
+            "end" => TokenKind::Int(SENTINEL.to_string()),
             _ => TokenKind::VarName(word.to_string()),
         }
     }
@@ -0,0 +1,257 @@
+use crate::bytecode::Op;
+use crate::error::{new_error, runtime_error};
+use crate::runtime::{self, SENTINEL};
+use crate::syscall;
+
+use std::io::{Result, Write};
+
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i64),
+    // the elements followed by the sentinel slot, laid out like the stack
+    Array(Vec<i64>),
+}
+
+#[derive(Debug)]
+struct Frame {
+    return_pc: usize,
+    caller_base: usize,
+    returned: i64,
+}
+
+// runs the ops from the bytecode Compiler. values live on one stack and
+// each call's locals sit in one vec from its base upwards
+pub struct Vm<W: Write> {
+    code: Vec<Op>,
+    stack: Vec<i64>,
+    locals: Vec<Value>,
+    frames: Vec<Frame>,
+    out: W,
+}
+
+impl<W: Write> Vm<W> {
+    pub fn new(code: Vec<Op>, out: W) -> Self {
+        Self {
+            code,
+            stack: Vec::new(),
+            locals: Vec::new(),
+            frames: Vec::new(),
+            out,
+        }
+    }
+
+    #[inline]
+    fn pop(&mut self) -> i64 {
+        self.stack.pop().unwrap()
+    }
+
+    #[inline]
+    fn int(&self, slot: usize) -> i64 {
+        match &self.locals[slot] {
+            Value::Int(value) => *value,
+            Value::Array(slots) => slots[0],
+        }
+    }
+
+    fn element(&mut self, slot: usize, index: i64) -> Result<&mut i64> {
+        let element = match &mut self.locals[slot] {
+            Value::Int(value) if index == 0 => Some(value),
+            Value::Int(_) => None,
+            Value::Array(slots) => usize::try_from(index)
+                .ok()
+                .and_then(|index| slots.get_mut(index)),
+        };
+        element.ok_or_else(|| new_error(&format!("index {} is outside of the array", index)))
+    }
+
+    fn array(&mut self, slot: usize) -> Result<&mut Vec<i64>> {
+        match &mut self.locals[slot] {
+            Value::Array(slots) => Ok(slots),
+            Value::Int(_) => Err(new_error("array not found in this scope")),
+        }
+    }
+
+    // runs to the first exit and gives back its code
+    pub fn run(&mut self) -> Result<i64> {
+        let mut pc = 0;
+        let mut base = 0;
+        loop {
+            let op = self.code[pc];
+            pc += 1;
+            match op {
+                Op::Push(value) => self.stack.push(value),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Load(slot) => {
+                    let value = self.int(base + slot as usize);
+                    self.stack.push(value);
+                }
+                Op::Store(slot) => {
+                    let value = self.pop();
+                    self.locals[base + slot as usize] = Value::Int(value);
+                }
+                Op::LoadIndex(slot) => {
+                    let index = self.pop();
+                    let value = *self.element(base + slot as usize, index)?;
+                    self.stack.push(value);
+                }
+                Op::StoreIndex(slot) => {
+                    let index = self.pop();
+                    let value = self.pop();
+                    *self.element(base + slot as usize, index)? = value;
+                }
+                Op::Apply(slot, op) => {
+                    let value = self.pop();
+                    let element = self.element(base + slot as usize, 0)?;
+                    *element = op.apply(*element, value)?;
+                }
+                Op::ApplyIndex(slot, op) => {
+                    let index = self.pop();
+                    let value = self.pop();
+                    let element = self.element(base + slot as usize, index)?;
+                    *element = op.apply(*element, value)?;
+                }
+                Op::StoreArray(slot, len) => {
+                    let mut slots = self.stack.split_off(self.stack.len() - len as usize);
+                    slots.push(SENTINEL);
+                    self.locals[base + slot as usize] = Value::Array(slots);
+                }
+                Op::StoreEmpty(slot, size) => {
+                    self.locals[base + slot as usize] =
+                        Value::Array(vec![SENTINEL; size as usize + 1]);
+                }
+                Op::StoreRange(slot) => {
+                    let step = self.pop();
+                    let stop = self.pop();
+                    let mut value = self.pop();
+                    let mut slots = Vec::new();
+                    while (step > 0 && value < stop) || (step < 0 && value > stop) {
+                        slots.push(value);
+                        value += step;
+                    }
+                    slots.push(SENTINEL);
+                    self.locals[base + slot as usize] = Value::Array(slots);
+                }
+                Op::Binary(op) => {
+                    let rh = self.pop();
+                    let lh = self.stack.last_mut().unwrap();
+                    *lh = op.apply(*lh, rh)?;
+                }
+                Op::CheckDivisor(line) => {
+                    if self.stack.last() == Some(&0) {
+                        return Err(runtime_error("division by zero", line as usize));
+                    }
+                }
+                Op::Not => {
+                    let top = self.stack.last_mut().unwrap();
+                    *top = !*top;
+                }
+                Op::Jump(target) => pc = target as usize,
+                Op::JumpIfZero(target) => {
+                    if self.pop() == 0 {
+                        pc = target as usize;
+                    }
+                }
+                Op::ForRange(var, hidden, exit) => {
+                    let value = self.int(base + var as usize);
+                    let stop = self.int(base + hidden as usize);
+                    let step = self.int(base + hidden as usize + 1);
+                    let running = (step > 0 && value < stop) || (step < 0 && value > stop);
+                    if !running {
+                        pc = exit as usize;
+                    }
+                }
+                Op::NextRange(var, step, head) => {
+                    let step = self.int(base + step as usize);
+                    let element = self.element(base + var as usize, 0)?;
+                    *element = element.wrapping_add(step);
+                    pc = head as usize;
+                }
+                Op::ForArray(var, hidden, exit) => {
+                    let position = self.int(base + hidden as usize + 1);
+                    let value = match &self.locals[base + hidden as usize] {
+                        Value::Array(slots) => slots.get(position as usize).copied(),
+                        Value::Int(value) if position == 0 => Some(*value),
+                        Value::Int(_) => None,
+                    }
+                    .unwrap_or(SENTINEL);
+                    self.locals[base + var as usize] = Value::Int(value);
+                    self.locals[base + hidden as usize + 1] = Value::Int(position + 1);
+                    if value == SENTINEL {
+                        pc = exit as usize;
+                    }
+                }
+                Op::Call(target, argc) => {
+                    let args = self.stack.split_off(self.stack.len() - argc as usize);
+                    self.frames.push(Frame {
+                        return_pc: pc,
+                        caller_base: base,
+                        returned: 0,
+                    });
+                    base = self.locals.len();
+                    self.locals.extend(args.into_iter().map(Value::Int));
+                    pc = target as usize;
+                }
+                Op::Enter(size) => self.locals.resize(base + size as usize, Value::Int(0)),
+                Op::SetReturn => {
+                    let value = self.pop();
+                    if let Some(frame) = self.frames.last_mut() {
+                        frame.returned = value;
+                    }
+                }
+                Op::Ret => {
+                    let frame = self.frames.pop().unwrap();
+                    self.locals.truncate(base);
+                    base = frame.caller_base;
+                    pc = frame.return_pc;
+                    self.stack.push(frame.returned);
+                }
+                Op::Print => {
+                    let value = *self.stack.last().unwrap();
+                    writeln!(self.out, "{}", value)?;
+                }
+                Op::Exit => {
+                    self.out.flush()?;
+                    return Ok(self.pop());
+                }
+                Op::Open(slot) => {
+                    let mode = self.pop();
+                    let flags = self.pop();
+                    let path = match &self.locals[base + slot as usize] {
+                        Value::Array(slots) => runtime::path(slots),
+                        Value::Int(value) => runtime::path(&[*value]),
+                    };
+                    self.stack.push(syscall::open(&path, flags, mode));
+                }
+                Op::Read(slot) | Op::Write(slot) => {
+                    let count = self.pop();
+                    let fd = self.pop();
+                    let slots = self.array(base + slot as usize)?;
+                    let count = runtime::clamp(count, slots.len() - 1);
+                    let result = match op {
+                        Op::Read(_) => runtime::read(fd, slots, count),
+                        _ if fd == 1 => {
+                            let bytes = runtime::bytes(slots, count);
+                            self.out.write_all(&bytes)?;
+                            bytes.len() as i64
+                        }
+                        _ => syscall::write(fd, &runtime::bytes(slots, count)),
+                    };
+                    self.stack.push(result);
+                }
+                Op::Close => {
+                    let fd = self.pop();
+                    self.stack.push(syscall::close(fd));
+                }
+                Op::Unlink(slot) => {
+                    let path = match &self.locals[base + slot as usize] {
+                        Value::Array(slots) => runtime::path(slots),
+                        Value::Int(value) => runtime::path(&[*value]),
+                    };
+                    self.stack.push(syscall::unlink(&path));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::parser::{ExpressionNode, StatementNode};
+
+use std::collections::HashSet;
+
+fn reads(expr: &ExpressionNode, names: &mut HashSet<String>) {
+    match expr {
+        ExpressionNode::Value(_) | ExpressionNode::PreAllocArray(_) => (),
+        ExpressionNode::Var(name) => {
+            names.insert(name.clone());
+        }
+        ExpressionNode::Index(name, index) => {
+            names.insert(name.clone());
+            reads(index, names);
+        }
+        ExpressionNode::Callable(_, args) | ExpressionNode::Array(args) => {
+            args.iter().for_each(|arg| reads(arg, names))
+        }
+        ExpressionNode::Infix(lh, _, rh) => {
+            reads(lh, names);
+            reads(rh, names);
+        }
+        ExpressionNode::Prefix(_, expr) => reads(expr, names),
+    }
+}
+
+fn calls(expr: &ExpressionNode, names: &mut HashSet<String>) {
+    match expr {
+        ExpressionNode::Callable(name, args) => {
+            names.insert(name.clone());
+            args.iter().for_each(|arg| calls(arg, names));
+        }
+        ExpressionNode::Array(args) => args.iter().for_each(|arg| calls(arg, names)),
+        ExpressionNode::Index(_, expr) | ExpressionNode::Prefix(_, expr) => calls(expr, names),
+        ExpressionNode::Infix(lh, _, rh) => {
+            calls(lh, names);
+            calls(rh, names);
+        }
+        _ => (),
+    }
+}
+
+fn expressions(statement: &StatementNode) -> Vec<&ExpressionNode> {
+    match statement {
+        StatementNode::Return(expr)
+        | StatementNode::Exit(expr)
+        | StatementNode::Assign(_, expr)
+        | StatementNode::AssignOp(_, _, expr)
+        | StatementNode::For(_, expr)
+        | StatementNode::If(expr)
+        | StatementNode::While(expr) => vec![expr],
+        StatementNode::AssignIndex(_, index, expr)
+        | StatementNode::AssignIndexOp(_, index, _, expr) => {
+            vec![index, expr]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// variables in one scope, the top level or a function body
+#[derive(Default)]
+struct Scope {
+    // each variable with the statement that first sets it
+    written: Vec<(String, usize)>,
+    read: HashSet<String>,
+}
+
+impl Scope {
+    fn write(&mut self, name: &str, at: usize) {
+        if !self.written.iter().any(|(written, _)| written == name) {
+            self.written.push((name.to_string(), at));
+        }
+    }
+
+    fn unused(self) -> Vec<(usize, String)> {
+        self.written
+            .into_iter()
+            .filter(|(name, _)| !name.starts_with('_') && !self.read.contains(name))
+            .map(|(name, at)| (at, format!("{} is set but never read", name)))
+            .collect()
+    }
+}
+
+// things in the program that are almost certainly mistakes: statements
+// that can never run, a return whose value is always replaced by the next
+// one, variables that are set and never read, and functions nobody calls. names starting with _ are meant to be unused. each warning
+// comes with the index of the statement it is about
+pub fn warnings(program: &[StatementNode]) -> Vec<(usize, String)> {
+    let mut warnings = Vec::new();
+    let mut scopes = vec![Scope::default()];
+    let mut functions = Vec::new();
+    let mut called = HashSet::new();
+    // whether an exit came before, and a return earlier in the same block,
+    // for each open block
+    let mut blocks: Vec<(bool, bool)> = Vec::new();
+    let (mut exited, mut returned) = (false, false);
+    for (at, statement) in program.iter().enumerate() {
+        let closes = matches!(
+            statement,
+            StatementNode::EndIf
+                | StatementNode::EndWhile
+                | StatementNode::EndFor
+                | StatementNode::EndFunc
+        );
+        if closes {
+            (exited, returned) = blocks.pop().unwrap_or_default();
+        } else if exited {
+            warnings.push((at, "this never runs, it comes after an exit".to_string()));
+            exited = false;
+        } else if returned && matches!(statement, StatementNode::Return(_)) {
+            warnings.push((
+                at,
+                "this return replaces the value of the one before it".to_string(),
+            ));
+        }
+
+        let scope = scopes.last_mut().unwrap();
+        for expr in expressions(statement) {
+            reads(expr, &mut scope.read);
+            calls(expr, &mut called);
+        }
+        match statement {
+            StatementNode::Assign(name, _) | StatementNode::For(name, _) => scope.write(name, at),
+            StatementNode::Exit(_) => exited = true,
+            StatementNode::Return(_) => returned = true,
+            StatementNode::Func(name, args, _) => {
+                functions.push((name.clone(), at));
+                let mut scope = Scope::default();
+                scope.read.extend(args.iter().cloned());
+                scopes.push(scope);
+            }
+            StatementNode::EndFunc if scopes.len() > 1 => {
+                warnings.extend(scopes.pop().unwrap().unused());
+            }
+            _ => (),
+        }
+        match statement {
+            // a return in a block might not run, so it only replaces one
+            // before it in the same block
+            StatementNode::If(_) | StatementNode::While(_) | StatementNode::For(..) => {
+                blocks.push((exited, returned));
+                returned = false;
+            }
+            // a function body starts out reachable whatever came before it
+            StatementNode::Func(..) => {
+                blocks.push((exited, returned));
+                (exited, returned) = (false, false);
+            }
+            _ => (),
+        }
+    }
+    warnings.extend(scopes.swap_remove(0).unused());
+    for (name, at) in functions {
+        if !called.contains(&name) {
+            warnings.push((at, format!("function {} is never called", name)));
+        }
+    }
+    warnings.sort_by_key(|(at, _)| *at);
+    warnings
+}
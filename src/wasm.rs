@@ -0,0 +1,1162 @@
+use crate::error::new_error;
+use crate::parser::{ExpressionNode, StatementNode};
+use crate::runtime::SENTINEL;
+
+use std::collections::HashMap;
+use std::io::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+        }
+    }
+}
+
+// the instructions the backend uses, printed as wat or encoded as bytes from
+// the same list so the two never disagree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    // no immediates, the text name and the opcode
+    Plain(&'static str, u8),
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    // i64 load and store at an address plus a constant offset
+    Load(u32),
+    Store(u32),
+    Block,
+    Loop,
+    If,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Call(u32),
+}
+
+const UNREACHABLE: Instr = Instr::Plain("unreachable", 0x00);
+const DROP: Instr = Instr::Plain("drop", 0x1A);
+const I32_EQZ: Instr = Instr::Plain("i32.eqz", 0x45);
+const I32_NE: Instr = Instr::Plain("i32.ne", 0x47);
+const I32_LE_U: Instr = Instr::Plain("i32.le_u", 0x4D);
+const I64_EQZ: Instr = Instr::Plain("i64.eqz", 0x50);
+const I64_EQ: Instr = Instr::Plain("i64.eq", 0x51);
+const I64_NE: Instr = Instr::Plain("i64.ne", 0x52);
+const I64_LT_S: Instr = Instr::Plain("i64.lt_s", 0x53);
+const I64_GT_S: Instr = Instr::Plain("i64.gt_s", 0x55);
+const I64_LE_S: Instr = Instr::Plain("i64.le_s", 0x57);
+const I32_ADD: Instr = Instr::Plain("i32.add", 0x6A);
+const I32_SUB: Instr = Instr::Plain("i32.sub", 0x6B);
+const I32_AND: Instr = Instr::Plain("i32.and", 0x71);
+const I32_OR: Instr = Instr::Plain("i32.or", 0x72);
+const I32_SHL: Instr = Instr::Plain("i32.shl", 0x74);
+const I32_SHR_U: Instr = Instr::Plain("i32.shr_u", 0x76);
+const I64_ADD: Instr = Instr::Plain("i64.add", 0x7C);
+const I64_SUB: Instr = Instr::Plain("i64.sub", 0x7D);
+const I64_MUL: Instr = Instr::Plain("i64.mul", 0x7E);
+const I64_DIV_S: Instr = Instr::Plain("i64.div_s", 0x7F);
+const I64_REM_S: Instr = Instr::Plain("i64.rem_s", 0x81);
+const I64_AND: Instr = Instr::Plain("i64.and", 0x83);
+const I64_OR: Instr = Instr::Plain("i64.or", 0x84);
+const I64_XOR: Instr = Instr::Plain("i64.xor", 0x85);
+const I64_SHL: Instr = Instr::Plain("i64.shl", 0x86);
+const I64_SHR_S: Instr = Instr::Plain("i64.shr_s", 0x87);
+const I32_WRAP_I64: Instr = Instr::Plain("i32.wrap_i64", 0xA7);
+const I64_EXTEND_I32_U: Instr = Instr::Plain("i64.extend_i32_u", 0xAD);
+
+// function indices, the imports come first
+const PRINT: u32 = 0;
+const EXIT: u32 = 1;
+const DIV_ZERO: u32 = 2;
+const ALLOC: u32 = 3;
+const RUNNING: u32 = 4;
+const FILL: u32 = 5;
+const RANGE_LEN: u32 = 6;
+const RANGE_FILL: u32 = 7;
+const FIRST_FUNC: u32 = 8;
+
+// the only global, the top of the stack arrays are carved from. address 0
+// is left alone
+const SP: u32 = 0;
+const SP_START: i32 = 8;
+
+#[derive(Debug, Clone)]
+enum Operand {
+    // the value is left on the stack
+    Int,
+    // locals holding the address of the first slot and the length
+    Array(u32, u32),
+}
+
+#[derive(Debug, Clone)]
+enum Var {
+    Int(u32),
+    Array(u32, u32),
+}
+
+#[derive(Debug)]
+enum Block {
+    If,
+    While,
+    // loop var and the local holding the step
+    Range(String, u32),
+    Array,
+}
+
+#[derive(Debug, Default)]
+struct Function {
+    name: String,
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+    locals: Vec<ValType>,
+    // runs first, carves out the storage for the array literals
+    prologue: Vec<Instr>,
+    code: Vec<Instr>,
+    vars: HashMap<String, Var>,
+    blocks: Vec<Block>,
+    frame: Option<u32>,
+    returned: Option<u32>,
+}
+
+impl Function {
+    fn new(name: &str, params: &[ValType], results: &[ValType], locals: &[ValType]) -> Self {
+        Self {
+            name: name.to_string(),
+            params: params.to_vec(),
+            results: results.to_vec(),
+            locals: locals.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn body(&self) -> Vec<Instr> {
+        [&self.prologue[..], &self.code[..]].concat()
+    }
+}
+
+// a finished module as wat text and as the binary .wasm
+#[derive(Debug)]
+pub struct WasmModule {
+    pub wat: String,
+    pub wasm: Vec<u8>,
+}
+
+// lowers the parse tree to a webassembly module. numbers are i64, arrays
+// live in linear memory and print and exit are imported from the host as
+// env.print(i64) and env.exit(i32), along with env.div_zero(i64) which is
+// given the line of a zero divisor. main is exported along with the memory
+#[derive(Debug)]
+pub struct WasmGenerator {
+    functions: Vec<Function>,
+    done: Vec<Function>,
+    funcs: HashMap<String, (u32, usize)>,
+    // the line in the file of each statement, and of the one being generated
+    lines: Vec<usize>,
+    line: usize,
+}
+
+impl WasmGenerator {
+    pub fn new() -> Self {
+        Self {
+            functions: vec![Function::new("main", &[], &[ValType::I32], &[])],
+            done: Vec::new(),
+            funcs: HashMap::new(),
+            lines: Vec::new(),
+            line: 0,
+        }
+    }
+
+    // without the lines, statements are counted from 1 like the parser does
+    pub fn with_lines(lines: &[usize]) -> Self {
+        Self {
+            lines: lines.to_vec(),
+            ..Self::new()
+        }
+    }
+
+    fn current(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn local(&mut self, ty: ValType) -> u32 {
+        let function = self.current();
+        function.locals.push(ty);
+        (function.params.len() + function.locals.len() - 1) as u32
+    }
+
+    fn emit(&mut self, instrs: &[Instr]) {
+        self.current().code.extend_from_slice(instrs);
+    }
+
+    fn var(&mut self, name: &str) -> Result<Var> {
+        self.current()
+            .vars
+            .get(name)
+            .cloned()
+            .ok_or_else(|| new_error(&format!("variable {} not found in this scope", name)))
+    }
+
+    fn array_var(&mut self, name: &str) -> Result<u32> {
+        match self.var(name)? {
+            Var::Array(slots, _) => Ok(slots),
+            Var::Int(_) => Err(new_error(&format!("{} is not an array", name))),
+        }
+    }
+
+    fn generate_int(&mut self, expr: ExpressionNode) -> Result<()> {
+        match self.generate_expr(expr)? {
+            Operand::Int => Ok(()),
+            Operand::Array(..) => Err(new_error("arrays can only be assigned or looped over")),
+        }
+    }
+
+    // leaves the address of slots[index] on the stack
+    fn generate_address(&mut self, slots: u32, index: ExpressionNode) -> Result<()> {
+        self.emit(&[Instr::LocalGet(slots)]);
+        self.generate_int(index)?;
+        self.emit(&[I32_WRAP_I64, Instr::I32Const(3), I32_SHL, I32_ADD]);
+        Ok(())
+    }
+
+    fn generate_expr(&mut self, expr: ExpressionNode) -> Result<Operand> {
+        match expr {
+            ExpressionNode::Value(value) => self.emit(&[Instr::I64Const(
+                value
+                    .parse::<i64>()
+                    .map_err(|_| new_error(&format!("invalid integer {}", value)))?,
+            )]),
+            ExpressionNode::Var(name) => match self.var(&name)? {
+                Var::Int(local) => self.emit(&[Instr::LocalGet(local)]),
+                Var::Array(slots, _) => self.emit(&[Instr::LocalGet(slots), Instr::Load(0)]),
+            },
+            ExpressionNode::Index(name, index) => {
+                let slots = self.array_var(&name)?;
+                self.generate_address(slots, *index)?;
+                self.emit(&[Instr::Load(0)]);
+            }
+            ExpressionNode::Infix(lh, op, rh) => {
+                self.generate_int(*lh)?;
+                self.generate_int(*rh)?;
+                self.generate_infix_op(&op)?;
+            }
+            ExpressionNode::Prefix(op, expr) => match op.as_str() {
+                "~" => {
+                    self.generate_int(*expr)?;
+                    self.emit(&[Instr::I64Const(-1), I64_XOR]);
+                }
+                _ => return Err(new_error(&format!("unknown operator {}", op))),
+            },
+            ExpressionNode::Callable(name, args) => {
+                return self.generate_call(&name, args.into_iter().map(|arg| *arg).collect())
+            }
+            ExpressionNode::Array(exprs) => {
+                let len = exprs.len();
+                let slots = self.generate_storage(len);
+                for (i, expr) in exprs.into_iter().enumerate() {
+                    self.emit(&[Instr::LocalGet(slots)]);
+                    self.generate_int(*expr)?;
+                    self.emit(&[Instr::Store(i as u32 * 8)]);
+                }
+                self.emit(&[
+                    Instr::LocalGet(slots),
+                    Instr::I64Const(SENTINEL),
+                    Instr::Store(len as u32 * 8),
+                ]);
+                return Ok(self.array_operand(slots, len));
+            }
+            ExpressionNode::PreAllocArray(size) => {
+                let slots = self.generate_storage(size);
+                self.emit(&[
+                    Instr::LocalGet(slots),
+                    Instr::I64Const(size as i64 + 1),
+                    Instr::Call(FILL),
+                ]);
+                return Ok(self.array_operand(slots, size));
+            }
+        }
+        Ok(Operand::Int)
+    }
+
+    fn array_operand(&mut self, slots: u32, len: usize) -> Operand {
+        let len_local = self.local(ValType::I64);
+        self.emit(&[Instr::I64Const(len as i64), Instr::LocalSet(len_local)]);
+        Operand::Array(slots, len_local)
+    }
+
+    // taken once per call when the function starts, so a literal in a loop
+    // reuses its slots
+    fn generate_storage(&mut self, len: usize) -> u32 {
+        let slots = self.local(ValType::I32);
+        self.current().prologue.extend_from_slice(&[
+            Instr::I32Const((len as i32 + 1) * 8),
+            Instr::Call(ALLOC),
+            Instr::LocalSet(slots),
+        ]);
+        slots
+    }
+
+    // wasm masks shift counts to 6 bits, the same as the cl register
+    fn generate_infix_op(&mut self, op: &str) -> Result<()> {
+        let instrs: &[Instr] = match op {
+            "+" => &[I64_ADD],
+            "-" => &[I64_SUB],
+            "*" => &[I64_MUL],
+            // the divisor is checked first so a zero stops at its line
+            "/" | "%" => {
+                let divisor = self.local(ValType::I64);
+                let line = self.lines.get(self.line).copied();
+                let line = line.unwrap_or(self.line + 1);
+                self.emit(&[
+                    Instr::LocalTee(divisor),
+                    I64_EQZ,
+                    Instr::If,
+                    Instr::I64Const(line as i64),
+                    Instr::Call(DIV_ZERO),
+                    UNREACHABLE,
+                    Instr::End,
+                    Instr::LocalGet(divisor),
+                ]);
+                match op {
+                    "/" => &[I64_DIV_S],
+                    _ => &[I64_REM_S],
+                }
+            }
+            "&" => &[I64_AND],
+            "|" => &[I64_OR],
+            "^" => &[I64_XOR],
+            "<<" => &[I64_SHL],
+            ">>" => &[I64_SHR_S],
+            "==" => &[I64_EQ, I64_EXTEND_I32_U],
+            "!=" => &[I64_NE, I64_EXTEND_I32_U],
+            _ => return Err(new_error(&format!("unknown operator {}", op))),
+        };
+        self.emit(instrs);
+        Ok(())
+    }
+
+    fn generate_call(&mut self, name: &str, args: Vec<ExpressionNode>) -> Result<Operand> {
+        match name {
+            "open" | "read" | "write" | "close" | "unlink" => {
+                return Err(new_error(&format!("{} isnt available in wasm", name)))
+            }
+            "range" => {
+                let (start, stop, step) = self.generate_range_args(args)?;
+                return Ok(self.generate_range(start, stop, step));
+            }
+            "print" => {
+                if args.is_empty() {
+                    return Err(new_error("print expects an argument"));
+                }
+                let last = args.len() - 1;
+                for (i, arg) in args.into_iter().enumerate() {
+                    self.generate_int(arg)?;
+                    if i != last {
+                        self.emit(&[DROP]);
+                    }
+                }
+                let value = self.local(ValType::I64);
+                self.emit(&[
+                    Instr::LocalTee(value),
+                    Instr::Call(PRINT),
+                    Instr::LocalGet(value),
+                ]);
+            }
+            name => {
+                let (index, arity) = *self
+                    .funcs
+                    .get(name)
+                    .ok_or_else(|| new_error(&format!("function {} undefined", name)))?;
+                if arity != args.len() {
+                    return Err(new_error(&format!(
+                        "{} expects {} arguments, got {}",
+                        name,
+                        arity,
+                        args.len()
+                    )));
+                }
+                for arg in args.into_iter() {
+                    self.generate_int(arg)?;
+                }
+                self.emit(&[Instr::Call(index)]);
+            }
+        }
+        Ok(Operand::Int)
+    }
+
+    // evaluates the range arguments into locals
+    fn generate_range_args(&mut self, args: Vec<ExpressionNode>) -> Result<(u32, u32, u32)> {
+        let mut args = args.into_iter();
+        let zero = || ExpressionNode::Value("0".to_string());
+        let one = || ExpressionNode::Value("1".to_string());
+        let (start, stop, step) = match (args.next(), args.next(), args.next(), args.next()) {
+            (Some(stop), None, None, None) => (zero(), stop, one()),
+            (Some(start), Some(stop), None, None) => (start, stop, one()),
+            (Some(start), Some(stop), Some(step), None) => (start, stop, step),
+            _ => return Err(new_error("range expects 1 to 3 arguments")),
+        };
+        let mut locals = Vec::new();
+        for expr in [start, stop, step] {
+            self.generate_int(expr)?;
+            let local = self.local(ValType::I64);
+            self.emit(&[Instr::LocalSet(local)]);
+            locals.push(local);
+        }
+        Ok((locals[0], locals[1], locals[2]))
+    }
+
+    // each range() keeps one buffer and only takes a bigger one when it
+    // has to, so a range in a loop doesnt eat the memory
+    fn generate_range(&mut self, start: u32, stop: u32, step: u32) -> Operand {
+        let slots = self.local(ValType::I32);
+        let capacity = self.local(ValType::I64);
+        let len = self.local(ValType::I64);
+        self.current()
+            .prologue
+            .extend_from_slice(&[Instr::I64Const(-1), Instr::LocalSet(capacity)]);
+        self.emit(&[
+            Instr::LocalGet(start),
+            Instr::LocalGet(stop),
+            Instr::LocalGet(step),
+            Instr::Call(RANGE_LEN),
+            Instr::LocalSet(len),
+            Instr::Block,
+            Instr::LocalGet(len),
+            Instr::LocalGet(capacity),
+            I64_LE_S,
+            Instr::BrIf(0),
+            Instr::LocalGet(len),
+            I32_WRAP_I64,
+            Instr::I32Const(1),
+            I32_ADD,
+            Instr::I32Const(3),
+            I32_SHL,
+            Instr::Call(ALLOC),
+            Instr::LocalSet(slots),
+            Instr::LocalGet(len),
+            Instr::LocalSet(capacity),
+            Instr::End,
+            Instr::LocalGet(slots),
+            Instr::LocalGet(start),
+            Instr::LocalGet(step),
+            Instr::LocalGet(len),
+            Instr::Call(RANGE_FILL),
+        ]);
+        Operand::Array(slots, len)
+    }
+
+    fn generate_assign(&mut self, name: String, node: ExpressionNode) -> Result<()> {
+        let operand = self.generate_expr(node)?;
+        self.assign(name, operand)
+    }
+
+    fn assign(&mut self, name: String, operand: Operand) -> Result<()> {
+        match (self.current().vars.get(&name).cloned(), operand) {
+            (None, Operand::Int) => {
+                let local = self.local(ValType::I64);
+                self.emit(&[Instr::LocalSet(local)]);
+                self.current().vars.insert(name, Var::Int(local));
+            }
+            (None, Operand::Array(slots, len)) => {
+                let var = (self.local(ValType::I32), self.local(ValType::I64));
+                self.emit(&[
+                    Instr::LocalGet(slots),
+                    Instr::LocalSet(var.0),
+                    Instr::LocalGet(len),
+                    Instr::LocalSet(var.1),
+                ]);
+                self.current().vars.insert(name, Var::Array(var.0, var.1));
+            }
+            (Some(Var::Int(local)), Operand::Int) => self.emit(&[Instr::LocalSet(local)]),
+            (Some(Var::Array(slots, _)), Operand::Int) => {
+                let value = self.local(ValType::I64);
+                self.emit(&[
+                    Instr::LocalSet(value),
+                    Instr::LocalGet(slots),
+                    Instr::LocalGet(value),
+                    Instr::Store(0),
+                ]);
+            }
+            (Some(Var::Array(var_slots, var_len)), Operand::Array(slots, len)) => {
+                self.emit(&[
+                    Instr::LocalGet(slots),
+                    Instr::LocalSet(var_slots),
+                    Instr::LocalGet(len),
+                    Instr::LocalSet(var_len),
+                ]);
+            }
+            (Some(Var::Int(_)), Operand::Array(..)) => {
+                return Err(new_error(&format!(
+                    "{} holds a number and cant be reassigned to an array",
+                    name
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_assign_index(
+        &mut self,
+        name: String,
+        index_expr: ExpressionNode,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        self.generate_int(assign_expr)?;
+        let value = self.local(ValType::I64);
+        self.emit(&[Instr::LocalSet(value)]);
+        let slots = self.array_var(&name)?;
+        self.generate_address(slots, index_expr)?;
+        self.emit(&[Instr::LocalGet(value), Instr::Store(0)]);
+        Ok(())
+    }
+
+    fn generate_assign_op(
+        &mut self,
+        name: String,
+        index_expr: Option<ExpressionNode>,
+        op: String,
+        assign_expr: ExpressionNode,
+    ) -> Result<()> {
+        self.generate_int(assign_expr)?;
+        let value = self.local(ValType::I64);
+        self.emit(&[Instr::LocalSet(value)]);
+        match (self.var(&name)?, index_expr) {
+            (Var::Int(local), None) => {
+                self.emit(&[Instr::LocalGet(local), Instr::LocalGet(value)]);
+                self.generate_infix_op(&op)?;
+                self.emit(&[Instr::LocalSet(local)]);
+            }
+            (var, index_expr) => {
+                let slots = self.array_var(&name)?;
+                match (var, index_expr) {
+                    (_, Some(index_expr)) => self.generate_address(slots, index_expr)?,
+                    _ => self.emit(&[Instr::LocalGet(slots)]),
+                }
+                let address = self.local(ValType::I32);
+                self.emit(&[
+                    Instr::LocalTee(address),
+                    Instr::LocalGet(address),
+                    Instr::Load(0),
+                    Instr::LocalGet(value),
+                ]);
+                self.generate_infix_op(&op)?;
+                self.emit(&[Instr::Store(0)]);
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_exit(&mut self, node: ExpressionNode) -> Result<()> {
+        self.generate_int(node)?;
+        self.emit(&[I32_WRAP_I64, Instr::Call(EXIT), UNREACHABLE]);
+        Ok(())
+    }
+
+    fn generate_if(&mut self, node: ExpressionNode) -> Result<()> {
+        self.generate_int(node)?;
+        self.emit(&[Instr::I64Const(0), I64_NE, Instr::If]);
+        self.current().blocks.push(Block::If);
+        Ok(())
+    }
+
+    // loops are a block to break out of around a loop to branch back to
+    fn generate_while(&mut self, node: ExpressionNode) -> Result<()> {
+        self.emit(&[Instr::Block, Instr::Loop]);
+        self.generate_int(node)?;
+        self.emit(&[I64_EQZ, Instr::BrIf(1)]);
+        self.current().blocks.push(Block::While);
+        Ok(())
+    }
+
+    fn generate_for(&mut self, varname: String, node: ExpressionNode) -> Result<()> {
+        let block = match node {
+            ExpressionNode::Callable(name, args) if name == "range" => {
+                let args = args.into_iter().map(|arg| *arg).collect();
+                let (start, stop, step) = self.generate_range_args(args)?;
+                self.emit(&[Instr::LocalGet(start)]);
+                self.assign(varname.clone(), Operand::Int)?;
+                self.emit(&[Instr::Block, Instr::Loop]);
+                self.generate_int(ExpressionNode::Var(varname.clone()))?;
+                self.emit(&[
+                    Instr::LocalGet(stop),
+                    Instr::LocalGet(step),
+                    Instr::Call(RUNNING),
+                    I32_EQZ,
+                    Instr::BrIf(1),
+                ]);
+                Block::Range(varname, step)
+            }
+            node => {
+                let slots = match self.generate_expr(node)? {
+                    Operand::Array(slots, _) => slots,
+                    Operand::Int => {
+                        let value = self.local(ValType::I64);
+                        let slots = self.generate_storage(1);
+                        self.emit(&[
+                            Instr::LocalSet(value),
+                            Instr::LocalGet(slots),
+                            Instr::LocalGet(value),
+                            Instr::Store(0),
+                            Instr::LocalGet(slots),
+                            Instr::I64Const(SENTINEL),
+                            Instr::Store(8),
+                        ]);
+                        slots
+                    }
+                };
+                let position = self.local(ValType::I32);
+                self.emit(&[Instr::LocalGet(slots), Instr::LocalSet(position)]);
+                self.emit(&[Instr::I64Const(0)]);
+                self.assign(varname.clone(), Operand::Int)?;
+                self.emit(&[
+                    Instr::Block,
+                    Instr::Loop,
+                    Instr::LocalGet(position),
+                    Instr::Load(0),
+                ]);
+                self.assign(varname.clone(), Operand::Int)?;
+                self.emit(&[
+                    Instr::LocalGet(position),
+                    Instr::I32Const(8),
+                    I32_ADD,
+                    Instr::LocalSet(position),
+                ]);
+                self.generate_int(ExpressionNode::Var(varname))?;
+                self.emit(&[Instr::I64Const(SENTINEL), I64_EQ, Instr::BrIf(1)]);
+                Block::Array
+            }
+        };
+        self.current().blocks.push(block);
+        Ok(())
+    }
+
+    fn generate_end(&mut self, closer: &str) -> Result<()> {
+        match (self.current().blocks.pop(), closer) {
+            (Some(Block::If), "if") => self.emit(&[Instr::End]),
+            (Some(Block::While), "while") | (Some(Block::Array), "for") => {
+                self.emit(&[Instr::Br(0), Instr::End, Instr::End])
+            }
+            (Some(Block::Range(varname, step)), "for") => {
+                self.generate_int(ExpressionNode::Var(varname.clone()))?;
+                self.emit(&[Instr::LocalGet(step), I64_ADD]);
+                self.assign(varname, Operand::Int)?;
+                self.emit(&[Instr::Br(0), Instr::End, Instr::End]);
+            }
+            _ => return Err(new_error(&format!("no {} to close", closer))),
+        }
+        Ok(())
+    }
+
+    fn generate_func(&mut self, name: String, args: Vec<String>) -> Result<()> {
+        if self.functions.len() > 1 {
+            return Err(new_error("functions cant be defined inside functions"));
+        }
+        let index = FIRST_FUNC + self.done.len() as u32;
+        self.funcs.insert(name.clone(), (index, args.len()));
+        // prefixed so they dont clash with main and the runtime helpers
+        let mut function = Function::new(
+            &format!("f_{}", name),
+            &vec![ValType::I64; args.len()],
+            &[ValType::I64],
+            &[],
+        );
+        function.vars = args
+            .into_iter()
+            .enumerate()
+            .map(|(i, arg)| (arg, Var::Int(i as u32)))
+            .collect();
+        self.functions.push(function);
+        // arrays taken during the call are handed back at oof
+        let frame = self.local(ValType::I32);
+        self.current()
+            .prologue
+            .extend_from_slice(&[Instr::GlobalGet(SP), Instr::LocalSet(frame)]);
+        let returned = self.local(ValType::I64);
+        let function = self.current();
+        function.frame = Some(frame);
+        function.returned = Some(returned);
+        Ok(())
+    }
+
+    fn generate_end_func(&mut self) -> Result<()> {
+        let (frame, returned) = match (self.current().frame, self.current().returned) {
+            (Some(frame), Some(returned)) if self.current().blocks.is_empty() => (frame, returned),
+            _ => return Err(new_error("no foo to close")),
+        };
+        self.emit(&[
+            Instr::LocalGet(frame),
+            Instr::GlobalSet(SP),
+            Instr::LocalGet(returned),
+        ]);
+        let function = self.functions.pop().unwrap();
+        self.done.push(function);
+        Ok(())
+    }
+
+    fn generate_return(&mut self, node: ExpressionNode) -> Result<()> {
+        self.generate_int(node)?;
+        match self.current().returned {
+            Some(returned) => self.emit(&[Instr::LocalSet(returned)]),
+            None => self.emit(&[DROP]),
+        }
+        Ok(())
+    }
+
+    pub fn generate(&mut self, program: Vec<StatementNode>) -> Result<WasmModule> {
+        for (i, line) in program.into_iter().enumerate() {
+            self.line = i;
+            match line {
+                StatementNode::Exit(expr_node) => self.generate_exit(expr_node)?,
+                StatementNode::Assign(name, expr_node) => self.generate_assign(name, expr_node)?,
+                StatementNode::For(var, expr_node) => self.generate_for(var, expr_node)?,
+                StatementNode::EndFor => self.generate_end("for")?,
+                StatementNode::While(expr_node) => self.generate_while(expr_node)?,
+                StatementNode::EndWhile => self.generate_end("while")?,
+                StatementNode::If(expr_node) => self.generate_if(expr_node)?,
+                StatementNode::EndIf => self.generate_end("if")?,
+                StatementNode::AssignIndex(name, index_expr, assign_expr) => {
+                    self.generate_assign_index(name, index_expr, assign_expr)?
+                }
+                StatementNode::AssignOp(name, op, expr_node) => {
+                    self.generate_assign_op(name, None, op, expr_node)?
+                }
+                StatementNode::AssignIndexOp(name, index_expr, op, assign_expr) => {
+                    self.generate_assign_op(name, Some(index_expr), op, assign_expr)?
+                }
+                StatementNode::EndFunc => self.generate_end_func()?,
+                StatementNode::Func(name, args, _) => self.generate_func(name, args)?,
+                StatementNode::Return(expr) => self.generate_return(expr)?,
+            };
+        }
+        if self.functions.len() > 1 || !self.current().blocks.is_empty() {
+            return Err(new_error("block is never closed"));
+        }
+        self.emit(&[Instr::I32Const(0)]);
+        let main = self.functions.pop().unwrap();
+        let mut functions = helpers();
+        functions.append(&mut self.done);
+        functions.push(main);
+        Ok(WasmModule {
+            wat: wat(&functions),
+            wasm: encode(&functions),
+        })
+    }
+}
+
+// the runtime every module carries, at the indices named above
+fn helpers() -> Vec<Function> {
+    use Instr::*;
+    use ValType::*;
+
+    // alloc(bytes) -> address, growing the memory when the stack runs off
+    // the end of it
+    let mut alloc = Function::new("alloc", &[I32], &[I32], &[I32]);
+    alloc.code = vec![
+        GlobalGet(SP),
+        LocalSet(1),
+        GlobalGet(SP),
+        LocalGet(0),
+        I32_ADD,
+        GlobalSet(SP),
+        Block,
+        GlobalGet(SP),
+        MemorySize,
+        I32Const(16),
+        I32_SHL,
+        I32_LE_U,
+        BrIf(0),
+        GlobalGet(SP),
+        I32Const(16),
+        I32_SHR_U,
+        I32Const(1),
+        I32_ADD,
+        MemorySize,
+        I32_SUB,
+        MemoryGrow,
+        I32Const(-1),
+        I32_NE,
+        BrIf(0),
+        UNREACHABLE,
+        End,
+        LocalGet(1),
+    ];
+
+    // running(value, stop, step), whether a range has more to give
+    let mut running = Function::new("running", &[I64, I64, I64], &[I32], &[]);
+    running.code = vec![
+        LocalGet(2),
+        I64Const(0),
+        I64_GT_S,
+        LocalGet(0),
+        LocalGet(1),
+        I64_LT_S,
+        I32_AND,
+        LocalGet(2),
+        I64Const(0),
+        I64_LT_S,
+        LocalGet(0),
+        LocalGet(1),
+        I64_GT_S,
+        I32_AND,
+        I32_OR,
+    ];
+
+    // fill(address, count) writes count sentinels
+    let mut fill = Function::new("fill", &[I32, I64], &[], &[]);
+    fill.code = vec![
+        Block,
+        Loop,
+        LocalGet(1),
+        I64_EQZ,
+        BrIf(1),
+        LocalGet(0),
+        I64Const(SENTINEL),
+        Store(0),
+        LocalGet(0),
+        I32Const(8),
+        I32_ADD,
+        LocalSet(0),
+        LocalGet(1),
+        I64Const(1),
+        I64_SUB,
+        LocalSet(1),
+        Br(0),
+        End,
+        End,
+    ];
+
+    // range_len(start, stop, step) counts the values a range gives
+    let mut range_len = Function::new("range_len", &[I64, I64, I64], &[I64], &[I64]);
+    range_len.code = vec![
+        Block,
+        Loop,
+        LocalGet(0),
+        LocalGet(1),
+        LocalGet(2),
+        Call(RUNNING),
+        I32_EQZ,
+        BrIf(1),
+        LocalGet(0),
+        LocalGet(2),
+        I64_ADD,
+        LocalSet(0),
+        LocalGet(3),
+        I64Const(1),
+        I64_ADD,
+        LocalSet(3),
+        Br(0),
+        End,
+        End,
+        LocalGet(3),
+    ];
+
+    // range_fill(address, start, step, len) writes the values and the
+    // sentinel after them
+    let mut range_fill = Function::new("range_fill", &[I32, I64, I64, I64], &[], &[]);
+    range_fill.code = vec![
+        Block,
+        Loop,
+        LocalGet(3),
+        I64_EQZ,
+        BrIf(1),
+        LocalGet(0),
+        LocalGet(1),
+        Store(0),
+        LocalGet(1),
+        LocalGet(2),
+        I64_ADD,
+        LocalSet(1),
+        LocalGet(0),
+        I32Const(8),
+        I32_ADD,
+        LocalSet(0),
+        LocalGet(3),
+        I64Const(1),
+        I64_SUB,
+        LocalSet(3),
+        Br(0),
+        End,
+        End,
+        LocalGet(0),
+        I64Const(SENTINEL),
+        Store(0),
+    ];
+
+    vec![alloc, running, fill, range_len, range_fill]
+}
+
+fn wat_types(keyword: &str, types: &[ValType]) -> String {
+    match types.is_empty() {
+        true => "".to_string(),
+        false => {
+            let names: Vec<&str> = types.iter().map(|ty| ty.name()).collect();
+            format!(" ({} {})", keyword, names.join(" "))
+        }
+    }
+}
+
+fn wat(functions: &[Function]) -> String {
+    let mut out = "(module\n".to_string();
+    out += "  (import \"env\" \"print\" (func $print (param i64)))\n";
+    out += "  (import \"env\" \"exit\" (func $exit (param i32)))\n";
+    out += "  (import \"env\" \"div_zero\" (func $div_zero (param i64)))\n";
+    out += "  (memory (export \"memory\") 1)\n";
+    out += &format!("  (global $sp (mut i32) (i32.const {}))\n", SP_START);
+    for function in functions {
+        let export = match function.name.as_str() {
+            "main" => " (export \"main\")",
+            _ => "",
+        };
+        out += &format!(
+            "  (func ${}{}{}{}{}\n",
+            function.name,
+            export,
+            wat_types("param", &function.params),
+            wat_types("result", &function.results),
+            wat_types("local", &function.locals)
+        );
+        let mut depth = 2;
+        for instr in function.body() {
+            if instr == Instr::End {
+                depth -= 1;
+            }
+            out += &format!("{}{}\n", "  ".repeat(depth), wat_instr(instr));
+            if matches!(instr, Instr::Block | Instr::Loop | Instr::If) {
+                depth += 1;
+            }
+        }
+        out += "  )\n";
+    }
+    out += ")\n";
+    out
+}
+
+fn wat_instr(instr: Instr) -> String {
+    match instr {
+        Instr::Plain(name, _) => name.to_string(),
+        Instr::MemorySize => "memory.size".to_string(),
+        Instr::MemoryGrow => "memory.grow".to_string(),
+        Instr::I32Const(value) => format!("i32.const {}", value),
+        Instr::I64Const(value) => format!("i64.const {}", value),
+        Instr::LocalGet(index) => format!("local.get {}", index),
+        Instr::LocalSet(index) => format!("local.set {}", index),
+        Instr::LocalTee(index) => format!("local.tee {}", index),
+        Instr::GlobalGet(index) => format!("global.get {}", index),
+        Instr::GlobalSet(index) => format!("global.set {}", index),
+        Instr::Load(offset) => format!("i64.load offset={}", offset),
+        Instr::Store(offset) => format!("i64.store offset={}", offset),
+        Instr::Block => "block".to_string(),
+        Instr::Loop => "loop".to_string(),
+        Instr::If => "if".to_string(),
+        Instr::End => "end".to_string(),
+        Instr::Br(depth) => format!("br {}", depth),
+        Instr::BrIf(depth) => format!("br_if {}", depth),
+        Instr::Call(index) => format!("call {}", index),
+    }
+}
+
+fn leb_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn leb_i64(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn encode_name(out: &mut Vec<u8>, name: &str) {
+    leb_u32(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn encode_instr(out: &mut Vec<u8>, instr: Instr) {
+    match instr {
+        Instr::Plain(_, opcode) => out.push(opcode),
+        Instr::MemorySize => out.extend_from_slice(&[0x3F, 0x00]),
+        Instr::MemoryGrow => out.extend_from_slice(&[0x40, 0x00]),
+        Instr::I32Const(value) => {
+            out.push(0x41);
+            leb_i64(out, value as i64);
+        }
+        Instr::I64Const(value) => {
+            out.push(0x42);
+            leb_i64(out, value);
+        }
+        Instr::LocalGet(index) | Instr::LocalSet(index) | Instr::LocalTee(index) => {
+            out.push(match instr {
+                Instr::LocalGet(_) => 0x20,
+                Instr::LocalSet(_) => 0x21,
+                _ => 0x22,
+            });
+            leb_u32(out, index);
+        }
+        Instr::GlobalGet(index) | Instr::GlobalSet(index) => {
+            out.push(if let Instr::GlobalGet(_) = instr {
+                0x23
+            } else {
+                0x24
+            });
+            leb_u32(out, index);
+        }
+        // align 2^3
+        Instr::Load(offset) | Instr::Store(offset) => {
+            out.push(if let Instr::Load(_) = instr {
+                0x29
+            } else {
+                0x37
+            });
+            out.push(3);
+            leb_u32(out, offset);
+        }
+        // the blocks never leave a value behind
+        Instr::Block => out.extend_from_slice(&[0x02, 0x40]),
+        Instr::Loop => out.extend_from_slice(&[0x03, 0x40]),
+        Instr::If => out.extend_from_slice(&[0x04, 0x40]),
+        Instr::End => out.push(0x0B),
+        Instr::Br(depth) | Instr::BrIf(depth) | Instr::Call(depth) => {
+            out.push(match instr {
+                Instr::Br(_) => 0x0C,
+                Instr::BrIf(_) => 0x0D,
+                _ => 0x10,
+            });
+            leb_u32(out, depth);
+        }
+    }
+}
+
+fn section(out: &mut Vec<u8>, id: u8, body: Vec<u8>) {
+    out.push(id);
+    leb_u32(out, body.len() as u32);
+    out.extend(body);
+}
+
+fn encode(functions: &[Function]) -> Vec<u8> {
+    // every signature once, the imports first
+    let mut types: Vec<(Vec<ValType>, Vec<ValType>)> =
+        vec![(vec![ValType::I64], vec![]), (vec![ValType::I32], vec![])];
+    let mut type_of = |params: &[ValType], results: &[ValType]| -> u32 {
+        let signature = (params.to_vec(), results.to_vec());
+        match types.iter().position(|ty| *ty == signature) {
+            Some(index) => index as u32,
+            None => {
+                types.push(signature);
+                types.len() as u32 - 1
+            }
+        }
+    };
+    let function_types: Vec<u32> = functions
+        .iter()
+        .map(|function| type_of(&function.params, &function.results))
+        .collect();
+
+    let mut out = b"\0asm".to_vec();
+    out.extend_from_slice(&[1, 0, 0, 0]);
+
+    let mut body = Vec::new();
+    leb_u32(&mut body, types.len() as u32);
+    for (params, results) in types.iter() {
+        body.push(0x60);
+        for list in [params, results] {
+            leb_u32(&mut body, list.len() as u32);
+            body.extend(list.iter().map(|ty| ty.code()));
+        }
+    }
+    section(&mut out, 1, body);
+
+    let mut body = Vec::new();
+    leb_u32(&mut body, ALLOC);
+    for (name, ty) in [("print", 0), ("exit", 1), ("div_zero", 0)] {
+        encode_name(&mut body, "env");
+        encode_name(&mut body, name);
+        body.push(0x00);
+        leb_u32(&mut body, ty);
+    }
+    section(&mut out, 2, body);
+
+    let mut body = Vec::new();
+    leb_u32(&mut body, functions.len() as u32);
+    for ty in function_types {
+        leb_u32(&mut body, ty);
+    }
+    section(&mut out, 3, body);
+
+    // one memory, at least a page
+    section(&mut out, 5, vec![1, 0x00, 1]);
+
+    let mut body = vec![1, ValType::I32.code(), 0x01];
+    encode_instr(&mut body, Instr::I32Const(SP_START));
+    body.push(0x0B);
+    section(&mut out, 6, body);
+
+    let mut body = Vec::new();
+    leb_u32(&mut body, 2);
+    encode_name(&mut body, "main");
+    body.push(0x00);
+    // main is the last function, after the imports
+    leb_u32(&mut body, ALLOC + functions.len() as u32 - 1);
+    encode_name(&mut body, "memory");
+    body.extend_from_slice(&[0x02, 0x00]);
+    section(&mut out, 7, body);
+
+    let mut body = Vec::new();
+    leb_u32(&mut body, functions.len() as u32);
+    for function in functions {
+        let mut code = Vec::new();
+        // runs of the same local type are grouped
+        let mut runs: Vec<(u32, ValType)> = Vec::new();
+        for ty in function.locals.iter() {
+            match runs.last_mut() {
+                Some((count, last)) if last == ty => *count += 1,
+                _ => runs.push((1, *ty)),
+            }
+        }
+        leb_u32(&mut code, runs.len() as u32);
+        for (count, ty) in runs {
+            leb_u32(&mut code, count);
+            code.push(ty.code());
+        }
+        for instr in function.body() {
+            encode_instr(&mut code, instr);
+        }
+        code.push(0x0B);
+        leb_u32(&mut body, code.len() as u32);
+        body.extend(code);
+    }
+    section(&mut out, 10, body);
+    out
+}
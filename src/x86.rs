@@ -0,0 +1,668 @@
+use crate::bytecode::BinOp;
+use crate::ir::{Builtin, Cond, Function, Inst, Operand, Program, Temp, Terminator};
+use crate::optimize::{build_ir, Options};
+use crate::parser::StatementNode;
+use crate::peephole::peephole;
+use crate::regalloc::{allocate, Location};
+use crate::runtime::{db, DIV_ZERO, IOBUF_SIZE, SENTINEL};
+
+use std::io::Result;
+
+// calls pass the first six arguments in these and the rest on the stack,
+// like the c calling convention
+const ARGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+// what temps are allocated to. calls keep these as they are, so nothing has
+// to be saved around them, and none of them are needed for arguments
+const REGISTERS: [&str; 5] = ["rbx", "r12", "r13", "r14", "r15"];
+
+// the routines behind the builtins. they take their arguments like any
+// other call and only touch the registers a call is allowed to clobber
+const RUNTIME: &str = "
+section .data
+    div_zero_before: db DIV_ZERO_BEFORE
+    div_zero_after: db DIV_ZERO_AFTER
+section .text
+zb_print:
+    mov rax, rdi
+    mov r8, rdi
+    mov rsi, printbuf
+    add rsi, 31
+    mov byte [rsi], 10
+    mov rcx, 10
+    cmp rax, 0
+    jge .print_digit
+    neg rax
+.print_digit:
+    dec rsi
+    xor rdx, rdx
+    div rcx
+    add dl, '0'
+    mov [rsi], dl
+    cmp rax, 0
+    jne .print_digit
+    cmp r8, 0
+    jge .print_write
+    dec rsi
+    mov byte [rsi], '-'
+.print_write:
+    mov rdx, printbuf
+    add rdx, 32
+    sub rdx, rsi
+    mov rdi, 1
+    mov rax, 1
+    syscall
+    mov rax, r8
+    ret
+zb_fill:
+    xor rax, rax
+.fill_loop:
+    cmp rax, rsi
+    jge .fill_done
+    mov qword [rdi + rax * 8], SENTINEL
+    inc rax
+    jmp .fill_loop
+.fill_done:
+    ret
+zb_range_len:
+    xor rax, rax
+.range_len_loop:
+    cmp rdx, 0
+    je .range_len_done
+    jl .range_len_down
+    cmp rdi, rsi
+    jge .range_len_done
+    jmp .range_len_next
+.range_len_down:
+    cmp rdi, rsi
+    jle .range_len_done
+.range_len_next:
+    add rdi, rdx
+    inc rax
+    jmp .range_len_loop
+.range_len_done:
+    ret
+zb_range_fill:
+    xor rax, rax
+.range_fill_loop:
+    cmp rax, rcx
+    jge .range_fill_done
+    mov [rdi + rax * 8], rsi
+    add rsi, rdx
+    inc rax
+    jmp .range_fill_loop
+.range_fill_done:
+    mov qword [rdi + rax * 8], SENTINEL
+    ret
+zb_path:
+    xor rcx, rcx
+.path_loop:
+    mov rax, [rdi + rcx * 8]
+    cmp rax, SENTINEL
+    je .path_done
+    mov [iobuf + rcx], al
+    inc rcx
+    cmp rcx, IOBUF_LAST
+    jne .path_loop
+.path_done:
+    mov byte [iobuf + rcx], 0
+    mov rax, iobuf
+    ret
+zb_clamp:
+    mov rax, IOBUF
+    cmp rdx, rax
+    cmova rdx, rax
+    cmp rcx, rdx
+    cmova rcx, rdx
+    ret
+zb_read:
+    call zb_clamp
+    mov r8, rsi
+    mov rdx, rcx
+    mov rsi, iobuf
+    xor rax, rax
+    syscall
+    xor rcx, rcx
+.read_loop:
+    cmp rcx, rax
+    jge .read_done
+    movzx rdx, byte [iobuf + rcx]
+    mov [r8 + rcx * 8], rdx
+    inc rcx
+    jmp .read_loop
+.read_done:
+    ret
+zb_write:
+    call zb_clamp
+    xor rax, rax
+.write_loop:
+    cmp rax, rcx
+    je .write_done
+    mov rdx, [rsi + rax * 8]
+    mov [iobuf + rax], dl
+    inc rax
+    jmp .write_loop
+.write_done:
+    mov rdx, rcx
+    mov rsi, iobuf
+    mov rax, 1
+    syscall
+    ret
+zb_div_zero:
+    mov rax, rdi
+    mov rsi, printbuf
+    add rsi, 32
+    mov rcx, 10
+.div_zero_digit:
+    dec rsi
+    xor rdx, rdx
+    div rcx
+    add dl, '0'
+    mov [rsi], dl
+    cmp rax, 0
+    jne .div_zero_digit
+    mov r8, rsi
+    mov rsi, div_zero_before
+    mov rdx, DIV_ZERO_BEFORE_LEN
+    call .div_zero_write
+    mov rsi, r8
+    mov rdx, printbuf
+    add rdx, 32
+    sub rdx, rsi
+    call .div_zero_write
+    mov rsi, div_zero_after
+    mov rdx, DIV_ZERO_AFTER_LEN
+    call .div_zero_write
+    mov rdi, 1
+    mov rax, 60
+    syscall
+.div_zero_write:
+    mov rdi, 2
+    mov rax, 1
+    syscall
+    ret
+";
+
+fn fits_i32(value: i64) -> bool {
+    i32::try_from(value).is_ok()
+}
+
+fn jump_condition(cond: Cond) -> &'static str {
+    match cond {
+        Cond::Eq => "e",
+        Cond::Ne => "ne",
+        Cond::Lt => "l",
+        Cond::Le => "le",
+        Cond::Gt => "g",
+        Cond::Ge => "ge",
+    }
+}
+
+fn negate(cond: Cond) -> Cond {
+    match cond {
+        Cond::Eq => Cond::Ne,
+        Cond::Ne => Cond::Eq,
+        Cond::Lt => Cond::Ge,
+        Cond::Le => Cond::Gt,
+        Cond::Gt => Cond::Le,
+        Cond::Ge => Cond::Lt,
+    }
+}
+
+// writes nasm for the ir. temps are kept in the callee saved registers as
+// far as they go, so calls and the runtime leave them alone, and the rest
+// get a slot below rbp. arrays with a fixed size sit under the slots in the
+// same frame, so each function only moves rsp once on the way in. the
+// output assembles with the Assembler too
+#[derive(Debug)]
+pub struct X86Generator {
+    assembly: String,
+    // which function is being written, labels are numbered by it
+    function: usize,
+    locations: Vec<Location>,
+    // where each spill slot and each fixed alloc lives, as an offset from rbp
+    slots: Vec<i64>,
+    allocs: Vec<i64>,
+    // the callee saved registers this function uses and where they are kept
+    saved: Vec<(&'static str, i64)>,
+    // numbers the labels around divisions
+    divisions: usize,
+}
+
+impl X86Generator {
+    pub fn new() -> Self {
+        Self {
+            assembly: format!(
+                "section .bss\n    iobuf: resb {}\n    printbuf: resb 32\nsection .text\n    global _start\n",
+                IOBUF_SIZE
+            ),
+            function: 0,
+            locations: Vec::new(),
+            slots: Vec::new(),
+            allocs: Vec::new(),
+            saved: Vec::new(),
+            divisions: 0,
+        }
+    }
+
+    fn generic(&mut self, cmd: &str) {
+        self.assembly += &format!("    {}\n", cmd);
+    }
+
+    fn label(&mut self, name: &str) {
+        self.assembly += &format!("{}:\n", name);
+    }
+
+    fn block_label(&self, block: usize) -> String {
+        format!(".L{}_{}", self.function, block)
+    }
+
+    // the register or stack slot holding the temp
+    fn place(&self, temp: Temp) -> String {
+        match self.locations[temp.0] {
+            Location::Reg(register) => REGISTERS[register].to_string(),
+            Location::Slot(slot) => format!("[rbp - {}]", self.slots[slot]),
+        }
+    }
+
+    fn register(&self, temp: Temp) -> Option<&'static str> {
+        match self.locations[temp.0] {
+            Location::Reg(register) => Some(REGISTERS[register]),
+            Location::Slot(_) => None,
+        }
+    }
+
+    // user functions get a prefix so they cant clash with registers or the
+    // runtime
+    fn function_label(name: &str) -> String {
+        format!("fn_{}", name)
+    }
+
+    fn load(&mut self, register: &str, operand: Operand) {
+        match operand {
+            Operand::Temp(temp) if self.place(temp) == register => (),
+            Operand::Temp(temp) => self.generic(&format!("mov {}, {}", register, self.place(temp))),
+            Operand::Const(value) => self.generic(&format!("mov {}, {}", register, value)),
+        }
+    }
+
+    fn store(&mut self, temp: Temp, register: &str) {
+        let place = self.place(temp);
+        if place != register {
+            self.generic(&format!("mov {}, {}", place, register));
+        }
+    }
+
+    // the right hand side of an instruction with a register on the left,
+    // either where the temp lives, an immediate or loaded into rcx
+    fn source(&mut self, operand: Operand) -> String {
+        match operand {
+            Operand::Temp(temp) => self.place(temp),
+            Operand::Const(value) if fits_i32(value) => value.to_string(),
+            operand => {
+                self.load("rcx", operand);
+                "rcx".to_string()
+            }
+        }
+    }
+
+    // the register to work out dest in, its own unless that would clobber
+    // the right hand side before it is read
+    fn work(&self, dest: Temp, rh: Operand) -> &'static str {
+        match self.register(dest) {
+            Some(register)
+                if rh != Operand::Temp(dest) && self.source_register(rh) != Some(register) =>
+            {
+                register
+            }
+            _ => "rax",
+        }
+    }
+
+    fn source_register(&self, operand: Operand) -> Option<&'static str> {
+        match operand {
+            Operand::Temp(temp) => self.register(temp),
+            Operand::Const(_) => None,
+        }
+    }
+
+    // allocates the temps and lays out the frame, returning how far rsp
+    // moves. kept to 16 bytes so calls see an aligned stack
+    fn frame(&mut self, function: &Function, saves: bool) -> i64 {
+        self.locations = allocate(function, REGISTERS.len());
+        let mut offset = 0;
+        let slots = self
+            .locations
+            .iter()
+            .filter(|location| matches!(location, Location::Slot(_)))
+            .count();
+        self.slots = (0..slots)
+            .map(|_| {
+                offset += 8;
+                offset
+            })
+            .collect();
+        self.saved = Vec::new();
+        for (register, name) in REGISTERS.iter().enumerate() {
+            if saves && self.locations.contains(&Location::Reg(register)) {
+                offset += 8;
+                self.saved.push((name, offset));
+            }
+        }
+        self.allocs = Vec::new();
+        for inst in function.blocks.iter().flat_map(|block| block.insts.iter()) {
+            if let Inst::Alloc(_, Operand::Const(count)) = inst {
+                offset += 8 * count.max(&1);
+                self.allocs.push(offset);
+            }
+        }
+        (offset + 15) / 16 * 16
+    }
+
+    fn generate_function(&mut self, function: &Function, label: &str) -> Result<()> {
+        // _start isnt called so there is no rbp or registers to keep
+        let size = self.frame(function, label != "_start");
+        self.label(label);
+        if label != "_start" {
+            self.generic("push rbp");
+        }
+        self.generic("mov rbp, rsp");
+        if size != 0 {
+            self.generic(&format!("sub rsp, {}", size));
+        }
+        for (register, offset) in self.saved.clone() {
+            self.generic(&format!("mov [rbp - {}], {}", offset, register));
+        }
+        for (i, param) in function.params.iter().enumerate() {
+            match ARGS.get(i) {
+                Some(register) => self.store(*param, register),
+                None => {
+                    let offset = 16 + 8 * (i - ARGS.len());
+                    self.generic(&format!("mov rax, [rbp + {}]", offset));
+                    self.store(*param, "rax");
+                }
+            }
+        }
+        let mut allocs = 0;
+        for (id, block) in function.blocks.iter().enumerate() {
+            let label = self.block_label(id);
+            self.label(&label);
+            for inst in block.insts.iter() {
+                self.generate_inst(inst, &mut allocs)?;
+            }
+            self.generate_term(&block.term, id);
+        }
+        self.function += 1;
+        Ok(())
+    }
+
+    fn generate_inst(&mut self, inst: &Inst, allocs: &mut usize) -> Result<()> {
+        match inst {
+            Inst::Copy(dest, value) => match (self.register(*dest), value) {
+                (Some(register), value) => self.load(register, *value),
+                (None, Operand::Const(value)) if fits_i32(*value) => {
+                    let place = self.place(*dest);
+                    self.generic(&format!("mov qword {}, {}", place, value));
+                }
+                (None, value) => {
+                    self.load("rax", *value);
+                    self.store(*dest, "rax");
+                }
+            },
+            Inst::Binary(dest, op, lh, rh) => self.generate_binary(*dest, *op, *lh, *rh),
+            Inst::Not(dest, value) => {
+                let work = self.work(*dest, Operand::Const(0));
+                self.load(work, *value);
+                self.generic(&format!("not {}", work));
+                self.store(*dest, work);
+            }
+            Inst::Alloc(dest, Operand::Const(_)) => {
+                let offset = self.allocs[*allocs];
+                *allocs += 1;
+                let work = self.work(*dest, Operand::Const(0));
+                self.generic(&format!("lea {}, [rbp - {}]", work, offset));
+                self.store(*dest, work);
+            }
+            // whole 16 bytes at a time so rsp stays aligned
+            Inst::Alloc(dest, count) => {
+                self.load("rax", *count);
+                self.generic("shl rax, 3");
+                self.generic("add rax, 15");
+                self.generic("and rax, -16");
+                self.generic("sub rsp, rax");
+                self.store(*dest, "rsp");
+            }
+            Inst::Load(dest, array, index) => {
+                let address = self.address(*array, *index);
+                let work = self.work(*dest, Operand::Const(0));
+                self.generic(&format!("mov {}, {}", work, address));
+                self.store(*dest, work);
+            }
+            Inst::Store(array, index, value) => {
+                let address = self.address(*array, *index);
+                match (*value, self.source_register(*value)) {
+                    (_, Some(register)) => self.generic(&format!("mov {}, {}", address, register)),
+                    (Operand::Const(value), _) if fits_i32(value) => {
+                        self.generic(&format!("mov qword {}, {}", address, value))
+                    }
+                    (value, _) => {
+                        self.load("rdx", value);
+                        self.generic(&format!("mov {}, rdx", address));
+                    }
+                }
+            }
+            Inst::Call(dest, name, args) => {
+                self.generate_call(&Self::function_label(name), args);
+                self.store(*dest, "rax");
+            }
+            Inst::Builtin(dest, Builtin::Syscall, args) => {
+                for (register, arg) in ["rax", "rdi", "rsi", "rdx"].iter().zip(args.iter()) {
+                    self.load(register, *arg);
+                }
+                self.generic("syscall");
+                self.store(*dest, "rax");
+            }
+            Inst::Builtin(dest, builtin, args) => {
+                self.generate_call(&format!("zb_{}", builtin), args);
+                self.store(*dest, "rax");
+            }
+        }
+        Ok(())
+    }
+
+    // the slot of an array element. the array and index are used where they
+    // are if they are in registers, otherwise loaded into rax and rcx
+    fn address(&mut self, array: Temp, index: Operand) -> String {
+        let base = match self.register(array) {
+            Some(register) => register,
+            None => {
+                self.load("rax", Operand::Temp(array));
+                "rax"
+            }
+        };
+        match (index, self.source_register(index)) {
+            (Operand::Const(index), _) if fits_i32(index.wrapping_mul(8)) => {
+                format!("[{} + {}]", base, index * 8)
+            }
+            (_, Some(register)) => format!("[{} + {} * 8]", base, register),
+            (index, _) => {
+                self.load("rcx", index);
+                format!("[{} + rcx * 8]", base)
+            }
+        }
+    }
+
+    fn generate_binary(&mut self, dest: Temp, op: BinOp, lh: Operand, rh: Operand) {
+        match op {
+            BinOp::Add | BinOp::Sub | BinOp::And | BinOp::Or | BinOp::Xor => {
+                let instruction = match op {
+                    BinOp::Add => "add",
+                    BinOp::Sub => "sub",
+                    BinOp::And => "and",
+                    BinOp::Or => "or",
+                    _ => "xor",
+                };
+                let work = self.work(dest, rh);
+                self.load(work, lh);
+                let source = self.source(rh);
+                self.generic(&format!("{} {}, {}", instruction, work, source));
+                self.store(dest, work);
+            }
+            // imul has no form with a 64 bit immediate
+            BinOp::Mul => {
+                let work = self.work(dest, rh);
+                self.load(work, lh);
+                let source = match rh {
+                    Operand::Const(_) => {
+                        self.load("rcx", rh);
+                        "rcx".to_string()
+                    }
+                    rh => self.source(rh),
+                };
+                self.generic(&format!("imul {}, {}", work, source));
+                self.store(dest, work);
+            }
+            // idiv faults on i64::MIN / -1, so dividing by -1 negates
+            // instead, which wraps the same way the interpreter does
+            BinOp::Div | BinOp::Mod => {
+                self.load("rax", lh);
+                self.load("rcx", rh);
+                let minus_one = !matches!(rh, Operand::Const(rh) if rh != -1);
+                let (divide, done) = (
+                    format!(".D{}_{}", self.function, self.divisions),
+                    format!(".E{}_{}", self.function, self.divisions),
+                );
+                if minus_one {
+                    self.divisions += 1;
+                    self.generic("cmp rcx, -1");
+                    self.generic(&format!("jne {}", divide));
+                    self.generic("neg rax");
+                    self.generic("xor rdx, rdx");
+                    self.generic(&format!("jmp {}", done));
+                    self.label(&divide);
+                }
+                self.generic("cqo");
+                self.generic("idiv rcx");
+                if minus_one {
+                    self.label(&done);
+                }
+                self.store(dest, if op == BinOp::Mod { "rdx" } else { "rax" });
+            }
+            BinOp::Eq | BinOp::Ne => {
+                self.load("rax", lh);
+                let source = self.source(rh);
+                self.generic(&format!("cmp rax, {}", source));
+                let set = if op == BinOp::Eq { "sete" } else { "setne" };
+                self.generic(&format!("{} al", set));
+                let work = self.work(dest, Operand::Const(0));
+                self.generic(&format!("movzx {}, al", work));
+                self.store(dest, work);
+            }
+            // shift counts have to live in cl
+            BinOp::Shl | BinOp::Shr => {
+                let work = self.work(dest, rh);
+                self.load(work, lh);
+                self.load("rcx", rh);
+                let instruction = if op == BinOp::Shl { "sal" } else { "sar" };
+                self.generic(&format!("{} {}, cl", instruction, work));
+                self.store(dest, work);
+            }
+        }
+    }
+
+    // arguments past the sixth are pushed last first, with padding so rsp
+    // is still 16 byte aligned at the call
+    fn generate_call(&mut self, label: &str, args: &[Operand]) {
+        let stacked = args.len().saturating_sub(ARGS.len());
+        let padding = stacked % 2 * 8;
+        if padding != 0 {
+            self.generic(&format!("sub rsp, {}", padding));
+        }
+        for arg in args.iter().skip(ARGS.len()).rev() {
+            self.load("rax", *arg);
+            self.generic("push rax");
+        }
+        for (register, arg) in ARGS.iter().zip(args.iter()) {
+            self.load(register, *arg);
+        }
+        self.generic(&format!("call {}", label));
+        let cleanup = stacked * 8 + padding;
+        if cleanup != 0 {
+            self.generic(&format!("add rsp, {}", cleanup));
+        }
+    }
+
+    // falls through to the next block instead of jumping to it
+    fn generate_term(&mut self, term: &Terminator, id: usize) {
+        match term {
+            Terminator::Jump(target) => {
+                if *target != id + 1 {
+                    let label = self.block_label(*target);
+                    self.generic(&format!("jmp {}", label));
+                }
+            }
+            Terminator::Branch(cond, lh, rh, then, other) => {
+                let lh = match self.source_register(*lh) {
+                    Some(register) => register,
+                    None => {
+                        self.load("rax", *lh);
+                        "rax"
+                    }
+                };
+                let source = self.source(*rh);
+                self.generic(&format!("cmp {}, {}", lh, source));
+                let (cond, target, other) = match *then == id + 1 {
+                    true => (negate(*cond), *other, *then),
+                    false => (*cond, *then, *other),
+                };
+                let label = self.block_label(target);
+                self.generic(&format!("j{} {}", jump_condition(cond), label));
+                if other != id + 1 {
+                    let label = self.block_label(other);
+                    self.generic(&format!("jmp {}", label));
+                }
+            }
+            Terminator::Return(value) => {
+                self.load("rax", *value);
+                for (register, offset) in self.saved.clone() {
+                    self.generic(&format!("mov {}, [rbp - {}]", register, offset));
+                }
+                self.generic("mov rsp, rbp");
+                self.generic("pop rbp");
+                self.generic("ret");
+            }
+            Terminator::Exit(value) => {
+                self.load("rdi", *value);
+                self.generic("mov rax, 60");
+                self.generic("syscall");
+            }
+        }
+    }
+
+    pub fn generate(&mut self, program: &Program) -> Result<String> {
+        self.generate_function(&program.main, "_start")?;
+        for function in program.functions.iter() {
+            let label = Self::function_label(&function.name);
+            self.generate_function(function, &label)?;
+        }
+        let (before, after) = DIV_ZERO;
+        self.assembly += &RUNTIME
+            .replace("DIV_ZERO_BEFORE_LEN", &before.len().to_string())
+            .replace("DIV_ZERO_AFTER_LEN", &after.len().to_string())
+            .replace("DIV_ZERO_BEFORE", &db(before))
+            .replace("DIV_ZERO_AFTER", &db(after))
+            .replace("IOBUF_LAST", &(IOBUF_SIZE - 1).to_string())
+            .replace("IOBUF", &IOBUF_SIZE.to_string())
+            .replace("SENTINEL", &SENTINEL.to_string());
+        Ok(self.assembly.to_owned())
+    }
+}
+
+// the parse tree to nasm by way of the ir
+pub fn compile(program: Vec<StatementNode>, lines: &[usize], options: Options) -> Result<String> {
+    let assembly = X86Generator::new().generate(&build_ir(program, lines, options)?)?;
+    match options.level {
+        0 => Ok(assembly),
+        _ => Ok(peephole(&assembly)),
+    }
+}